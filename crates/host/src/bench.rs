@@ -0,0 +1,237 @@
+//! Measurements of the cost of a function, to compare two builds of a plugin.
+//!
+//! Times vary between runs and machines, but the fuel, the copied bytes and the
+//! memory of a call only depend on the plugin and the arguments: they are reliable to
+//! find regressions.
+
+use crate::{Limits, Plugin, PluginError};
+use std::{
+    fmt,
+    time::{Duration, Instant},
+};
+
+#[derive(Clone, Debug)]
+pub struct Options {
+    /// Number of measured calls.
+    pub iterations: usize,
+    /// Number of calls before the measured ones.
+    pub warmup: usize,
+    /// Limits of the instances.
+    pub limits: Limits,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Self {
+            iterations: 100,
+            warmup: 10,
+            limits: Limits::default(),
+        }
+    }
+}
+
+/// The measurements of a function.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Report {
+    /// Median time to instantiate the plugin.
+    pub instantiation: Duration,
+    /// Median time of a call.
+    pub call: Duration,
+    pub fastest_call: Duration,
+    pub slowest_call: Duration,
+    /// Fuel consumed by the first call on a new instance: roughly, the number of
+    /// instructions executed.
+    pub fuel: u64,
+    /// Bytes copied to the plugin (the arguments) by a call.
+    pub args_copied: usize,
+    /// Bytes copied from the plugin (the result) by a call.
+    pub result_copied: usize,
+    /// Size of the memory after all the calls, in bytes.
+    pub peak_memory: usize,
+}
+
+/// The fields of a report, in the order of the baselines.
+const FIELDS: [&str; 8] = [
+    "instantiation_ns",
+    "call_ns",
+    "fastest_call_ns",
+    "slowest_call_ns",
+    "fuel",
+    "args_copied",
+    "result_copied",
+    "peak_memory",
+];
+
+impl Report {
+    fn values(&self) -> [u64; 8] {
+        [
+            self.instantiation.as_nanos() as u64,
+            self.call.as_nanos() as u64,
+            self.fastest_call.as_nanos() as u64,
+            self.slowest_call.as_nanos() as u64,
+            self.fuel,
+            self.args_copied as u64,
+            self.result_copied as u64,
+            self.peak_memory as u64,
+        ]
+    }
+
+    /// The report as a baseline: a `name value` line for each measurement.
+    pub fn to_baseline(&self) -> String {
+        FIELDS
+            .iter()
+            .zip(self.values())
+            .map(|(name, value)| format!("{name} {value}\n"))
+            .collect()
+    }
+
+    /// Read a baseline written by [`Report::to_baseline`].
+    pub fn from_baseline(text: &str) -> Result<Self, String> {
+        let mut values = [None; 8];
+        for line in text.lines().filter(|line| !line.trim().is_empty()) {
+            let (name, value) = line
+                .split_once(' ')
+                .ok_or_else(|| format!("invalid line in the baseline: {line}"))?;
+            let index = FIELDS
+                .iter()
+                .position(|field| *field == name)
+                .ok_or_else(|| format!("unknown measurement in the baseline: {name}"))?;
+            let value = value
+                .trim()
+                .parse()
+                .map_err(|_| format!("invalid value in the baseline: {line}"))?;
+            values[index] = Some(value);
+        }
+        let value = |index: usize| {
+            values[index]
+                .ok_or_else(|| format!("missing measurement in the baseline: {}", FIELDS[index]))
+        };
+        Ok(Self {
+            instantiation: Duration::from_nanos(value(0)?),
+            call: Duration::from_nanos(value(1)?),
+            fastest_call: Duration::from_nanos(value(2)?),
+            slowest_call: Duration::from_nanos(value(3)?),
+            fuel: value(4)?,
+            args_copied: value(5)? as usize,
+            result_copied: value(6)? as usize,
+            peak_memory: value(7)? as usize,
+        })
+    }
+
+    /// The report, with the changes since `baseline`.
+    pub fn compared_to<'a>(&'a self, baseline: &'a Report) -> impl fmt::Display + 'a {
+        Comparison {
+            report: self,
+            baseline: Some(baseline),
+        }
+    }
+}
+
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        Comparison {
+            report: self,
+            baseline: None,
+        }
+        .fmt(f)
+    }
+}
+
+struct Comparison<'a> {
+    report: &'a Report,
+    baseline: Option<&'a Report>,
+}
+
+impl fmt::Display for Comparison<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let report = self.report;
+        let change = |value: fn(&Report) -> u64| match self.baseline {
+            Some(baseline) if value(baseline) == value(report) => " (no change)".to_owned(),
+            Some(baseline) if value(baseline) == 0 => " (was 0)".to_owned(),
+            Some(baseline) => {
+                let (old, new) = (value(baseline) as f64, value(report) as f64);
+                format!(" ({:+.1}%)", (new - old) / old * 100.0)
+            }
+            None => String::new(),
+        };
+        writeln!(
+            f,
+            "instantiation: {:.2?}{}",
+            report.instantiation,
+            change(|r| r.instantiation.as_nanos() as u64)
+        )?;
+        writeln!(
+            f,
+            "call:          {:.2?}{} (fastest {:.2?}, slowest {:.2?})",
+            report.call,
+            change(|r| r.call.as_nanos() as u64),
+            report.fastest_call,
+            report.slowest_call
+        )?;
+        writeln!(f, "fuel:          {}{}", report.fuel, change(|r| r.fuel))?;
+        writeln!(
+            f,
+            "arguments:     {} bytes copied to the plugin{}",
+            report.args_copied,
+            change(|r| r.args_copied as u64)
+        )?;
+        writeln!(
+            f,
+            "result:        {} bytes copied from the plugin{}",
+            report.result_copied,
+            change(|r| r.result_copied as u64)
+        )?;
+        write!(
+            f,
+            "peak memory:   {} pages ({} bytes){}",
+            report.peak_memory / 65536,
+            report.peak_memory,
+            change(|r| r.peak_memory as u64)
+        )
+    }
+}
+
+/// Measure the instantiation of the plugin, and the calls of `function` with `args`.
+///
+/// The calls are made on the same instance, like in typst. Fails if a call fails.
+pub fn bench(
+    binary: &[u8],
+    function: &str,
+    args: &[&[u8]],
+    options: &Options,
+) -> Result<Report, PluginError> {
+    let mut instantiations = Vec::new();
+    for _ in 0..options.iterations.clamp(1, 20) {
+        let start = Instant::now();
+        Plugin::with_limits(binary, options.limits)?;
+        instantiations.push(start.elapsed());
+    }
+
+    let mut plugin = Plugin::with_limits(binary, options.limits)?;
+    plugin.call(function, args)?;
+    let first = plugin.last_call();
+    for _ in 0..options.warmup {
+        plugin.call(function, args)?;
+    }
+    let mut calls = Vec::new();
+    for _ in 0..options.iterations.max(1) {
+        let start = Instant::now();
+        plugin.call(function, args)?;
+        calls.push(start.elapsed());
+    }
+    Ok(Report {
+        instantiation: median(&mut instantiations),
+        call: median(&mut calls),
+        fastest_call: calls[0],
+        slowest_call: calls[calls.len() - 1],
+        fuel: first.fuel,
+        args_copied: first.args_copied,
+        result_copied: first.result_copied,
+        peak_memory: plugin.last_call().memory,
+    })
+}
+
+fn median(durations: &mut [Duration]) -> Duration {
+    durations.sort();
+    durations[durations.len() / 2]
+}
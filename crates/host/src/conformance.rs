@@ -0,0 +1,207 @@
+//! Declarative test suites for plugins: each case calls a function, and gives the
+//! expected result.
+//!
+//! A suite is a text file with one case per line:
+//!
+//! ```text
+//! # Comments start with `#`.
+//! hello => ok "Hello from wasm!!!"
+//! concatenate hello world => ok hello*world
+//! returns_err => error "This is an `Err`"
+//! will_panic => trap
+//! ```
+//!
+//! The function is followed by its arguments, then `=>` and the expected status:
+//! `ok` (the function returned `0`), `error` (it returned `1`), `returns` (either of
+//! them, but not a trap or another failure) or `trap`. The
+//! arguments and the expected result have the syntax of [`parse_value`]. When the
+//! result is omitted after `ok` or `error`, any result is accepted.
+//!
+//! Each case runs on a new instance of the plugin, so that a case cannot break the
+//! next ones. The instances are strict (see [`Plugin::set_strict`]): a case fails if
+//! the plugin breaks a rule of the protocol, even one that typst does not check.
+
+use crate::{
+    format::{format_value, parse_value},
+    Limits, Plugin, PluginError,
+};
+use std::{fmt, path::Path};
+
+/// The cases of a suite.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Suite {
+    pub cases: Vec<Case>,
+}
+
+/// A call, and its expected result.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Case {
+    /// Line of the case in the suite, starting at 1.
+    pub line: usize,
+    pub function: String,
+    pub args: Vec<Vec<u8>>,
+    pub status: Status,
+    /// The expected result, or the expected error message. `None` accepts any result.
+    pub output: Option<Vec<u8>>,
+}
+
+/// How a call ends.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Status {
+    /// The function returned `0`.
+    Ok,
+    /// The function returned `1`.
+    Error,
+    /// The execution trapped.
+    Trap,
+    /// Expects `Ok` or `Error`.
+    Returns,
+}
+
+impl fmt::Display for Status {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Ok => "ok",
+            Self::Error => "error",
+            Self::Trap => "trap",
+            Self::Returns => "returns",
+        })
+    }
+}
+
+/// A case whose result is not the expected one.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Failure {
+    pub line: usize,
+    pub function: String,
+    pub message: String,
+}
+
+impl fmt::Display for Failure {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "line {}: {}: {}", self.line, self.function, self.message)
+    }
+}
+
+impl Suite {
+    /// Read the suite at `path`.
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, String> {
+        let path = path.as_ref();
+        let text = std::fs::read_to_string(path)
+            .map_err(|err| format!("cannot read {}: {err}", path.display()))?;
+        Self::parse(&text).map_err(|err| format!("{}: {err}", path.display()))
+    }
+
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let mut cases = Vec::new();
+        for (index, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let case =
+                parse_case(index + 1, line).map_err(|err| format!("line {}: {err}", index + 1))?;
+            cases.push(case);
+        }
+        Ok(Self { cases })
+    }
+
+    /// Run each case on a new instance of `binary`, and return the failures.
+    pub fn run(&self, binary: &[u8]) -> Vec<Failure> {
+        self.run_with_limits(binary, Limits::default())
+    }
+
+    /// Like [`Suite::run`], with limits on the resources of each instance.
+    pub fn run_with_limits(&self, binary: &[u8], limits: Limits) -> Vec<Failure> {
+        self.cases
+            .iter()
+            .filter_map(|case| {
+                let message = case.run(binary, limits).err()?;
+                Some(Failure {
+                    line: case.line,
+                    function: case.function.clone(),
+                    message,
+                })
+            })
+            .collect()
+    }
+}
+
+impl Case {
+    fn run(&self, binary: &[u8], limits: Limits) -> Result<(), String> {
+        let mut plugin = Plugin::with_limits(binary, limits).map_err(|err| err.to_string())?;
+        plugin.set_strict(true);
+        let args: Vec<&[u8]> = self.args.iter().map(Vec::as_slice).collect();
+        let (status, output) = match plugin.call(&self.function, &args) {
+            Ok(output) => (Status::Ok, output),
+            Err(PluginError::Errored(message)) => (Status::Error, message.into_bytes()),
+            Err(PluginError::InvalidErrorMessage(message)) => (Status::Error, message),
+            Err(PluginError::Trap(_)) => (Status::Trap, Vec::new()),
+            Err(err) => return Err(err.to_string()),
+        };
+        let returned = self.status == Status::Returns && status != Status::Trap;
+        if status != self.status && !returned {
+            return Err(format!("expected {}, got {status}", self.status));
+        }
+        match &self.output {
+            Some(expected) if *expected != output => Err(format!(
+                "expected {}, got {}",
+                format_value(expected),
+                format_value(&output)
+            )),
+            _ => Ok(()),
+        }
+    }
+}
+
+fn parse_case(line: usize, text: &str) -> Result<Case, String> {
+    let (function, mut rest) = text.split_once(char::is_whitespace).unwrap_or((text, ""));
+    let mut args = Vec::new();
+    loop {
+        rest = rest.trim_start();
+        if let Some(expected) = rest.strip_prefix("=>") {
+            if expected.is_empty() || expected.starts_with(char::is_whitespace) {
+                rest = expected.trim_start();
+                break;
+            }
+        }
+        if rest.is_empty() {
+            return Err("missing `=>` and the expected result".to_owned());
+        }
+        let (arg, length) = parse_value(rest)?;
+        args.push(arg);
+        rest = &rest[length..];
+    }
+
+    let (status, expected) = rest.split_once(char::is_whitespace).unwrap_or((rest, ""));
+    let status = match status {
+        "ok" => Status::Ok,
+        "error" => Status::Error,
+        "trap" => Status::Trap,
+        "returns" => Status::Returns,
+        _ => {
+            return Err(format!(
+                "expected ok, error, returns or trap, found {status:?}"
+            ))
+        }
+    };
+    let expected = expected.trim();
+    let output = if expected.is_empty() {
+        None
+    } else if matches!(status, Status::Trap | Status::Returns) {
+        return Err(format!("`{status}` has no result"));
+    } else {
+        let (output, length) = parse_value(expected)?;
+        if !expected[length..].trim().is_empty() {
+            return Err("the result must be a single value".to_owned());
+        }
+        Some(output)
+    };
+    Ok(Case {
+        line,
+        function: function.to_owned(),
+        args,
+        status,
+        output,
+    })
+}
@@ -0,0 +1,234 @@
+//! Checks that the functions of a plugin are pure: typst caches the calls of plugins,
+//! so the same arguments must give the same result, whatever was called before.
+//!
+//! The result of each call on a new instance is compared with the result of the same
+//! call:
+//!
+//! - on another new instance;
+//! - a second time on the same instance;
+//! - on a single instance that runs all the calls, in order, in reverse order, and
+//!   in a random order.
+//!
+//! Calls that fail without an error returned by the plugin (a trap, ...) are left
+//! out of the sequences on a single instance: after a trap, the state of the
+//! instance can be anything. Calls that go over the [`Limits`] are reported, since
+//! they may never return.
+
+use crate::{
+    format::format_value,
+    fuzz::{describe, initial_corpus, Rng},
+    Function, Limits, Plugin, PluginError,
+};
+use std::fmt;
+
+/// A call of a function.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Call {
+    pub function: String,
+    pub args: Vec<Vec<u8>>,
+}
+
+impl fmt::Display for Call {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // Long arguments would hide the rest of the message.
+        let args: Vec<_> = self
+            .args
+            .iter()
+            .map(|arg| match arg.len() {
+                0..=32 => format_value(arg),
+                length => format!("<{length} bytes>"),
+            })
+            .collect();
+        write!(f, "{}({})", self.function, args.join(", "))
+    }
+}
+
+/// How a call was repeated.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Scenario {
+    /// On another new instance.
+    NewInstance,
+    /// Twice in a row on the same instance.
+    SameInstance,
+    /// On an instance that ran the previous calls, in order.
+    InOrder,
+    /// On an instance that ran the following calls, in reverse order.
+    Reversed,
+    /// On an instance that ran other calls, in a random order.
+    Shuffled,
+}
+
+impl fmt::Display for Scenario {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::NewInstance => "on another new instance",
+            Self::SameInstance => "when called twice on the same instance",
+            Self::InOrder => "after the previous calls",
+            Self::Reversed => "after the calls in reverse order",
+            Self::Shuffled => "after the calls in a random order",
+        })
+    }
+}
+
+/// A call whose result changed.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Divergence {
+    pub call: Call,
+    pub scenario: Scenario,
+    /// The functions called before on the same instance, in order.
+    pub previous: Vec<String>,
+    /// The result on a new instance.
+    pub expected: String,
+    pub got: String,
+}
+
+impl fmt::Display for Divergence {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}: {} on a new instance, but {} {}",
+            self.call, self.expected, self.got, self.scenario
+        )?;
+        if let [.., last] = self.previous.as_slice() {
+            write!(
+                f,
+                " ({} calls, the last one to {last})",
+                self.previous.len()
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// The result of [`check`].
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Report {
+    pub divergences: Vec<Divergence>,
+    /// Calls that went over the limits on a new instance, with the error: only the
+    /// first one of each function.
+    pub over_limits: Vec<(Call, PluginError)>,
+}
+
+/// Generate `count` calls of each function, with arbitrary arguments of at most
+/// `max_input` bytes.
+pub fn generate_calls(
+    functions: &[Function],
+    count: usize,
+    max_input: usize,
+    seed: u64,
+) -> Vec<Call> {
+    let mut rng = Rng::new(seed);
+    let corpus = initial_corpus();
+    let mut calls = Vec::new();
+    for function in functions {
+        for _ in 0..count {
+            calls.push(Call {
+                function: function.name.clone(),
+                args: (0..function.arity)
+                    .map(|_| rng.argument(&corpus, max_input))
+                    .collect(),
+            });
+        }
+    }
+    calls
+}
+
+/// Run the calls in each [`Scenario`], and return the divergences: only the first
+/// one of each function in each scenario.
+///
+/// `seed` decides the random order of the calls. Each instance has the given `limits`:
+/// without a fuel limit, a call that loops forever never returns.
+pub fn check(
+    binary: &[u8],
+    calls: &[Call],
+    seed: u64,
+    limits: Limits,
+) -> Result<Report, PluginError> {
+    let new = || Plugin::with_limits(binary, limits);
+    // Fail early if the plugin cannot be loaded.
+    new()?;
+    let call = |plugin: &mut Plugin, call: &Call| {
+        let args: Vec<&[u8]> = call.args.iter().map(Vec::as_slice).collect();
+        plugin.call(&call.function, &args)
+    };
+    let fresh = |c: &Call| new().and_then(|mut plugin| call(&mut plugin, c));
+    let expected: Vec<_> = calls.iter().map(fresh).collect();
+
+    let mut over_limits: Vec<(Call, PluginError)> = Vec::new();
+    for (call, result) in calls.iter().zip(&expected) {
+        if let Err(err @ (PluginError::OutOfFuel(_) | PluginError::MemoryLimit(_))) = result {
+            if !over_limits.iter().any(|(c, _)| c.function == call.function) {
+                over_limits.push((call.clone(), err.clone()));
+            }
+        }
+    }
+
+    let mut divergences: Vec<Divergence> = Vec::new();
+    let mut report = |index: usize, scenario, previous: &[usize], got: &Result<_, _>| {
+        let call = &calls[index];
+        let known = divergences.iter().any(|divergence| {
+            divergence.call.function == call.function && divergence.scenario == scenario
+        });
+        if !known {
+            divergences.push(Divergence {
+                call: call.clone(),
+                scenario,
+                previous: previous
+                    .iter()
+                    .map(|&i| calls[i].function.clone())
+                    .collect(),
+                expected: describe(&expected[index]),
+                got: describe(got),
+            });
+        }
+    };
+
+    for (index, c) in calls.iter().enumerate() {
+        let got = fresh(c);
+        if got != expected[index] {
+            report(index, Scenario::NewInstance, &[], &got);
+        }
+    }
+
+    // Calls that crash are left out of the sequences.
+    let returned: Vec<usize> = (0..calls.len())
+        .filter(|&index| {
+            !matches!(
+                &expected[index],
+                Err(err) if !matches!(err, PluginError::Errored(_))
+            )
+        })
+        .collect();
+    for &index in &returned {
+        let mut plugin = new()?;
+        let _ = call(&mut plugin, &calls[index]);
+        let got = call(&mut plugin, &calls[index]);
+        if got != expected[index] {
+            report(index, Scenario::SameInstance, &[index], &got);
+        }
+    }
+
+    let mut shuffled = returned.clone();
+    let mut rng = Rng::new(seed);
+    for i in (1..shuffled.len()).rev() {
+        shuffled.swap(i, rng.below(i + 1));
+    }
+    let reversed = returned.iter().rev().copied().collect();
+    for (scenario, order) in [
+        (Scenario::InOrder, returned),
+        (Scenario::Reversed, reversed),
+        (Scenario::Shuffled, shuffled),
+    ] {
+        let mut plugin = new()?;
+        for (position, &index) in order.iter().enumerate() {
+            let got = call(&mut plugin, &calls[index]);
+            if got != expected[index] {
+                report(index, scenario, &order[..position], &got);
+            }
+        }
+    }
+    Ok(Report {
+        divergences,
+        over_limits,
+    })
+}
@@ -0,0 +1,214 @@
+//! Human-readable forms of the arguments and results of plugins.
+
+use ciborium::Value;
+use std::fmt::Write;
+
+/// Decode `bytes` as CBOR, and print the value as indented JSON.
+///
+/// Plugins often use CBOR to exchange structured values with typst (with
+/// `cbor.encode` and `cbor()`).
+///
+/// JSON has no byte strings: they are printed as strings of hexadecimal digits. Map
+/// keys that are not strings are printed as JSON, in a string.
+pub fn cbor_to_json(bytes: &[u8]) -> Result<String, String> {
+    let value: Value = ciborium::from_reader(bytes).map_err(|err| err.to_string())?;
+    serde_json::to_string_pretty(&to_json(value)).map_err(|err| err.to_string())
+}
+
+fn to_json(value: Value) -> serde_json::Value {
+    use serde_json::{Number, Value as Json};
+    match value {
+        Value::Integer(integer) => {
+            let integer = i128::from(integer);
+            match Number::from_i128(integer) {
+                Some(number) => Json::Number(number),
+                // Beyond the range of `u64`: JSON parsers read it as a float anyway.
+                None => Number::from_f64(integer as f64).map_or(Json::Null, Json::Number),
+            }
+        }
+        Value::Float(float) => Number::from_f64(float).map_or(Json::Null, Json::Number),
+        Value::Bool(bool) => Json::Bool(bool),
+        Value::Text(text) => Json::String(text),
+        Value::Bytes(bytes) => Json::String(hex(&bytes)),
+        Value::Tag(_, value) => to_json(*value),
+        Value::Array(values) => Json::Array(values.into_iter().map(to_json).collect()),
+        Value::Map(entries) => Json::Object(
+            entries
+                .into_iter()
+                .map(|(key, value)| {
+                    let key = match key {
+                        Value::Text(key) => key,
+                        key => to_json(key).to_string(),
+                    };
+                    (key, to_json(value))
+                })
+                .collect(),
+        ),
+        _ => Json::Null,
+    }
+}
+
+/// Lowercase hexadecimal digits of `bytes`, without separators.
+pub fn hex(bytes: &[u8]) -> String {
+    let mut hex = String::with_capacity(2 * bytes.len());
+    for byte in bytes {
+        write!(hex, "{byte:02x}").unwrap();
+    }
+    hex
+}
+
+/// A hex dump of `bytes`, like the one of `hexdump -C`: 16 bytes per line, after
+/// their offset, and followed by their printable ASCII characters.
+pub fn hex_dump(bytes: &[u8]) -> String {
+    let mut dump = String::new();
+    for (line, chunk) in bytes.chunks(16).enumerate() {
+        write!(dump, "{:08x} ", line * 16).unwrap();
+        for i in 0..16 {
+            if i == 8 {
+                dump.push(' ');
+            }
+            match chunk.get(i) {
+                Some(byte) => write!(dump, " {byte:02x}").unwrap(),
+                None => dump.push_str("   "),
+            }
+        }
+        dump.push_str("  |");
+        for &byte in chunk {
+            dump.push(if byte.is_ascii_graphic() || byte == b' ' {
+                byte as char
+            } else {
+                '.'
+            });
+        }
+        dump.push_str("|\n");
+    }
+    dump
+}
+
+/// Parse hexadecimal digits, ignoring whitespace and an optional `0x` prefix.
+pub fn from_hex(hex: &str) -> Result<Vec<u8>, String> {
+    let digits: Vec<u8> = hex
+        .trim()
+        .trim_start_matches("0x")
+        .bytes()
+        .filter(|c| !c.is_ascii_whitespace())
+        .collect();
+    if !digits.len().is_multiple_of(2) {
+        return Err(format!("odd number of hexadecimal digits: {hex}"));
+    }
+    digits
+        .chunks(2)
+        .map(|pair| {
+            std::str::from_utf8(pair)
+                .ok()
+                .and_then(|pair| u8::from_str_radix(pair, 16).ok())
+                .ok_or_else(|| format!("invalid hexadecimal digits: {hex}"))
+        })
+        .collect()
+}
+
+/// Parse arguments separated by whitespace, with the syntax of `wmp repl`.
+///
+/// See [`parse_value`] for the syntax of each argument.
+pub fn parse_arguments(mut line: &str) -> Result<Vec<Vec<u8>>, String> {
+    let mut args = Vec::new();
+    loop {
+        line = line.trim_start();
+        if line.is_empty() {
+            return Ok(args);
+        }
+        let (arg, length) = parse_value(line)?;
+        args.push(arg);
+        line = &line[length..];
+    }
+}
+
+/// Parse the value at the start of `text`, and return its bytes and its length in
+/// `text`. A value is one of:
+///
+/// - a word or a quoted string (with the escapes `\"`, `\\`, `\n` and `\t`), as UTF-8
+///   text;
+/// - `0x` followed by hexadecimal digits;
+/// - `@` followed by the path of a file, for the contents of the file;
+/// - `cbor:` followed by a JSON value, encoded as CBOR.
+pub fn parse_value(text: &str) -> Result<(Vec<u8>, usize), String> {
+    if let Some(json) = text.strip_prefix("cbor:") {
+        let (cbor, length) = json_to_cbor(json)?;
+        return Ok((cbor, "cbor:".len() + length));
+    }
+    if text.starts_with('"') {
+        let (string, length) = parse_string(text)?;
+        return Ok((string.into_bytes(), length));
+    }
+    let length = text.find(char::is_whitespace).unwrap_or(text.len());
+    let word = &text[..length];
+    let value = if let Some(hex) = word.strip_prefix("0x") {
+        from_hex(hex)?
+    } else if let Some(path) = word.strip_prefix('@') {
+        std::fs::read(path).map_err(|err| format!("cannot read {path}: {err}"))?
+    } else {
+        word.as_bytes().to_vec()
+    };
+    Ok((value, length))
+}
+
+/// Write `bytes` with the syntax of [`parse_value`]: as a quoted string if they are
+/// printable UTF-8 text, or as hexadecimal digits.
+pub fn format_value(bytes: &[u8]) -> String {
+    match std::str::from_utf8(bytes) {
+        Ok(text)
+            if !text
+                .chars()
+                .any(|c| c.is_control() && !matches!(c, '\n' | '\t')) =>
+        {
+            let mut quoted = String::from('"');
+            for c in text.chars() {
+                match c {
+                    '"' => quoted.push_str("\\\""),
+                    '\\' => quoted.push_str("\\\\"),
+                    '\n' => quoted.push_str("\\n"),
+                    '\t' => quoted.push_str("\\t"),
+                    c => quoted.push(c),
+                }
+            }
+            quoted.push('"');
+            quoted
+        }
+        _ => format!("0x{}", hex(bytes)),
+    }
+}
+
+/// Parse a quoted string at the start of `text`, and return its length in `text`.
+fn parse_string(text: &str) -> Result<(String, usize), String> {
+    let mut string = String::new();
+    let mut chars = text.char_indices().skip(1);
+    while let Some((offset, c)) = chars.next() {
+        match c {
+            '"' => return Ok((string, offset + 1)),
+            '\\' => string.push(match chars.next() {
+                Some((_, 'n')) => '\n',
+                Some((_, 't')) => '\t',
+                Some((_, c @ ('"' | '\\'))) => c,
+                _ => return Err("invalid escape in a string".to_owned()),
+            }),
+            c => string.push(c),
+        }
+    }
+    Err("unterminated string".to_owned())
+}
+
+/// Parse the JSON value at the start of `json`, and encode it as CBOR.
+///
+/// Returns the CBOR bytes, and the length of the JSON value in `json`, so that it can
+/// be followed by other text. Numbers without a fractional part or an exponent are
+/// encoded as integers.
+pub fn json_to_cbor(json: &str) -> Result<(Vec<u8>, usize), String> {
+    let mut values = serde_json::Deserializer::from_str(json).into_iter::<serde_json::Value>();
+    let value = match values.next() {
+        Some(value) => value.map_err(|err| err.to_string())?,
+        None => return Err("expected a JSON value, found the end of the input".to_owned()),
+    };
+    let mut cbor = Vec::new();
+    ciborium::into_writer(&value, &mut cbor).map_err(|err| err.to_string())?;
+    Ok((cbor, values.byte_offset()))
+}
@@ -0,0 +1,417 @@
+//! Fuzzing of the functions of a plugin: they are called with arbitrary arguments, to
+//! find the inputs that make them crash.
+//!
+//! A call has a [`Problem`] if it fails with something else than an error returned by
+//! the plugin (a trap, an out of bounds pointer, a violation of the protocol that
+//! typst would accept, ...), if its result is too large, or
+//! if calling the function again with the same arguments gives another result: typst
+//! caches the calls of plugins, so a function must only depend on its arguments.
+//!
+//! [`fuzz`] generates the arguments itself. [`check`] can be used as the body of a
+//! target of another fuzzer, with [`split_input`] to get the arguments:
+//!
+//! ```ignore
+//! fuzz_target!(|data: &[u8]| {
+//!     let args = split_input(data, 2);
+//!     if let Some(problem) = check(PLUGIN, "concatenate", &args, &Options::default()) {
+//!         panic!("{problem}");
+//!     }
+//! });
+//! ```
+
+use crate::{format::format_value, Function, Limits, Plugin, PluginError};
+use ciborium::Value;
+use std::fmt;
+
+#[derive(Clone, Debug)]
+pub struct Options {
+    /// Number of calls of each function.
+    pub runs: usize,
+    /// Seed of the random generator: the same seed generates the same arguments.
+    pub seed: u64,
+    /// Maximum length of a generated argument.
+    pub max_input: usize,
+    /// Results (and error messages) longer than this are a problem.
+    pub max_output: usize,
+    /// Number of calls with the same arguments, on the same instance, to check that
+    /// the results are the same.
+    pub repeat: usize,
+    /// Limits of each instance. By default, each call has [`DEFAULT_FUEL`], so that
+    /// an infinite loop is a problem instead of stopping the fuzzing.
+    pub limits: Limits,
+}
+
+/// Fuel of each call when fuzzing or checking the determinism, unless another limit
+/// is given: arbitrary arguments can make a plugin loop forever.
+pub const DEFAULT_FUEL: u64 = 100_000_000;
+
+impl Default for Options {
+    fn default() -> Self {
+        Self {
+            runs: 1000,
+            seed: 0,
+            max_input: 1024,
+            max_output: 1 << 20,
+            repeat: 2,
+            limits: Limits {
+                fuel: Some(DEFAULT_FUEL),
+                memory: None,
+            },
+        }
+    }
+}
+
+/// What is wrong with a call.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Problem {
+    /// The call failed, but the plugin did not return an error.
+    Crash(PluginError),
+    /// The result has this length, which is more than [`Options::max_output`].
+    LargeOutput(usize),
+    /// Calling the function again with the same arguments gave another result.
+    Nondeterministic { first: String, then: String },
+}
+
+impl Problem {
+    /// Whether both problems are the same bug, which does not depend on the details
+    /// of the error message or of the results.
+    pub fn same_kind(&self, other: &Self) -> bool {
+        match (self, other) {
+            (
+                Self::Crash(PluginError::ProtocolViolation(a)),
+                Self::Crash(PluginError::ProtocolViolation(b)),
+            ) => std::mem::discriminant(&a.rule) == std::mem::discriminant(&b.rule),
+            (Self::Crash(a), Self::Crash(b)) => {
+                std::mem::discriminant(a) == std::mem::discriminant(b)
+            }
+            (a, b) => std::mem::discriminant(a) == std::mem::discriminant(b),
+        }
+    }
+}
+
+impl fmt::Display for Problem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Crash(err) => write!(f, "{err}"),
+            Self::LargeOutput(length) => write!(f, "the result has {length} bytes"),
+            Self::Nondeterministic { first, then } => {
+                write!(f, "the same call gave {first}, then {then}")
+            }
+        }
+    }
+}
+
+/// A call with a problem.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Finding {
+    pub function: String,
+    pub args: Vec<Vec<u8>>,
+    pub problem: Problem,
+}
+
+impl Finding {
+    /// A conformance suite (see [`crate::conformance`]) with the call. For a crash,
+    /// the case fails until the plugin is fixed; for the other problems, it records
+    /// the arguments.
+    pub fn to_suite(&self) -> String {
+        let mut call = self.function.clone();
+        for arg in &self.args {
+            call.push(' ');
+            call.push_str(&format_value(arg));
+        }
+        let problem = self.problem.to_string().replace('\n', " ");
+        format!("# Found by `wmp fuzz`: {problem}\n{call} => returns\n")
+    }
+}
+
+/// Call `function` with `args` on a new instance, [`Options::repeat`] times, and
+/// return the first problem.
+pub fn check(
+    binary: &[u8],
+    function: &str,
+    args: &[Vec<u8>],
+    options: &Options,
+) -> Option<Problem> {
+    let args: Vec<&[u8]> = args.iter().map(Vec::as_slice).collect();
+    let mut plugin = match Plugin::with_limits(binary, options.limits) {
+        Ok(plugin) => plugin,
+        Err(err) => return Some(Problem::Crash(err)),
+    };
+    plugin.set_strict(true);
+    let mut first = None;
+    for _ in 0..options.repeat.max(1) {
+        let result = plugin.call(function, &args);
+        let output = match &result {
+            Ok(output) => output,
+            Err(PluginError::Errored(message)) => message.as_bytes(),
+            Err(err) => return Some(Problem::Crash(err.clone())),
+        };
+        if output.len() > options.max_output {
+            return Some(Problem::LargeOutput(output.len()));
+        }
+        match &first {
+            None => first = Some(result),
+            Some(first) if *first != result => {
+                return Some(Problem::Nondeterministic {
+                    first: describe(first),
+                    then: describe(&result),
+                })
+            }
+            Some(_) => {}
+        }
+    }
+    None
+}
+
+/// A short description of a result, for the messages.
+pub(crate) fn describe(result: &Result<Vec<u8>, PluginError>) -> String {
+    match result {
+        Ok(output) if output.len() <= 32 => format_value(output),
+        Ok(output) => format!("{} bytes", output.len()),
+        Err(err) => format!("an error ({err})"),
+    }
+}
+
+/// Split the input of another fuzzer into `arity` arguments: each argument but the
+/// last one is prefixed by its length, on one byte.
+pub fn split_input(mut data: &[u8], arity: usize) -> Vec<Vec<u8>> {
+    let mut args = Vec::with_capacity(arity);
+    for i in 0..arity {
+        if i + 1 == arity {
+            args.push(data.to_vec());
+            break;
+        }
+        let (length, rest) = data.split_first().unwrap_or((&0, &[]));
+        let (arg, rest) = rest.split_at((*length as usize).min(rest.len()));
+        args.push(arg.to_vec());
+        data = rest;
+    }
+    args
+}
+
+/// Call each function [`Options::runs`] times with generated arguments, and return
+/// the problems, with minimised arguments. Only the first problem of each kind is
+/// reported for a function. `report` is called with each finding as soon as it is
+/// found.
+pub fn fuzz(
+    binary: &[u8],
+    functions: &[Function],
+    options: &Options,
+    mut report: impl FnMut(&Finding),
+) -> Vec<Finding> {
+    let mut rng = Rng::new(options.seed);
+    let mut findings: Vec<Finding> = Vec::new();
+    for function in functions {
+        // Arguments for which the function succeeded, to be mutated: they are more
+        // likely to reach the code after the parsing of the arguments.
+        let mut corpus = initial_corpus();
+        for _ in 0..options.runs {
+            let args: Vec<_> = (0..function.arity)
+                .map(|_| rng.argument(&corpus, options.max_input))
+                .collect();
+            let Some(problem) = check(binary, &function.name, &args, options) else {
+                if corpus.len() < 256 {
+                    corpus.extend(args);
+                }
+                continue;
+            };
+            let known = findings.iter().any(|finding| {
+                finding.function == function.name && finding.problem.same_kind(&problem)
+            });
+            if known {
+                continue;
+            }
+            let finding = minimise(binary, &function.name, args, problem, options);
+            report(&finding);
+            findings.push(finding);
+        }
+    }
+    findings
+}
+
+/// Arguments to start the mutations from: short text, and empty CBOR values.
+pub(crate) fn initial_corpus() -> Vec<Vec<u8>> {
+    vec![
+        Vec::new(),
+        b"0".to_vec(),
+        b"hello".to_vec(),
+        vec![0xa0],
+        vec![0x80],
+    ]
+}
+
+/// Make the arguments as short and as simple as possible, while they still cause a
+/// problem of the same kind.
+pub fn minimise(
+    binary: &[u8],
+    function: &str,
+    mut args: Vec<Vec<u8>>,
+    mut problem: Problem,
+    options: &Options,
+) -> Finding {
+    let mut reproduces = |args: &[Vec<u8>]| match check(binary, function, args, options) {
+        Some(new) if new.same_kind(&problem) => {
+            problem = new;
+            true
+        }
+        _ => false,
+    };
+    for i in 0..args.len() {
+        // Remove chunks, smaller and smaller.
+        let mut chunk = args[i].len().div_ceil(2);
+        while chunk > 0 {
+            let mut start = 0;
+            while start < args[i].len() {
+                let mut candidate = args.clone();
+                let end = (start + chunk).min(args[i].len());
+                candidate[i].drain(start..end);
+                if reproduces(&candidate) {
+                    args = candidate;
+                } else {
+                    start += chunk;
+                }
+            }
+            chunk /= 2;
+        }
+        // Replace the remaining bytes by `a`.
+        if args[i].len() <= 256 {
+            for j in 0..args[i].len() {
+                if args[i][j] != b'a' {
+                    let mut candidate = args.clone();
+                    candidate[i][j] = b'a';
+                    if reproduces(&candidate) {
+                        args = candidate;
+                    }
+                }
+            }
+        }
+    }
+    Finding {
+        function: function.to_owned(),
+        args,
+        problem,
+    }
+}
+
+/// A xorshift64* generator: fast, and good enough to generate arguments.
+pub(crate) struct Rng(u64);
+
+impl Rng {
+    pub(crate) fn new(seed: u64) -> Self {
+        // The state must not be zero.
+        Self(seed ^ 0x9e37_79b9_7f4a_7c15)
+    }
+
+    fn next(&mut self) -> u64 {
+        self.0 ^= self.0 >> 12;
+        self.0 ^= self.0 << 25;
+        self.0 ^= self.0 >> 27;
+        self.0.wrapping_mul(0x2545_f491_4f6c_dd1d)
+    }
+
+    /// A number in `0..n`, or 0 if `n` is 0.
+    pub(crate) fn below(&mut self, n: usize) -> usize {
+        if n == 0 {
+            0
+        } else {
+            (self.next() % n as u64) as usize
+        }
+    }
+
+    /// A length up to `max`, more often small than large.
+    fn length(&mut self, max: usize) -> usize {
+        let bound = self.below(max + 1);
+        self.below(bound + 1)
+    }
+
+    fn bytes(&mut self, length: usize) -> Vec<u8> {
+        (0..length).map(|_| self.next() as u8).collect()
+    }
+
+    pub(crate) fn argument(&mut self, corpus: &[Vec<u8>], max: usize) -> Vec<u8> {
+        let mut arg = match self.below(8) {
+            0 => Vec::new(),
+            1 | 2 => {
+                let length = self.length(max);
+                self.bytes(length)
+            }
+            3 => {
+                let length = self.length(max);
+                (0..length).map(|_| b' ' + self.below(95) as u8).collect()
+            }
+            4 => {
+                let mut cbor = Vec::new();
+                ciborium::into_writer(&self.cbor(0), &mut cbor).unwrap();
+                cbor
+            }
+            _ => {
+                let mut arg = corpus[self.below(corpus.len())].clone();
+                for _ in 0..1 + self.below(4) {
+                    self.mutate(&mut arg, max);
+                }
+                arg
+            }
+        };
+        arg.truncate(max);
+        arg
+    }
+
+    fn mutate(&mut self, arg: &mut Vec<u8>, max: usize) {
+        const INTERESTING: [u8; 6] = [0, 0x7f, 0x80, 0xff, b'"', b'\\'];
+        let position = self.below(arg.len() + 1);
+        let last = arg.len().saturating_sub(1);
+        match self.below(6) {
+            0 if !arg.is_empty() => arg[position.min(last)] ^= 1 << self.below(8),
+            1 if !arg.is_empty() => {
+                arg[position.min(last)] = INTERESTING[self.below(INTERESTING.len())];
+            }
+            2 => {
+                let length = self.length(16);
+                let bytes = self.bytes(length);
+                arg.splice(position..position, bytes);
+            }
+            3 => {
+                let end = (position + self.length(16)).min(arg.len());
+                arg.drain(position..end);
+            }
+            4 => {
+                let end = (position + self.length(max)).min(arg.len());
+                let copy = arg[position..end].to_vec();
+                arg.splice(position..position, copy);
+            }
+            _ => arg.truncate(position),
+        }
+    }
+
+    /// A random CBOR value: plugins often decode their arguments with CBOR.
+    fn cbor(&mut self, depth: usize) -> Value {
+        let max = if depth < 3 { 9 } else { 6 };
+        match self.below(max) {
+            0 => Value::Null,
+            1 => Value::Bool(self.below(2) == 0),
+            2 => Value::Integer((self.next() as i64 >> self.below(64)).into()),
+            3 => Value::Float(f64::from_bits(self.next()) % 1e6),
+            4 => {
+                let length = self.length(16);
+                Value::Text(
+                    (0..length)
+                        .map(|_| (b'a' + self.below(26) as u8) as char)
+                        .collect(),
+                )
+            }
+            5 => {
+                let length = self.length(16);
+                Value::Bytes(self.bytes(length))
+            }
+            6 | 7 => Value::Array((0..self.below(5)).map(|_| self.cbor(depth + 1)).collect()),
+            _ => Value::Map(
+                (0..self.below(5))
+                    .map(|i| {
+                        let key = ((b'a' + i as u8) as char).to_string();
+                        (Value::Text(key), self.cbor(depth + 1))
+                    })
+                    .collect(),
+            ),
+        }
+    }
+}
@@ -0,0 +1,504 @@
+//! Host side of the minimal protocol, with the same semantics as typst.
+//!
+//! This allows running plugins without typst: to test them, debug them, or use them
+//! from another program.
+//!
+//! # Example
+//!
+//! ```no_run
+//! use wasm_minimal_protocol_host::Plugin;
+//!
+//! let mut plugin = Plugin::load("hello.wasm")?;
+//! let result = plugin.call("concatenate", &[b"hello", b"world"])?;
+//! assert_eq!(result, b"helloworld");
+//! # Ok::<(), wasm_minimal_protocol_host::PluginError>(())
+//! ```
+//!
+//! # Protocol
+//!
+//! The specification of the low-level protocol can be found in the typst documentation:
+//! <https://typst.app/docs/reference/foundations/plugin/#protocol>
+
+pub mod bench;
+pub mod conformance;
+pub mod determinism;
+pub mod format;
+pub mod fuzz;
+pub mod protocol;
+pub mod snapshot;
+
+use protocol::{check_calls, HostCall, Rule, Violation};
+use std::path::Path;
+use wasmi::{
+    errors::{ErrorKind, InstantiationError},
+    Caller, Config, Engine, Extern, Instance, Linker, Memory, Module, Store, StoreLimits,
+    StoreLimitsBuilder, TrapCode, Val, ValType,
+};
+
+/// Module of the functions provided to plugins.
+pub const HOST_MODULE: &str = "typst_env";
+
+/// A loaded plugin.
+///
+/// Like in typst, the instance is kept between calls: a function can see the changes
+/// made to the memory by previous calls.
+pub struct Plugin {
+    store: Store<HostState>,
+    instance: Instance,
+    limits: Limits,
+    strict: bool,
+    last_call: CallStats,
+    violations: Vec<Violation>,
+}
+
+/// Limits on the resources used by a plugin. There is no limit by default, like in
+/// typst.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Limits {
+    /// Fuel available to each call (and to the start function): roughly, the number of
+    /// instructions executed.
+    pub fuel: Option<u64>,
+    /// Maximum size of the memory, in bytes.
+    pub memory: Option<usize>,
+}
+
+/// Measurements of a call, see [`Plugin::last_call`].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct CallStats {
+    /// Fuel consumed by the call: roughly, the number of instructions executed.
+    pub fuel: u64,
+    /// Bytes copied to the memory of the plugin, by
+    /// `wasm_minimal_protocol_write_args_to_buffer`.
+    pub args_copied: usize,
+    /// Bytes copied from the memory of the plugin, by
+    /// `wasm_minimal_protocol_send_result_to_host`.
+    pub result_copied: usize,
+    /// Size of the memory after the call, in bytes. The memory cannot shrink, so it is
+    /// the peak size.
+    pub memory: usize,
+}
+
+/// A function of the plugin that follows the protocol.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Function {
+    pub name: String,
+    /// Number of arguments.
+    pub arity: usize,
+}
+
+struct HostState {
+    /// Arguments of the current call, written by `wasm_minimal_protocol_write_args_to_buffer`.
+    args: Vec<Vec<u8>>,
+    /// Result sent by `wasm_minimal_protocol_send_result_to_host`.
+    output: Vec<u8>,
+    /// Out of bounds access during the current call.
+    memory_error: Option<MemoryError>,
+    /// Bytes copied by the host functions during the current call.
+    args_copied: usize,
+    result_copied: usize,
+    /// Host functions called during the current call.
+    calls: Vec<HostCall>,
+    limits: StoreLimits,
+}
+
+#[derive(Clone, Copy, Debug)]
+struct MemoryError {
+    offset: usize,
+    length: usize,
+    write: bool,
+}
+
+/// The error messages are the same as typst's.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum PluginError {
+    /// The module could not be read, or is not valid.
+    Load(String),
+    /// The module could not be instantiated: for example, it imports functions that
+    /// are not part of the protocol, or its start function trapped.
+    Instantiate(String),
+    /// The module does not export a memory called `memory`.
+    NoMemory,
+    /// There is no exported function with this name.
+    NoFunction(String),
+    /// The function does not have the signature of a protocol function.
+    Signature { function: String, message: String },
+    /// The function was called with the wrong number of arguments.
+    ArgumentCount { expected: usize, given: usize },
+    /// The execution of the plugin trapped.
+    Trap(String),
+    /// The plugin used all the fuel of [`Limits::fuel`], which is given.
+    OutOfFuel(u64),
+    /// The memory of the plugin would be larger than [`Limits::memory`], which is given.
+    MemoryLimit(usize),
+    /// The plugin gave an out of bounds pointer to the host. It is also reported by
+    /// [`Plugin::violations`], with the calls of the host functions.
+    OutOfBounds {
+        offset: usize,
+        length: usize,
+        write: bool,
+    },
+    /// The function returned `1`, with this error message.
+    Errored(String),
+    /// The function returned `1`, but the error message is not valid UTF-8.
+    InvalidErrorMessage(Vec<u8>),
+    /// The function returned something else than `0` or `1`, or, in strict mode, broke
+    /// another rule of the protocol.
+    ProtocolViolation(Violation),
+}
+
+impl std::fmt::Display for PluginError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Load(err) => write!(f, "failed to load WebAssembly module ({err})"),
+            Self::Instantiate(err) => write!(f, "{err}"),
+            Self::NoMemory => write!(f, "plugin does not export its memory"),
+            Self::NoFunction(name) => {
+                write!(f, "plugin does not contain a function called {name}")
+            }
+            Self::Signature { function, message } => {
+                write!(f, "plugin function `{function}` {message}")
+            }
+            Self::ArgumentCount { expected, given } => write!(
+                f,
+                "plugin function takes {expected} argument{}, but {given} {} given",
+                if *expected == 1 { "" } else { "s" },
+                if *given == 1 { "was" } else { "were" },
+            ),
+            Self::Trap(err) => write!(f, "plugin panicked: {err}"),
+            Self::OutOfFuel(fuel) => {
+                write!(f, "plugin ran out of fuel (the limit is {fuel})")
+            }
+            Self::MemoryLimit(bytes) => write!(
+                f,
+                "plugin tried to use more memory than the limit of {bytes} bytes"
+            ),
+            Self::OutOfBounds {
+                offset,
+                length,
+                write,
+            } => {
+                let kind = if *write { "write" } else { "read" };
+                write!(
+                    f,
+                    "plugin tried to {kind} out of bounds: pointer {offset:#x} is out of bounds for {kind} of length {length}"
+                )
+            }
+            Self::Errored(message) => write!(f, "plugin errored with: {message}"),
+            Self::InvalidErrorMessage(_) => {
+                write!(
+                    f,
+                    "plugin errored, but did not return a valid error message"
+                )
+            }
+            Self::ProtocolViolation(violation) => {
+                write!(f, "plugin did not respect the protocol: {violation}")
+            }
+        }
+    }
+}
+
+impl std::error::Error for PluginError {}
+
+impl Plugin {
+    /// Read and instantiate the plugin at `path`.
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, PluginError> {
+        let binary = std::fs::read(path).map_err(|err| PluginError::Load(err.to_string()))?;
+        Self::new(&binary)
+    }
+
+    /// Read and instantiate the plugin at `path`, with limits on its resources.
+    pub fn load_with_limits(path: impl AsRef<Path>, limits: Limits) -> Result<Self, PluginError> {
+        let binary = std::fs::read(path).map_err(|err| PluginError::Load(err.to_string()))?;
+        Self::with_limits(&binary, limits)
+    }
+
+    /// Instantiate a plugin, and run its start function.
+    pub fn new(binary: &[u8]) -> Result<Self, PluginError> {
+        Self::with_limits(binary, Limits::default())
+    }
+
+    /// Instantiate a plugin with limits on its resources, and run its start function.
+    pub fn with_limits(binary: &[u8], limits: Limits) -> Result<Self, PluginError> {
+        // Without a limit, fuel is only used to measure the calls.
+        let mut config = Config::default();
+        config.consume_fuel(true);
+        let engine = Engine::new(&config);
+        let module =
+            Module::new(&engine, binary).map_err(|err| PluginError::Load(err.to_string()))?;
+
+        let mut linker = Linker::new(&engine);
+        linker
+            .func_wrap(
+                HOST_MODULE,
+                "wasm_minimal_protocol_send_result_to_host",
+                send_result_to_host,
+            )
+            .unwrap();
+        linker
+            .func_wrap(
+                HOST_MODULE,
+                "wasm_minimal_protocol_write_args_to_buffer",
+                write_args_to_buffer,
+            )
+            .unwrap();
+
+        // Without a limit, growing past the maximum of the module returns -1, like in
+        // typst, instead of trapping.
+        let mut memory_limit = StoreLimitsBuilder::new();
+        if let Some(bytes) = limits.memory {
+            memory_limit = memory_limit.memory_size(bytes).trap_on_grow_failure(true);
+        }
+        let state = HostState {
+            args: Vec::new(),
+            output: Vec::new(),
+            memory_error: None,
+            args_copied: 0,
+            result_copied: 0,
+            calls: Vec::new(),
+            limits: memory_limit.build(),
+        };
+        let mut store = Store::new(&engine, state);
+        store.limiter(|state| &mut state.limits);
+        store.set_fuel(limits.fuel.unwrap_or(u64::MAX)).unwrap();
+        let instance = linker
+            .instantiate_and_start(&mut store, &module)
+            .map_err(|err| match limit_error(&err, &limits) {
+                Some(err) => err,
+                None => PluginError::Instantiate(err.to_string()),
+            })?;
+        if instance.get_memory(&store, "memory").is_none() {
+            return Err(PluginError::NoMemory);
+        }
+        Ok(Self {
+            store,
+            instance,
+            limits,
+            strict: false,
+            last_call: CallStats::default(),
+            violations: Vec::new(),
+        })
+    }
+
+    /// The exported functions that can be called with [`Plugin::call`]: their
+    /// parameters and their result are `i32`s.
+    pub fn functions(&self) -> Vec<Function> {
+        self.instance
+            .exports(&self.store)
+            .filter_map(|export| {
+                let ty = export.ty(&self.store);
+                let ty = ty.func()?;
+                let is_protocol = ty.params().iter().all(|ty| *ty == ValType::I32)
+                    && ty.results() == [ValType::I32];
+                is_protocol.then(|| Function {
+                    name: export.name().to_owned(),
+                    arity: ty.params().len(),
+                })
+            })
+            .collect()
+    }
+
+    /// Call the function `name` with the given arguments, and return its result.
+    pub fn call(&mut self, name: &str, args: &[&[u8]]) -> Result<Vec<u8>, PluginError> {
+        self.violations.clear();
+        let func = self
+            .instance
+            .get_func(&self.store, name)
+            .ok_or_else(|| PluginError::NoFunction(name.to_owned()))?;
+        let ty = func.ty(&self.store);
+        if ty.params().iter().any(|ty| *ty != ValType::I32) {
+            return Err(PluginError::Signature {
+                function: name.to_owned(),
+                message: "has a parameter that is not a 32-bit integer".to_owned(),
+            });
+        }
+        if ty.results() != [ValType::I32] {
+            return Err(PluginError::Signature {
+                function: name.to_owned(),
+                message: "does not return exactly one 32-bit integer".to_owned(),
+            });
+        }
+        if ty.params().len() != args.len() {
+            return Err(PluginError::ArgumentCount {
+                expected: ty.params().len(),
+                given: args.len(),
+            });
+        }
+
+        let lengths: Vec<_> = args.iter().map(|arg| Val::I32(arg.len() as i32)).collect();
+        let state = self.store.data_mut();
+        state.args = args.iter().map(|arg| arg.to_vec()).collect();
+        state.output.clear();
+        state.memory_error = None;
+        state.args_copied = 0;
+        state.result_copied = 0;
+        state.calls.clear();
+        let fuel = self.limits.fuel.unwrap_or(u64::MAX);
+        self.store.set_fuel(fuel).unwrap();
+
+        let mut code = Val::I32(-1);
+        let result = func.call(&mut self.store, &lengths, std::slice::from_mut(&mut code));
+        self.last_call = CallStats {
+            fuel: fuel - self.store.get_fuel().unwrap(),
+            args_copied: self.store.data().args_copied,
+            result_copied: self.store.data().result_copied,
+            memory: self.memory().len(),
+        };
+        let state = self.store.data_mut();
+        state.args.clear();
+        result.map_err(|err| {
+            limit_error(&err, &self.limits).unwrap_or_else(|| PluginError::Trap(err.to_string()))
+        })?;
+
+        let Val::I32(code) = code else { unreachable!() };
+        let calls = std::mem::take(&mut state.calls);
+        let memory_error = state.memory_error.take();
+        let mut rules = Vec::new();
+        if let Some(MemoryError {
+            offset,
+            length,
+            write,
+        }) = memory_error
+        {
+            rules.push(Rule::OutOfBounds {
+                offset,
+                length,
+                write,
+            });
+        }
+        if code != 0 && code != 1 {
+            rules.push(Rule::InvalidCode(code));
+        }
+        rules.extend(check_calls(&calls));
+        self.violations = rules
+            .into_iter()
+            .map(|rule| Violation {
+                function: name.to_owned(),
+                rule,
+                calls: calls.clone(),
+            })
+            .collect();
+
+        if let Some(MemoryError {
+            offset,
+            length,
+            write,
+        }) = memory_error
+        {
+            return Err(PluginError::OutOfBounds {
+                offset,
+                length,
+                write,
+            });
+        }
+        let violation = self
+            .violations
+            .iter()
+            .find(|violation| self.strict || violation.rule.is_fatal());
+        if let Some(violation) = violation {
+            return Err(PluginError::ProtocolViolation(violation.clone()));
+        }
+
+        let output = std::mem::take(&mut self.store.data_mut().output);
+        match code {
+            0 => Ok(output),
+            _ => match String::from_utf8(output) {
+                Ok(message) => Err(PluginError::Errored(message)),
+                Err(err) => Err(PluginError::InvalidErrorMessage(err.into_bytes())),
+            },
+        }
+    }
+
+    /// The rules of the protocol broken by the last call of [`Plugin::call`], including
+    /// the ones that made it fail. A call that trapped has none.
+    pub fn violations(&self) -> &[Violation] {
+        &self.violations
+    }
+
+    /// In strict mode, a call that breaks any rule of the protocol fails with
+    /// [`PluginError::ProtocolViolation`], even when typst would accept it.
+    pub fn set_strict(&mut self, strict: bool) {
+        self.strict = strict;
+    }
+
+    /// Measurements of the last call of [`Plugin::call`], even if it failed.
+    pub fn last_call(&self) -> CallStats {
+        self.last_call
+    }
+
+    /// The memory exported by the plugin.
+    pub fn memory(&self) -> &[u8] {
+        // Checked when the plugin is loaded.
+        let memory = self.instance.get_memory(&self.store, "memory").unwrap();
+        memory.data(&self.store)
+    }
+}
+
+/// The error when the execution stopped because it reached one of the `limits`, or
+/// when the initial memory is larger than the limit.
+fn limit_error(err: &wasmi::Error, limits: &Limits) -> Option<PluginError> {
+    let denied = matches!(
+        err.kind(),
+        ErrorKind::Instantiation(InstantiationError::FailedToInstantiateMemory(
+            wasmi::errors::MemoryError::ResourceLimiterDeniedAllocation
+        ))
+    );
+    if denied {
+        return Some(PluginError::MemoryLimit(limits.memory?));
+    }
+    match err.as_trap_code()? {
+        TrapCode::OutOfFuel => Some(PluginError::OutOfFuel(limits.fuel?)),
+        TrapCode::GrowthOperationLimited => Some(PluginError::MemoryLimit(limits.memory?)),
+        _ => None,
+    }
+}
+
+/// The memory exported by the plugin, which can be missing while the start function
+/// runs.
+fn exported_memory(caller: &Caller<'_, HostState>) -> Option<Memory> {
+    caller.get_export("memory").and_then(Extern::into_memory)
+}
+
+fn write_args_to_buffer(mut caller: Caller<'_, HostState>, ptr: u32) {
+    let Some(memory) = exported_memory(&caller) else {
+        return;
+    };
+    caller.data_mut().calls.push(HostCall::WriteArgs { ptr });
+    let args = std::mem::take(&mut caller.data_mut().args);
+    let mut offset = ptr as usize;
+    for arg in args {
+        if memory.write(&mut caller, offset, &arg).is_err() {
+            caller.data_mut().memory_error = Some(MemoryError {
+                offset,
+                length: arg.len(),
+                write: true,
+            });
+            return;
+        }
+        offset += arg.len();
+        caller.data_mut().args_copied += arg.len();
+    }
+}
+
+fn send_result_to_host(mut caller: Caller<'_, HostState>, ptr: u32, len: u32) {
+    let Some(memory) = exported_memory(&caller) else {
+        return;
+    };
+    caller
+        .data_mut()
+        .calls
+        .push(HostCall::SendResult { ptr, len });
+    let mut buffer = std::mem::take(&mut caller.data_mut().output);
+    buffer.resize(len as usize, 0);
+    if memory.read(&caller, ptr as usize, &mut buffer).is_err() {
+        caller.data_mut().memory_error = Some(MemoryError {
+            offset: ptr as usize,
+            length: len as usize,
+            write: false,
+        });
+        return;
+    }
+    let state = caller.data_mut();
+    state.result_copied += buffer.len();
+    state.output = buffer;
+}
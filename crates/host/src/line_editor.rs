@@ -0,0 +1,96 @@
+//! Line editor with tab completion, for the REPL.
+//!
+//! When stdin is not a terminal, lines are read as is, without completion.
+
+use rustyline::{
+    completion::{Completer, Pair},
+    error::ReadlineError,
+    highlight::Highlighter,
+    hint::Hinter,
+    history::DefaultHistory,
+    validate::Validator,
+    CompletionType, Config, Context, Editor, Helper,
+};
+use std::io::{BufRead, IsTerminal};
+
+pub(crate) struct LineEditor {
+    /// `None` when stdin is not a terminal.
+    editor: Option<Editor<Completions, DefaultHistory>>,
+}
+
+impl LineEditor {
+    pub fn new(completions: Vec<String>) -> std::io::Result<Self> {
+        if !std::io::stdin().is_terminal() {
+            return Ok(Self { editor: None });
+        }
+        let config = Config::builder()
+            .completion_type(CompletionType::List)
+            .auto_add_history(true)
+            .build();
+        let mut editor = Editor::with_config(config).map_err(std::io::Error::other)?;
+        editor.set_helper(Some(Completions(completions)));
+        Ok(Self {
+            editor: Some(editor),
+        })
+    }
+
+    /// Read a line, without its line ending. Returns `None` at the end of the input.
+    pub fn read_line(&mut self, prompt: &str) -> std::io::Result<Option<String>> {
+        let Some(editor) = &mut self.editor else {
+            let mut line = String::new();
+            if std::io::stdin().lock().read_line(&mut line)? == 0 {
+                return Ok(None);
+            }
+            return Ok(Some(line.trim_end_matches(['\n', '\r']).to_owned()));
+        };
+        loop {
+            match editor.readline(prompt) {
+                Ok(line) => return Ok(Some(line)),
+                // Ctrl-C clears the line.
+                Err(ReadlineError::Interrupted) => {}
+                Err(ReadlineError::Eof) => return Ok(None),
+                Err(ReadlineError::Io(err)) => return Err(err),
+                Err(err) => return Err(std::io::Error::other(err)),
+            }
+        }
+    }
+}
+
+/// Words that can be completed at the start of the line.
+struct Completions(Vec<String>);
+
+impl Completer for Completions {
+    type Candidate = Pair;
+
+    fn complete(
+        &self,
+        line: &str,
+        pos: usize,
+        _: &Context<'_>,
+    ) -> rustyline::Result<(usize, Vec<Pair>)> {
+        let word = &line[..pos];
+        if word.contains(' ') {
+            return Ok((pos, Vec::new()));
+        }
+        let candidates = self
+            .0
+            .iter()
+            .filter(|completion| completion.starts_with(word))
+            .map(|completion| Pair {
+                display: completion.clone(),
+                replacement: format!("{completion} "),
+            })
+            .collect();
+        Ok((0, candidates))
+    }
+}
+
+impl Hinter for Completions {
+    type Hint = String;
+}
+
+impl Highlighter for Completions {}
+
+impl Validator for Completions {}
+
+impl Helper for Completions {}
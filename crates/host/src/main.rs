@@ -0,0 +1,363 @@
+mod line_editor;
+mod parse_args;
+mod repl;
+
+use clap::{CommandFactory, FromArgMatches};
+use parse_args::{Cli, Command, Format};
+use std::{
+    io::Write,
+    path::{Path, PathBuf},
+    process::ExitCode,
+};
+use wasm_minimal_protocol_host::{
+    bench,
+    conformance::Suite,
+    determinism,
+    format::{cbor_to_json, hex},
+    fuzz, Function, Limits, Plugin, PluginError,
+};
+
+/// Exit status when the function returned an error.
+const EXIT_ERRORED: u8 = 1;
+/// Exit status when the plugin trapped.
+const EXIT_TRAP: u8 = 2;
+/// Exit status for any other error.
+const EXIT_FAILURE: u8 = 3;
+/// Exit status when the plugin went over its fuel or memory limit.
+const EXIT_LIMIT: u8 = 4;
+
+fn main() -> ExitCode {
+    let matches = Cli::command().get_matches();
+    let cli = Cli::from_arg_matches(&matches).unwrap_or_else(|err| err.exit());
+    let (_, matches) = matches.subcommand().unwrap();
+    match cli.command {
+        Command::Call {
+            plugin,
+            function,
+            arguments,
+            limits,
+            strict,
+            format,
+        } => {
+            let args = match arguments.values(matches) {
+                Ok(args) => args,
+                Err(err) => return fail(EXIT_FAILURE, err),
+            };
+            let mut plugin = match Plugin::load_with_limits(&plugin, limits.limits()) {
+                Ok(plugin) => plugin,
+                Err(err) => return fail(exit_status(&err), err),
+            };
+            plugin.set_strict(strict);
+            let args: Vec<&[u8]> = args.iter().map(Vec::as_slice).collect();
+            let result = plugin.call(&function, &args);
+            for violation in plugin.violations() {
+                if !matches!(&result, Err(PluginError::ProtocolViolation(err)) if err == violation)
+                {
+                    eprintln!("warning: plugin did not respect the protocol: {violation}");
+                }
+            }
+            match result {
+                Ok(result) => match print_result(&result, format) {
+                    Ok(()) => ExitCode::SUCCESS,
+                    Err(err) => fail(EXIT_FAILURE, err),
+                },
+                Err(err) => fail(exit_status(&err), err),
+            }
+        }
+        Command::Bench {
+            plugin,
+            function,
+            arguments,
+            limits,
+            iterations,
+            warmup,
+            save_baseline,
+            baseline,
+        } => {
+            let options = bench::Options {
+                iterations,
+                warmup,
+                limits: limits.limits(),
+            };
+            let result = arguments.values(matches).and_then(|args| {
+                run_bench(
+                    &plugin,
+                    &function,
+                    &args,
+                    &options,
+                    save_baseline.as_deref(),
+                    baseline.as_deref(),
+                )
+            });
+            match result {
+                Ok(()) => ExitCode::SUCCESS,
+                Err(err) => fail(EXIT_FAILURE, err),
+            }
+        }
+        Command::Repl {
+            plugin,
+            limits,
+            strict,
+        } => match repl::run(&plugin, limits.limits(), strict) {
+            Ok(()) => ExitCode::SUCCESS,
+            Err(err) => fail(EXIT_FAILURE, err),
+        },
+        Command::Fuzz {
+            plugin,
+            functions,
+            runs,
+            seed,
+            max_input,
+            max_output,
+            out_dir,
+            limits,
+        } => {
+            let options = fuzz::Options {
+                runs,
+                seed: seed.unwrap_or_else(random_seed),
+                max_input,
+                max_output,
+                limits: limits.limits_with_fuel(fuzz::DEFAULT_FUEL),
+                ..fuzz::Options::default()
+            };
+            match run_fuzz(&plugin, &functions, &options, &out_dir) {
+                Ok(true) => ExitCode::SUCCESS,
+                Ok(false) => ExitCode::from(EXIT_ERRORED),
+                Err(err) => fail(EXIT_FAILURE, err),
+            }
+        }
+        Command::CheckDeterminism {
+            plugin,
+            functions,
+            suites,
+            calls,
+            seed,
+            max_input,
+            limits,
+        } => {
+            let seed = seed.unwrap_or_else(random_seed);
+            let limits = limits.limits_with_fuel(fuzz::DEFAULT_FUEL);
+            match check_determinism(&plugin, &functions, &suites, calls, max_input, seed, limits) {
+                Ok(true) => ExitCode::SUCCESS,
+                Ok(false) => ExitCode::from(EXIT_ERRORED),
+                Err(err) => fail(EXIT_FAILURE, err),
+            }
+        }
+        Command::Test {
+            plugin,
+            suites,
+            limits,
+        } => match test(&plugin, &suites, limits.limits()) {
+            Ok(true) => ExitCode::SUCCESS,
+            Ok(false) => ExitCode::from(EXIT_ERRORED),
+            Err(err) => fail(EXIT_FAILURE, err),
+        },
+    }
+}
+
+fn run_bench(
+    path: &Path,
+    function: &str,
+    args: &[Vec<u8>],
+    options: &bench::Options,
+    save_baseline: Option<&Path>,
+    baseline: Option<&Path>,
+) -> Result<(), String> {
+    let binary =
+        std::fs::read(path).map_err(|err| format!("cannot read {}: {err}", path.display()))?;
+    let baseline = baseline
+        .map(|path| {
+            let text = std::fs::read_to_string(path)
+                .map_err(|err| format!("cannot read {}: {err}", path.display()))?;
+            bench::Report::from_baseline(&text).map_err(|err| format!("{}: {err}", path.display()))
+        })
+        .transpose()?;
+    let args: Vec<&[u8]> = args.iter().map(Vec::as_slice).collect();
+    let report = bench::bench(&binary, function, &args, options).map_err(|err| err.to_string())?;
+    match &baseline {
+        Some(baseline) => println!("{}", report.compared_to(baseline)),
+        None => println!("{report}"),
+    }
+    if let Some(path) = save_baseline {
+        std::fs::write(path, report.to_baseline())
+            .map_err(|err| format!("cannot write {}: {err}", path.display()))?;
+    }
+    Ok(())
+}
+
+/// Run the suites, print their failures, and return whether all the cases passed.
+fn test(plugin: &Path, suites: &[PathBuf], limits: Limits) -> Result<bool, String> {
+    let binary =
+        std::fs::read(plugin).map_err(|err| format!("cannot read {}: {err}", plugin.display()))?;
+    let (mut passed, mut failed) = (0, 0);
+    for path in suites {
+        let suite = Suite::load(path)?;
+        let failures = suite.run_with_limits(&binary, limits);
+        for failure in &failures {
+            println!("{}: {failure}", path.display());
+        }
+        passed += suite.cases.len() - failures.len();
+        failed += failures.len();
+    }
+    println!("{passed} passed, {failed} failed");
+    Ok(failed == 0)
+}
+
+/// Fuzz the functions, save the problems in `out_dir`, and return whether there was
+/// no problem.
+fn run_fuzz(
+    path: &Path,
+    names: &[String],
+    options: &fuzz::Options,
+    out_dir: &Path,
+) -> Result<bool, String> {
+    let binary =
+        std::fs::read(path).map_err(|err| format!("cannot read {}: {err}", path.display()))?;
+    let functions = select_functions(&binary, names)?;
+    eprintln!("fuzzing with seed {}", options.seed);
+    let mut saved = Ok(0);
+    let findings = fuzz::fuzz(&binary, &functions, options, |finding| {
+        println!("{}: {}", finding.function, finding.problem);
+        if let Ok(count) = &mut saved {
+            match save_finding(finding, out_dir) {
+                Ok(path) => {
+                    println!("  saved to {}", path.display());
+                    *count += 1;
+                }
+                Err(err) => saved = Err(err),
+            }
+        }
+    });
+    saved?;
+    let plural = if findings.len() == 1 { "" } else { "s" };
+    println!("{} problem{plural} found", findings.len());
+    Ok(findings.is_empty())
+}
+
+/// Call the functions in several orders, print the results that changed, and return
+/// whether there was none.
+fn check_determinism(
+    path: &Path,
+    names: &[String],
+    suites: &[PathBuf],
+    count: usize,
+    max_input: usize,
+    seed: u64,
+    limits: Limits,
+) -> Result<bool, String> {
+    let binary =
+        std::fs::read(path).map_err(|err| format!("cannot read {}: {err}", path.display()))?;
+    let functions = select_functions(&binary, names)?;
+    let mut calls = Vec::new();
+    for path in suites {
+        let suite = Suite::load(path)?;
+        calls.extend(
+            suite
+                .cases
+                .into_iter()
+                .filter(|case| functions.iter().any(|f| f.name == case.function))
+                .map(|case| determinism::Call {
+                    function: case.function,
+                    args: case.args,
+                }),
+        );
+    }
+    calls.extend(determinism::generate_calls(
+        &functions, count, max_input, seed,
+    ));
+
+    eprintln!("checking {} calls with seed {seed}", calls.len());
+    let report =
+        determinism::check(&binary, &calls, seed, limits).map_err(|err| err.to_string())?;
+    for (call, err) in &report.over_limits {
+        println!("{call}: {err}");
+    }
+    for divergence in &report.divergences {
+        println!("{divergence}");
+    }
+    if !report.over_limits.is_empty() {
+        let plural = if report.over_limits.len() == 1 {
+            ""
+        } else {
+            "s"
+        };
+        println!("{} call{plural} over the limits", report.over_limits.len());
+    }
+    let plural = if report.divergences.len() == 1 {
+        ""
+    } else {
+        "s"
+    };
+    println!("{} divergent result{plural}", report.divergences.len());
+    Ok(report.divergences.is_empty() && report.over_limits.is_empty())
+}
+
+/// The protocol functions of the plugin with the given names, or all of them if
+/// `names` is empty.
+fn select_functions(binary: &[u8], names: &[String]) -> Result<Vec<Function>, String> {
+    let mut functions = Plugin::new(binary)
+        .map_err(|err| err.to_string())?
+        .functions();
+    if !names.is_empty() {
+        if let Some(name) = names
+            .iter()
+            .find(|name| !functions.iter().any(|f| f.name == **name))
+        {
+            return Err(PluginError::NoFunction(name.clone()).to_string());
+        }
+        functions.retain(|function| names.contains(&function.name));
+    }
+    Ok(functions)
+}
+
+fn random_seed() -> u64 {
+    let now = std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH);
+    now.map_or(0, |now| now.as_nanos() as u64)
+}
+
+/// Write the finding to a new file of `out_dir`, and return its path.
+fn save_finding(finding: &fuzz::Finding, out_dir: &Path) -> Result<PathBuf, String> {
+    let error = |err: std::io::Error| format!("cannot write in {}: {err}", out_dir.display());
+    std::fs::create_dir_all(out_dir).map_err(error)?;
+    let path = (1..)
+        .map(|n| out_dir.join(format!("{}-{n}.txt", finding.function)))
+        .find(|path| !path.exists())
+        .unwrap();
+    std::fs::write(&path, finding.to_suite()).map_err(error)?;
+    Ok(path)
+}
+
+fn exit_status(err: &PluginError) -> u8 {
+    match err {
+        PluginError::Errored(_) | PluginError::InvalidErrorMessage(_) => EXIT_ERRORED,
+        PluginError::Trap(_) => EXIT_TRAP,
+        PluginError::OutOfFuel(_) | PluginError::MemoryLimit(_) => EXIT_LIMIT,
+        _ => EXIT_FAILURE,
+    }
+}
+
+fn fail(status: u8, err: impl std::fmt::Display) -> ExitCode {
+    eprintln!("error: {err}");
+    ExitCode::from(status)
+}
+
+fn print_result(result: &[u8], format: Format) -> Result<(), String> {
+    let mut stdout = std::io::stdout().lock();
+    let written = match format {
+        Format::Raw => stdout.write_all(result),
+        Format::Hex => writeln!(stdout, "{}", hex(result)),
+        Format::Utf8 => {
+            let text = std::str::from_utf8(result)
+                .map_err(|err| format!("the result is not valid UTF-8: {err}"))?;
+            writeln!(stdout, "{text}")
+        }
+        Format::Json => {
+            let json = cbor_to_json(result)
+                .map_err(|err| format!("the result is not valid CBOR: {err}"))?;
+            writeln!(stdout, "{json}")
+        }
+    };
+    written
+        .and_then(|()| stdout.flush())
+        .map_err(|err| err.to_string())
+}
@@ -0,0 +1,261 @@
+use clap::{ArgMatches, Args, Parser, Subcommand, ValueEnum};
+use std::path::PathBuf;
+use wasm_minimal_protocol_host::{format::from_hex, Limits};
+
+/// Run typst plugins without typst.
+#[derive(Parser)]
+#[command(name = "wmp", version)]
+pub(crate) struct Cli {
+    #[command(subcommand)]
+    pub command: Command,
+}
+
+#[derive(Subcommand)]
+pub(crate) enum Command {
+    /// Call a function of a plugin, and print its result.
+    ///
+    /// The exit status is 0 if the function succeeded, 1 if it returned an error, 2 if
+    /// it trapped, 3 for any other error, and 4 if it went over `--max-fuel` or
+    /// `--max-memory`.
+    ///
+    /// The rules of the protocol that typst does not check (the result is sent
+    /// exactly once, the arguments are asked at most once, before the result) are
+    /// checked too: breaking them prints a warning, or fails with `--strict`.
+    Call {
+        /// Path of the plugin.
+        plugin: PathBuf,
+        /// Name of the function.
+        function: String,
+        #[command(flatten)]
+        arguments: Arguments,
+        #[command(flatten)]
+        limits: LimitArgs,
+        /// Fail if the plugin breaks a rule of the protocol, even one that typst does
+        /// not check.
+        #[arg(long)]
+        strict: bool,
+        /// How to print the result.
+        #[arg(short, long, value_enum, default_value_t = Format::Raw)]
+        format: Format,
+    },
+    /// Measure the cost of a function: the time of the instantiation and of a call, the
+    /// fuel consumed, the bytes copied, and the peak memory.
+    ///
+    /// The fuel, the copied bytes and the memory do not depend on the machine: compare
+    /// them with a baseline to find regressions.
+    Bench {
+        /// Path of the plugin.
+        plugin: PathBuf,
+        /// Name of the function.
+        function: String,
+        #[command(flatten)]
+        arguments: Arguments,
+        #[command(flatten)]
+        limits: LimitArgs,
+        /// Number of measured calls.
+        #[arg(short = 'n', long, default_value_t = 100)]
+        iterations: usize,
+        /// Number of calls before the measured ones.
+        #[arg(long, default_value_t = 10)]
+        warmup: usize,
+        /// Save the measurements to this file.
+        #[arg(long, value_name = "PATH")]
+        save_baseline: Option<PathBuf>,
+        /// Compare the measurements with the ones saved in this file.
+        #[arg(long, value_name = "PATH")]
+        baseline: Option<PathBuf>,
+    },
+    /// Call the functions of a plugin interactively, on the same instance.
+    Repl {
+        /// Path of the plugin.
+        plugin: PathBuf,
+        #[command(flatten)]
+        limits: LimitArgs,
+        /// Fail the calls that break a rule of the protocol, even one that typst does
+        /// not check.
+        #[arg(long)]
+        strict: bool,
+    },
+    /// Run conformance suites against a plugin, and print the failed cases.
+    ///
+    /// Each line of a suite is a case: `function arg1 arg2 ... => ok|error|returns|trap
+    /// [result]`, with the argument syntax of `wmp repl`. A case fails if the plugin
+    /// breaks a rule of the protocol, even one that typst does not check. The exit
+    /// status is 0 if all the cases passed, 1 if some failed, and 3 for any other
+    /// error.
+    Test {
+        /// Path of the plugin.
+        plugin: PathBuf,
+        /// Paths of the suites.
+        #[arg(required = true)]
+        suites: Vec<PathBuf>,
+        #[command(flatten)]
+        limits: LimitArgs,
+    },
+    /// Check that the functions of a plugin always give the same result for the same
+    /// arguments, as typst requires.
+    ///
+    /// Each call is compared with the same call on a new instance: on another new
+    /// instance, twice on the same instance, and after the other calls in several
+    /// orders. Calls that go over `--max-fuel` or `--max-memory` are reported too,
+    /// since they cannot be checked. The exit status is 0 if all the results were the
+    /// same and no call went over the limits, 1 otherwise, and 3 for any other error.
+    CheckDeterminism {
+        /// Path of the plugin.
+        plugin: PathBuf,
+        /// Functions to check. All the protocol functions by default.
+        #[arg(long = "function", value_name = "NAME", value_delimiter = ',')]
+        functions: Vec<String>,
+        /// Conformance suites whose calls are checked too.
+        #[arg(long = "suite", value_name = "PATH")]
+        suites: Vec<PathBuf>,
+        /// Number of calls of each function, with generated arguments.
+        #[arg(long, default_value_t = 20)]
+        calls: usize,
+        /// Seed of the random generator. Random by default.
+        #[arg(long)]
+        seed: Option<u64>,
+        /// Maximum length of a generated argument.
+        #[arg(long, value_name = "BYTES", default_value_t = 256)]
+        max_input: usize,
+        #[command(flatten)]
+        limits: LimitArgs,
+    },
+    /// Call the functions of a plugin with arbitrary arguments, to find crashes.
+    ///
+    /// A call has a problem if it fails without returning an error (a trap, an out of
+    /// bounds pointer, a violation of the protocol, ...), if its result is too large,
+    /// or if calling the function again gives another result. The arguments of each
+    /// problem are minimised, and saved as a conformance suite that `wmp test` can
+    /// run. Going over `--max-fuel` or `--max-memory` is a problem too, to find
+    /// infinite loops and leaks. The exit status is 0 if no problem was found, 1
+    /// otherwise, and 3 for any other error.
+    Fuzz {
+        /// Path of the plugin.
+        plugin: PathBuf,
+        /// Functions to fuzz. All the protocol functions by default.
+        #[arg(long = "function", value_name = "NAME", value_delimiter = ',')]
+        functions: Vec<String>,
+        /// Number of calls of each function.
+        #[arg(long, default_value_t = 1000)]
+        runs: usize,
+        /// Seed of the random generator. Random by default.
+        #[arg(long)]
+        seed: Option<u64>,
+        /// Maximum length of a generated argument.
+        #[arg(long, value_name = "BYTES", default_value_t = 1024)]
+        max_input: usize,
+        /// Results longer than this are a problem.
+        #[arg(long, value_name = "BYTES", default_value_t = 1 << 20)]
+        max_output: usize,
+        /// Directory where the problems are saved.
+        #[arg(long, default_value = "fuzz-findings")]
+        out_dir: PathBuf,
+        #[command(flatten)]
+        limits: LimitArgs,
+    },
+}
+
+/// Arguments of a plugin function. They are passed in the order of the command line.
+#[derive(Args)]
+pub(crate) struct Arguments {
+    /// An argument, given as UTF-8 text.
+    #[arg(long = "arg", value_name = "STRING")]
+    pub text: Vec<String>,
+    /// An argument, read from a file.
+    #[arg(long = "arg-file", value_name = "PATH")]
+    pub file: Vec<PathBuf>,
+    /// An argument, given as hexadecimal digits (for example '00ff').
+    #[arg(long = "arg-hex", value_name = "HEX")]
+    pub hex: Vec<String>,
+}
+
+impl Arguments {
+    /// The bytes of each argument, in the order of the command line.
+    ///
+    /// `matches` are the matches of the subcommand, which give the order of the
+    /// arguments.
+    pub fn values(&self, matches: &ArgMatches) -> Result<Vec<Vec<u8>>, String> {
+        let mut values = Vec::new();
+        let indices = |id| matches.indices_of(id).into_iter().flatten();
+        for (index, text) in indices("text").zip(&self.text) {
+            values.push((index, text.as_bytes().to_vec()));
+        }
+        for (index, path) in indices("file").zip(&self.file) {
+            let bytes = std::fs::read(path)
+                .map_err(|err| format!("cannot read {}: {err}", path.display()))?;
+            values.push((index, bytes));
+        }
+        for (index, hex) in indices("hex").zip(&self.hex) {
+            values.push((index, from_hex(hex)?));
+        }
+        values.sort_by_key(|(index, _)| *index);
+        Ok(values.into_iter().map(|(_, value)| value).collect())
+    }
+}
+
+/// Limits on the resources of the plugin. There is no limit by default, like in typst,
+/// except for the fuel in `fuzz` and `check-determinism`.
+#[derive(Args)]
+pub(crate) struct LimitArgs {
+    /// Fuel available to each call: roughly, the number of instructions it can execute.
+    /// Unlimited by default, except in `fuzz` and `check-determinism`, where it is
+    /// 100000000.
+    #[arg(long, value_name = "FUEL")]
+    pub max_fuel: Option<u64>,
+    /// Maximum size of the memory of the plugin, in bytes, or with a unit (for example
+    /// '64KiB' or '16MiB').
+    #[arg(long, value_name = "SIZE", value_parser = parse_size)]
+    pub max_memory: Option<usize>,
+}
+
+impl LimitArgs {
+    pub fn limits(&self) -> Limits {
+        Limits {
+            fuel: self.max_fuel,
+            memory: self.max_memory,
+        }
+    }
+
+    /// The limits, with `fuel` if no fuel limit is given.
+    pub fn limits_with_fuel(&self, fuel: u64) -> Limits {
+        Limits {
+            fuel: Some(self.max_fuel.unwrap_or(fuel)),
+            ..self.limits()
+        }
+    }
+}
+
+/// A number of bytes, with an optional `KiB`, `MiB` or `GiB` unit.
+fn parse_size(text: &str) -> Result<usize, String> {
+    let (number, unit) = match text.find(|c: char| !c.is_ascii_digit()) {
+        Some(index) => text.split_at(index),
+        None => (text, ""),
+    };
+    let unit = match unit.trim() {
+        "" | "B" => 1,
+        "KiB" => 1 << 10,
+        "MiB" => 1 << 20,
+        "GiB" => 1 << 30,
+        unit => return Err(format!("unknown unit {unit:?}, expected KiB, MiB or GiB")),
+    };
+    let number: usize = number
+        .parse()
+        .map_err(|_| format!("invalid size {text:?}"))?;
+    number
+        .checked_mul(unit)
+        .ok_or_else(|| format!("size {text:?} is too large"))
+}
+
+/// How to print the result of a function.
+#[derive(Clone, Copy, ValueEnum)]
+pub(crate) enum Format {
+    /// The bytes of the result, as is.
+    Raw,
+    /// Hexadecimal digits.
+    Hex,
+    /// UTF-8 text. Fails if the result is not valid UTF-8.
+    Utf8,
+    /// Decode the result as CBOR, and print it as JSON.
+    Json,
+}
@@ -0,0 +1,136 @@
+//! The rules of the protocol that plugins can break without a trap.
+//!
+//! During a call, a plugin must:
+//!
+//! - call `wasm_minimal_protocol_write_args_to_buffer` at most once, before sending its
+//!   result;
+//! - call `wasm_minimal_protocol_send_result_to_host` exactly once, with its result or
+//!   its error message;
+//! - give pointers that are in bounds of its memory;
+//! - return `0` or `1`.
+//!
+//! Typst does not check the first two rules: it writes nothing the second time the
+//! arguments are requested, and keeps the last result sent (or an empty one). The
+//! host checks them anyway, see [`crate::Plugin::violations`].
+
+use std::fmt;
+
+/// A call of a host function by the plugin.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum HostCall {
+    /// `wasm_minimal_protocol_write_args_to_buffer`, with its pointer.
+    WriteArgs { ptr: u32 },
+    /// `wasm_minimal_protocol_send_result_to_host`, with its pointer and its length.
+    SendResult { ptr: u32, len: u32 },
+}
+
+impl fmt::Display for HostCall {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::WriteArgs { ptr } => write!(f, "write_args_to_buffer({ptr:#x})"),
+            Self::SendResult { ptr, len } => write!(f, "send_result_to_host({ptr:#x}, {len})"),
+        }
+    }
+}
+
+/// A rule of the protocol.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Rule {
+    /// The function returned without calling `send_result_to_host`.
+    NoResult,
+    /// The function called `send_result_to_host` more than once.
+    ResultSentTwice,
+    /// The function called `write_args_to_buffer` more than once.
+    ArgsWrittenTwice,
+    /// The function called `write_args_to_buffer` after `send_result_to_host`.
+    ArgsAfterResult,
+    /// The function returned something else than `0` or `1`.
+    InvalidCode(i32),
+    /// The function gave a pointer that is out of bounds of its memory.
+    OutOfBounds {
+        offset: usize,
+        length: usize,
+        write: bool,
+    },
+}
+
+impl Rule {
+    /// Whether the call fails even outside of the strict mode, like in typst.
+    pub fn is_fatal(&self) -> bool {
+        matches!(self, Self::InvalidCode(_) | Self::OutOfBounds { .. })
+    }
+}
+
+impl fmt::Display for Rule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::NoResult => write!(f, "returned without sending a result"),
+            Self::ResultSentTwice => write!(f, "sent a result more than once"),
+            Self::ArgsWrittenTwice => write!(f, "asked for its arguments more than once"),
+            Self::ArgsAfterResult => write!(f, "asked for its arguments after sending a result"),
+            Self::InvalidCode(code) => write!(f, "returned {code}, instead of 0 or 1"),
+            Self::OutOfBounds {
+                offset,
+                length,
+                write,
+            } => {
+                let kind = if *write { "write" } else { "read" };
+                write!(
+                    f,
+                    "gave an out of bounds pointer: {offset:#x}, for a {kind} of length {length}"
+                )
+            }
+        }
+    }
+}
+
+/// A rule broken by a call.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Violation {
+    /// The function that was called.
+    pub function: String,
+    pub rule: Rule,
+    /// The host functions called by the plugin during the call, in order.
+    pub calls: Vec<HostCall>,
+}
+
+impl fmt::Display for Violation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "`{}` {} (host calls: ", self.function, self.rule)?;
+        if self.calls.is_empty() {
+            write!(f, "none")?;
+        }
+        for (i, call) in self.calls.iter().enumerate() {
+            if i > 0 {
+                write!(f, ", ")?;
+            }
+            write!(f, "{call}")?;
+        }
+        write!(f, ")")
+    }
+}
+
+/// The rules broken by the sequence of host calls of a function that returned, in the
+/// order of the calls, each one once.
+pub(crate) fn check_calls(calls: &[HostCall]) -> Vec<Rule> {
+    let mut rules = Vec::new();
+    let mut push = |rule| {
+        if !rules.contains(&rule) {
+            rules.push(rule);
+        }
+    };
+    let (mut args_written, mut result_sent) = (false, false);
+    for call in calls {
+        match call {
+            HostCall::WriteArgs { .. } if result_sent => push(Rule::ArgsAfterResult),
+            HostCall::WriteArgs { .. } if args_written => push(Rule::ArgsWrittenTwice),
+            HostCall::WriteArgs { .. } => args_written = true,
+            HostCall::SendResult { .. } if result_sent => push(Rule::ResultSentTwice),
+            HostCall::SendResult { .. } => result_sent = true,
+        }
+    }
+    if !result_sent {
+        push(Rule::NoResult);
+    }
+    rules
+}
@@ -0,0 +1,137 @@
+//! Interactive calls to the functions of a plugin.
+//!
+//! The instance is kept between calls, so that the state of the plugin can be
+//! observed across calls.
+
+use crate::{line_editor::LineEditor, parse_args::Format};
+use clap::ValueEnum;
+use std::{path::Path, time::Instant};
+use wasm_minimal_protocol_host::{
+    format::{cbor_to_json, hex, parse_arguments},
+    Limits, Plugin, PluginError,
+};
+
+const PAGE_SIZE: usize = 65536;
+
+const HELP: &str = "\
+Call a function with `name arg1 arg2 ...`. Each argument is one of:
+  hello, \"hello world\"    UTF-8 text (with the escapes \\\" \\\\ \\n \\t)
+  0x00ff                  hexadecimal digits
+  @path/to/file           the contents of a file
+  cbor:{\"a\": [1, 2]}      a JSON value, encoded as CBOR
+
+Commands:
+  :functions              list the protocol functions
+  :format raw|hex|utf8|json
+                          how to print results (raw prints the bytes as text)
+  :memory                 print the size of the memory
+  :reload                 start again with a new instance
+  :help                   print this message
+  :quit                   exit (or Ctrl-D)";
+
+pub(crate) fn run(path: &Path, limits: Limits, strict: bool) -> Result<(), String> {
+    let mut plugin = Plugin::load_with_limits(path, limits).map_err(|err| err.to_string())?;
+    plugin.set_strict(strict);
+    let functions = plugin.functions();
+    print_functions(&plugin);
+    println!("Type :help for help.");
+
+    let mut completions: Vec<_> = functions.iter().map(|f| f.name.clone()).collect();
+    completions.extend(
+        [
+            ":functions",
+            ":format",
+            ":memory",
+            ":reload",
+            ":help",
+            ":quit",
+        ]
+        .map(String::from),
+    );
+    let mut editor = LineEditor::new(completions).map_err(|err| err.to_string())?;
+    let mut format = Format::Raw;
+    while let Some(line) = editor.read_line("> ").map_err(|err| err.to_string())? {
+        let line = line.trim();
+        let (name, rest) = line.split_once(' ').unwrap_or((line, ""));
+        match name {
+            "" => {}
+            ":quit" | ":q" => break,
+            ":help" => println!("{HELP}"),
+            ":functions" => print_functions(&plugin),
+            ":memory" => println!("{} pages", plugin.memory().len() / PAGE_SIZE),
+            ":reload" => match Plugin::load_with_limits(path, limits) {
+                Ok(new) => {
+                    plugin = new;
+                    plugin.set_strict(strict);
+                }
+                Err(err) => println!("error: {err}"),
+            },
+            ":format" => match Format::from_str(rest.trim(), true) {
+                Ok(new) => format = new,
+                Err(err) => println!("error: {err}"),
+            },
+            command if command.starts_with(':') => {
+                println!("error: unknown command {command}, type :help for help")
+            }
+            function => match parse_arguments(rest) {
+                Ok(args) => call(&mut plugin, function, &args, format),
+                Err(err) => println!("error: {err}"),
+            },
+        }
+    }
+    Ok(())
+}
+
+fn print_functions(plugin: &Plugin) {
+    println!("Functions:");
+    for function in plugin.functions() {
+        let plural = if function.arity == 1 { "" } else { "s" };
+        println!("  {} ({} argument{plural})", function.name, function.arity);
+    }
+}
+
+fn call(plugin: &mut Plugin, function: &str, args: &[Vec<u8>], format: Format) {
+    let args: Vec<&[u8]> = args.iter().map(Vec::as_slice).collect();
+    let memory_before = plugin.memory().len();
+    let start = Instant::now();
+    let result = plugin.call(function, &args);
+    let elapsed = start.elapsed();
+    let memory_after = plugin.memory().len();
+
+    match &result {
+        Ok(result) => println!("{}", display(result, format)),
+        Err(err) => println!("error: {err}"),
+    }
+    for violation in plugin.violations() {
+        if !matches!(&result, Err(PluginError::ProtocolViolation(err)) if err == violation) {
+            println!("warning: plugin did not respect the protocol: {violation}");
+        }
+    }
+    let mut summary = format!("{elapsed:.2?}");
+    if let Ok(result) = &result {
+        summary = format!("{} bytes, {summary}", result.len());
+    }
+    if memory_after != memory_before {
+        summary.push_str(&format!(
+            ", memory grew from {} to {} pages",
+            memory_before / PAGE_SIZE,
+            memory_after / PAGE_SIZE
+        ));
+    }
+    println!("({summary})");
+}
+
+fn display(result: &[u8], format: Format) -> String {
+    match format {
+        Format::Raw => String::from_utf8_lossy(result).into_owned(),
+        Format::Hex => hex(result),
+        Format::Utf8 => match std::str::from_utf8(result) {
+            Ok(text) => text.to_owned(),
+            Err(_) => format!("{} (not valid UTF-8)", hex(result)),
+        },
+        Format::Json => match cbor_to_json(result) {
+            Ok(json) => json,
+            Err(err) => format!("{} (not valid CBOR: {err})", hex(result)),
+        },
+    }
+}
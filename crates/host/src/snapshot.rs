@@ -0,0 +1,231 @@
+//! Snapshot tests of the results of plugins, in the style of `insta`.
+//!
+//! [`Snapshots::assert_call`] calls a function, and compares its result with the one
+//! saved in the snapshot directory, in a readable form. The first run saves the
+//! snapshot. When the result changes, the test fails with a diff, and the new
+//! snapshot is saved next to the old one, with the `.snap.new` extension.
+//!
+//! Run the tests with `WMP_UPDATE_SNAPSHOTS=1` to accept the new results. When the
+//! `CI` environment variable is set, missing snapshots fail the test instead of being
+//! saved.
+//!
+//! ```no_run
+//! use wasm_minimal_protocol_host::{snapshot::{Format, Snapshots}, Plugin};
+//!
+//! let snapshots = Snapshots::new(concat!(env!("CARGO_MANIFEST_DIR"), "/tests/snapshots"));
+//! let mut plugin = Plugin::load("hello.wasm")?;
+//! snapshots.assert_call("concatenate", &mut plugin, "concatenate", &[b"a", b"b"], Format::Utf8);
+//! # Ok::<(), wasm_minimal_protocol_host::PluginError>(())
+//! ```
+
+use crate::{
+    format::{cbor_to_json, format_value, hex_dump},
+    Plugin, PluginError,
+};
+use std::{
+    fmt::Write,
+    path::{Path, PathBuf},
+};
+
+/// Environment variable to set to `1` to accept the new snapshots.
+pub const UPDATE_VARIABLE: &str = "WMP_UPDATE_SNAPSHOTS";
+
+/// How results are written in the snapshots.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Format {
+    /// UTF-8 text.
+    Utf8,
+    /// A hex dump, with the printable ASCII characters.
+    Hex,
+    /// Decoded as CBOR, and written as JSON.
+    Json,
+}
+
+impl Format {
+    fn name(self) -> &'static str {
+        match self {
+            Self::Utf8 => "utf8",
+            Self::Hex => "hex",
+            Self::Json => "json",
+        }
+    }
+
+    /// `bytes` in this format. Bytes that cannot be decoded are written as a hex dump.
+    fn render(self, bytes: &[u8]) -> String {
+        match self {
+            Self::Utf8 => match std::str::from_utf8(bytes) {
+                Ok(text) => text.to_owned(),
+                Err(err) => format!("not valid UTF-8 ({err}):\n{}", hex_dump(bytes)),
+            },
+            Self::Hex => hex_dump(bytes),
+            Self::Json => match cbor_to_json(bytes) {
+                Ok(json) => json,
+                Err(err) => format!("not valid CBOR ({err}):\n{}", hex_dump(bytes)),
+            },
+        }
+    }
+}
+
+/// The contents of a snapshot: a call and its result.
+pub fn render(
+    function: &str,
+    args: &[&[u8]],
+    result: &Result<Vec<u8>, PluginError>,
+    format: Format,
+) -> String {
+    let args: String = args
+        .iter()
+        .map(|arg| format!(" {}", format_value(arg)))
+        .collect();
+    let (status, body) = match result {
+        Ok(output) => ("ok", format.render(output)),
+        Err(PluginError::Errored(message)) => ("error", message.clone()),
+        Err(PluginError::InvalidErrorMessage(message)) => ("error", format.render(message)),
+        Err(PluginError::Trap(message)) => ("trap", message.clone()),
+        Err(err) => ("failed", err.to_string()),
+    };
+    let mut snapshot = format!(
+        "function: {function}\nargs:{args}\nformat: {}\nstatus: {status}\n---\n{body}",
+        format.name()
+    );
+    if !snapshot.ends_with('\n') {
+        snapshot.push('\n');
+    }
+    snapshot
+}
+
+/// A directory of snapshots.
+#[derive(Clone, Debug)]
+pub struct Snapshots {
+    dir: PathBuf,
+    update: bool,
+    ci: bool,
+}
+
+impl Snapshots {
+    /// Snapshots saved in `dir`, as `{name}.snap` files.
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self {
+            dir: dir.into(),
+            update: std::env::var_os(UPDATE_VARIABLE).is_some_and(|value| value == "1"),
+            ci: std::env::var_os("CI").is_some(),
+        }
+    }
+
+    /// Whether new results replace the saved snapshots, instead of failing.
+    pub fn update(mut self, update: bool) -> Self {
+        self.update = update;
+        self
+    }
+
+    /// Call `function`, and compare its result with the snapshot `name`.
+    ///
+    /// # Panics
+    ///
+    /// When the snapshot is different, with a diff.
+    #[track_caller]
+    pub fn assert_call(
+        &self,
+        name: &str,
+        plugin: &mut Plugin,
+        function: &str,
+        args: &[&[u8]],
+        format: Format,
+    ) {
+        let result = plugin.call(function, args);
+        if let Err(message) = self.check(name, &render(function, args, &result, format)) {
+            panic!("{message}");
+        }
+    }
+
+    /// Compare `snapshot` with the saved snapshot `name`, save it if needed, and
+    /// return the reason of the failure, if any.
+    pub fn check(&self, name: &str, snapshot: &str) -> Result<(), String> {
+        let path = self.dir.join(format!("{name}.snap"));
+        let new_path = self.dir.join(format!("{name}.snap.new"));
+        let saved = match std::fs::read_to_string(&path) {
+            Ok(saved) => Some(saved.replace("\r\n", "\n")),
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => None,
+            Err(err) => return Err(format!("cannot read {}: {err}", path.display())),
+        };
+        match saved {
+            Some(saved) if saved == snapshot => {
+                remove(&new_path)?;
+                Ok(())
+            }
+            Some(_) | None if self.update => {
+                write(&path, snapshot)?;
+                remove(&new_path)
+            }
+            None if !self.ci => write(&path, snapshot),
+            None => {
+                write(&new_path, snapshot)?;
+                Err(format!(
+                    "snapshot `{name}` is missing, the result was written to {}",
+                    new_path.display()
+                ))
+            }
+            Some(saved) => {
+                write(&new_path, snapshot)?;
+                Err(format!(
+                    "snapshot `{name}` changed (- saved, + new):\n{}\
+                     the new snapshot was written to {}, run the tests with \
+                     {UPDATE_VARIABLE}=1 to accept it",
+                    diff(&saved, snapshot),
+                    new_path.display()
+                ))
+            }
+        }
+    }
+}
+
+fn write(path: &Path, contents: &str) -> Result<(), String> {
+    let error = |err: std::io::Error| format!("cannot write {}: {err}", path.display());
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent).map_err(error)?;
+    }
+    std::fs::write(path, contents).map_err(error)
+}
+
+fn remove(path: &Path) -> Result<(), String> {
+    match std::fs::remove_file(path) {
+        Err(err) if err.kind() != std::io::ErrorKind::NotFound => {
+            Err(format!("cannot remove {}: {err}", path.display()))
+        }
+        _ => Ok(()),
+    }
+}
+
+/// The lines of `old` and `new`, prefixed by `-` if they were removed, `+` if they
+/// were added, and spaces if they did not change.
+pub fn diff(old: &str, new: &str) -> String {
+    let old: Vec<_> = old.lines().collect();
+    let new: Vec<_> = new.lines().collect();
+    // `common[i][j]` is the length of the longest common subsequence of `old[i..]`
+    // and `new[j..]`.
+    let mut common = vec![vec![0; new.len() + 1]; old.len() + 1];
+    for i in (0..old.len()).rev() {
+        for j in (0..new.len()).rev() {
+            common[i][j] = if old[i] == new[j] {
+                common[i + 1][j + 1] + 1
+            } else {
+                common[i + 1][j].max(common[i][j + 1])
+            };
+        }
+    }
+    let mut diff = String::new();
+    let (mut i, mut j) = (0, 0);
+    while i < old.len() || j < new.len() {
+        if i < old.len() && j < new.len() && old[i] == new[j] {
+            writeln!(diff, "  {}", old[i]).unwrap();
+            (i, j) = (i + 1, j + 1);
+        } else if j == new.len() || (i < old.len() && common[i + 1][j] >= common[i][j + 1]) {
+            writeln!(diff, "- {}", old[i]).unwrap();
+            i += 1;
+        } else {
+            writeln!(diff, "+ {}", new[j]).unwrap();
+            j += 1;
+        }
+    }
+    diff
+}
@@ -0,0 +1,91 @@
+mod common;
+
+use common::fixture;
+use std::time::Duration;
+use wasm_minimal_protocol_host::{
+    bench::{bench, Options, Report},
+    Plugin, PluginError,
+};
+
+fn options() -> Options {
+    Options {
+        iterations: 5,
+        warmup: 1,
+        ..Options::default()
+    }
+}
+
+#[test]
+fn measurements() {
+    let binary = fixture("examples");
+    let report = bench(&binary, "concatenate", &[b"hello", b"world"], &options()).unwrap();
+    assert_eq!((report.args_copied, report.result_copied), (10, 11));
+    assert_eq!(report.peak_memory, 65536);
+    assert!(report.fastest_call <= report.call && report.call <= report.slowest_call);
+    // The fuel only depends on the plugin and on the arguments.
+    assert!(report.fuel > 0);
+    let again = bench(&binary, "concatenate", &[b"hello", b"world"], &options()).unwrap();
+    assert_eq!(again.fuel, report.fuel);
+    let longer = bench(&binary, "concatenate", &[b"hello", b"world!"], &options()).unwrap();
+    assert!(longer.fuel >= report.fuel);
+
+    assert!(matches!(
+        bench(&binary, "will_panic", &[], &options()),
+        Err(PluginError::Trap(_))
+    ));
+}
+
+#[test]
+fn last_call() {
+    let mut plugin = Plugin::new(&fixture("hello")).unwrap();
+    plugin.call("double_it", &[b"abc"]).unwrap();
+    let stats = plugin.last_call();
+    assert_eq!((stats.args_copied, stats.result_copied), (3, 6));
+    assert_eq!(stats.memory, 65536);
+    // Measured even when the call fails.
+    plugin.call("returns_err", &[]).unwrap_err();
+    assert_eq!(plugin.last_call().result_copied, 16);
+    assert!(plugin.last_call().fuel > 0);
+}
+
+#[test]
+fn baselines() {
+    let report = Report {
+        instantiation: Duration::from_micros(100),
+        call: Duration::from_micros(2),
+        fastest_call: Duration::from_micros(1),
+        slowest_call: Duration::from_micros(9),
+        fuel: 1000,
+        args_copied: 10,
+        result_copied: 0,
+        peak_memory: 131072,
+    };
+    let baseline = report.to_baseline();
+    assert!(baseline.starts_with("instantiation_ns 100000\ncall_ns 2000\n"));
+    assert_eq!(Report::from_baseline(&baseline).unwrap(), report);
+    assert_eq!(
+        Report::from_baseline("fuel 1\n").unwrap_err(),
+        "missing measurement in the baseline: instantiation_ns"
+    );
+    assert_eq!(
+        Report::from_baseline("speed 1\n").unwrap_err(),
+        "unknown measurement in the baseline: speed"
+    );
+
+    let new = Report {
+        fuel: 1100,
+        result_copied: 4,
+        ..report.clone()
+    };
+    let comparison = new.compared_to(&report).to_string();
+    assert_eq!(
+        comparison,
+        "instantiation: 100.00µs (no change)\n\
+         call:          2.00µs (no change) (fastest 1.00µs, slowest 9.00µs)\n\
+         fuel:          1100 (+10.0%)\n\
+         arguments:     10 bytes copied to the plugin (no change)\n\
+         result:        4 bytes copied from the plugin (was 0)\n\
+         peak memory:   2 pages (131072 bytes) (no change)"
+    );
+    assert!(!report.to_string().contains("change"));
+}
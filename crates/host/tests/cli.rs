@@ -0,0 +1,400 @@
+mod common;
+
+use common::{fixture, wat};
+use std::{
+    io::Write,
+    path::PathBuf,
+    process::{Command, Stdio},
+};
+
+/// A new empty directory, with the given fixtures written as `{name}.wasm`.
+fn temp_dir(test: &str, fixtures: &[&str]) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("wmp-{test}-{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    for name in fixtures {
+        std::fs::write(dir.join(format!("{name}.wasm")), fixture(name)).unwrap();
+    }
+    dir
+}
+
+fn wmp(args: &[&str], dir: &PathBuf) -> std::process::Output {
+    Command::new(env!("CARGO_BIN_EXE_wmp"))
+        .args(args)
+        .current_dir(dir)
+        .output()
+        .unwrap()
+}
+
+#[test]
+fn call() {
+    let dir = temp_dir("call", &["hello"]);
+    std::fs::write(dir.join("s2.txt"), "s2").unwrap();
+    let output = wmp(
+        &[
+            "call",
+            "hello.wasm",
+            "shuffle",
+            "--arg-hex",
+            "7331",
+            "--arg-file",
+            "s2.txt",
+            "--arg",
+            "s3",
+        ],
+        &dir,
+    );
+    assert!(output.status.success());
+    assert_eq!(output.stdout, b"s3-s1-s2");
+
+    let output = wmp(
+        &[
+            "call",
+            "hello.wasm",
+            "double_it",
+            "--arg",
+            "ab",
+            "-f",
+            "hex",
+        ],
+        &dir,
+    );
+    assert_eq!(output.stdout, b"61626162\n");
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn json() {
+    let dir = temp_dir("json", &["hello"]);
+    // {"a": [1, -2.5, h'00ff'], "b": "c\n"}
+    let cbor = "a2 6161 83 01 f9c100 4200ff 6162 62630a";
+    let output = wmp(
+        &[
+            "call",
+            "hello.wasm",
+            "concatenate",
+            "--arg-hex",
+            cbor,
+            "--arg",
+            "",
+            "-f",
+            "json",
+        ],
+        &dir,
+    );
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        "{\n  \"a\": [\n    1,\n    -2.5,\n    \"00ff\"\n  ],\n  \"b\": \"c\\n\"\n}\n"
+    );
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn exit_status() {
+    let dir = temp_dir("exit-status", &["hello"]);
+    std::fs::write(dir.join("no_memory.wasm"), wat("(module)")).unwrap();
+    for (args, status) in [
+        (&["hello.wasm", "hello"][..], 0),
+        (&["hello.wasm", "returns_err"], 1),
+        (&["hello.wasm", "will_panic"], 2),
+        (&["hello.wasm", "bad_code"], 3),
+        (&["hello.wasm", "concatenate"], 3),
+        (&["no_memory.wasm", "hello"], 3),
+    ] {
+        let output = wmp(&[&["call"], args].concat(), &dir);
+        assert_eq!(output.status.code(), Some(status), "{args:?}");
+    }
+    let output = wmp(&["call", "hello.wasm", "returns_err"], &dir);
+    assert_eq!(
+        String::from_utf8(output.stderr).unwrap(),
+        "error: plugin errored with: This is an `Err`\n"
+    );
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn limits() {
+    let dir = temp_dir("limits", &["limits"]);
+    for (args, status, stderr) in [
+        (
+            &["spin", "--max-fuel", "1000"][..],
+            4,
+            "error: plugin ran out of fuel (the limit is 1000)
+",
+        ),
+        (
+            &["grow", "--max-memory", "1MiB"],
+            4,
+            "error: plugin tried to use more memory than the limit of 1048576 bytes
+",
+        ),
+        (&["grow", "--max-memory", "2MiB"], 0, ""),
+        (
+            &["grow", "--max-memory", "2MB"],
+            2,
+            "error: invalid value '2MB' for '--max-memory <SIZE>': \
+             unknown unit \"MB\", expected KiB, MiB or GiB\n\n\
+             For more information, try '--help'.\n",
+        ),
+    ] {
+        let output = wmp(&[&["call", "limits.wasm"], args].concat(), &dir);
+        assert_eq!(output.status.code(), Some(status), "{args:?}");
+        assert_eq!(
+            String::from_utf8(output.stderr).unwrap(),
+            stderr,
+            "{args:?}"
+        );
+    }
+
+    std::fs::write(dir.join("suite.txt"), "work => ok\nspin => trap\n").unwrap();
+    let output = wmp(
+        &["test", "limits.wasm", "suite.txt", "--max-fuel", "10000"],
+        &dir,
+    );
+    assert_eq!(output.status.code(), Some(1));
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        "suite.txt: line 2: spin: plugin ran out of fuel (the limit is 10000)\n\
+         1 passed, 1 failed\n"
+    );
+
+    // Fuzzing and checking the determinism have a fuel limit by default.
+    let output = wmp(
+        &["fuzz", "limits.wasm", "--function", "spin", "--runs", "1"],
+        &dir,
+    );
+    assert_eq!(output.status.code(), Some(1));
+    assert!(String::from_utf8(output.stdout).unwrap().starts_with(
+        "spin: plugin ran out of fuel (the limit is 100000000)\n  \
+         saved to fuzz-findings/spin-1.txt\n"
+    ));
+    let output = wmp(
+        &[
+            "check-determinism",
+            "limits.wasm",
+            "--function",
+            "spin,work",
+            "--calls",
+            "1",
+        ],
+        &dir,
+    );
+    assert_eq!(output.status.code(), Some(1));
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        "spin(): plugin ran out of fuel (the limit is 100000000)\n\
+         1 call over the limits\n\
+         0 divergent results\n"
+    );
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn strict() {
+    let dir = temp_dir("strict", &["violations", "hello"]);
+    let output = wmp(&["call", "violations.wasm", "result_twice"], &dir);
+    assert_eq!(output.status.code(), Some(0));
+    assert_eq!(output.stdout, b"second");
+    assert_eq!(
+        String::from_utf8(output.stderr).unwrap(),
+        "warning: plugin did not respect the protocol: `result_twice` sent a result more \
+         than once (host calls: send_result_to_host(0x0, 5), send_result_to_host(0x6, 6))\n"
+    );
+
+    let output = wmp(&["call", "violations.wasm", "no_result", "--strict"], &dir);
+    assert_eq!(output.status.code(), Some(3));
+    assert_eq!(
+        String::from_utf8(output.stderr).unwrap(),
+        "error: plugin did not respect the protocol: `no_result` returned without sending \
+         a result (host calls: none)\n"
+    );
+
+    // The violation that is the error is not repeated as a warning.
+    let output = wmp(&["call", "hello.wasm", "bad_code"], &dir);
+    assert_eq!(
+        String::from_utf8(output.stderr).unwrap(),
+        "warning: plugin did not respect the protocol: `bad_code` returned without \
+         sending a result (host calls: none)\n\
+         error: plugin did not respect the protocol: `bad_code` returned 2, instead of 0 or \
+         1 (host calls: none)\n"
+    );
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn repl() {
+    let dir = temp_dir("repl", &["hello"]);
+    std::fs::write(dir.join("s3.txt"), "s3").unwrap();
+    let mut child = Command::new(env!("CARGO_BIN_EXE_wmp"))
+        .args(["repl", "hello.wasm"])
+        .current_dir(&dir)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .unwrap();
+    child
+        .stdin
+        .take()
+        .unwrap()
+        .write_all(
+            b"shuffle \"s 1\" 0x7332 @s3.txt\n\
+              count\n\
+              count\n\
+              :format json\n\
+              concatenate cbor:{\"a\": [true, null]} \"\"\n\
+              will_panic\n",
+        )
+        .unwrap();
+    let output = child.wait_with_output().unwrap();
+    assert!(output.status.success());
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(stdout.contains("  shuffle (3 arguments)\n"));
+    assert!(stdout.contains("\ns3-s 1-s2\n(9 bytes, "));
+    // The instance is kept between calls.
+    assert!(stdout.contains("\n\u{0}\n(1 bytes, "));
+    assert!(stdout.contains("\n\u{1}\n(1 bytes, "));
+    assert!(stdout.contains("\n{\n  \"a\": [\n    true,\n    null\n  ]\n}\n"));
+    assert!(stdout.contains("\nerror: plugin panicked: "));
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn test() {
+    let dir = temp_dir("test", &["hello", "examples"]);
+    let core = concat!(env!("CARGO_MANIFEST_DIR"), "/conformance/core.txt");
+    let output = wmp(&["test", "examples.wasm", core], &dir);
+    assert_eq!(output.status.code(), Some(0));
+    assert_eq!(output.stdout, b"10 passed, 0 failed\n");
+
+    // `concatenate` has no separator in this fixture, and `returns_ok` is missing.
+    let output = wmp(&["test", "hello.wasm", core], &dir);
+    assert_eq!(output.status.code(), Some(1));
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(stdout
+        .contains("core.txt: line 7: concatenate: expected \"hello*world\", got \"helloworld\"\n"));
+    assert!(stdout.ends_with("7 passed, 3 failed\n"));
+
+    std::fs::write(dir.join("bad.txt"), "hello").unwrap();
+    let output = wmp(&["test", "examples.wasm", "bad.txt"], &dir);
+    assert_eq!(output.status.code(), Some(3));
+    assert_eq!(
+        output.stderr,
+        b"error: bad.txt: line 1: missing `=>` and the expected result\n"
+    );
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn fuzz() {
+    let dir = temp_dir("fuzz", &["fuzz", "examples"]);
+    let args = ["--runs", "200", "--seed", "1", "--max-output", "64"];
+    let output = wmp(
+        &[
+            &["fuzz", "fuzz.wasm", "--function", "parse,counter"],
+            &args[..],
+        ]
+        .concat(),
+        &dir,
+    );
+    assert_eq!(output.status.code(), Some(1));
+    assert_eq!(output.stderr, b"fuzzing with seed 1\n");
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(stdout.starts_with(
+        "counter: the same call gave 0x00, then 0x01\n  saved to fuzz-findings/counter-1.txt\n\
+         parse: plugin panicked: "
+    ));
+    assert!(stdout.ends_with("  saved to fuzz-findings/parse-1.txt\n2 problems found\n"));
+
+    // The saved problem can be reproduced.
+    let output = wmp(&["test", "fuzz.wasm", "fuzz-findings/parse-1.txt"], &dir);
+    assert_eq!(output.status.code(), Some(1));
+
+    // Problems are not overwritten.
+    wmp(
+        &[&["fuzz", "fuzz.wasm", "--function", "parse"], &args[..]].concat(),
+        &dir,
+    );
+    assert!(dir.join("fuzz-findings/parse-2.txt").exists());
+
+    let output = wmp(&["fuzz", "examples.wasm", "--runs", "100"], &dir);
+    // `will_panic` always panics.
+    assert!(String::from_utf8(output.stdout)
+        .unwrap()
+        .ends_with("1 problem found\n"));
+
+    let output = wmp(&["fuzz", "fuzz.wasm", "--function", "missing"], &dir);
+    assert_eq!(output.status.code(), Some(3));
+    assert_eq!(
+        output.stderr,
+        b"error: plugin does not contain a function called missing\n"
+    );
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn check_determinism() {
+    let dir = temp_dir("check-determinism", &["determinism", "examples"]);
+    std::fs::write(dir.join("suite.txt"), "store x => ok\npeek => ok\n").unwrap();
+    let output = wmp(
+        &[
+            "check-determinism",
+            "determinism.wasm",
+            "--function",
+            "peek,store,echo",
+            "--suite",
+            "suite.txt",
+            "--calls",
+            "0",
+            "--seed",
+            "1",
+        ],
+        &dir,
+    );
+    assert_eq!(output.status.code(), Some(1));
+    assert_eq!(output.stderr, b"checking 2 calls with seed 1\n");
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(stdout.starts_with(
+        "peek(): \"\" on a new instance, but \"x\" after the previous calls (1 calls, \
+         the last one to store)\n"
+    ));
+    assert!(stdout.ends_with(" divergent results\n"));
+
+    let output = wmp(&["check-determinism", "examples.wasm"], &dir);
+    assert_eq!(output.status.code(), Some(0));
+    assert_eq!(output.stdout, b"0 divergent results\n");
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn bench() {
+    let dir = temp_dir("bench", &["examples"]);
+    let bench = [
+        "bench",
+        "examples.wasm",
+        "double_it",
+        "--arg",
+        "abc",
+        "-n",
+        "3",
+    ];
+    let output = wmp(
+        &[&bench[..], &["--save-baseline", "base.txt"]].concat(),
+        &dir,
+    );
+    assert_eq!(output.status.code(), Some(0));
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(stdout.contains("\narguments:     3 bytes copied to the plugin\n"));
+    assert!(stdout.contains("\nresult:        6 bytes copied from the plugin\n"));
+    assert!(std::fs::read_to_string(dir.join("base.txt"))
+        .unwrap()
+        .contains("\nargs_copied 3\n"));
+
+    let output = wmp(&[&bench[..], &["--baseline", "base.txt"]].concat(), &dir);
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(stdout.contains("\nfuel:          "));
+    assert!(stdout.contains(" bytes copied to the plugin (no change)\n"));
+
+    let output = wmp(&["bench", "examples.wasm", "will_panic"], &dir);
+    assert_eq!(output.status.code(), Some(3));
+    std::fs::remove_dir_all(dir).unwrap();
+}
@@ -0,0 +1,39 @@
+//! Helpers shared by the integration tests.
+
+// Each test binary only uses some of the helpers.
+#![allow(dead_code)]
+
+pub fn fixture(name: &str) -> Vec<u8> {
+    wat(&std::fs::read_to_string(fixture_path(name, "wat")).unwrap())
+}
+
+pub fn fixture_path(name: &str, extension: &str) -> std::path::PathBuf {
+    format!(
+        "{}/tests/fixtures/{name}.{extension}",
+        env!("CARGO_MANIFEST_DIR")
+    )
+    .into()
+}
+
+pub fn wat(source: &str) -> Vec<u8> {
+    let buffer = wast::parser::ParseBuffer::new(source).unwrap();
+    let mut wat: wast::Wat = wast::parser::parse(&buffer).unwrap();
+    wat.encode().unwrap()
+}
+
+/// A precompiled example of `tests/examples`.
+pub fn example(name: &str) -> Vec<u8> {
+    let path = format!("{}/tests/examples/{name}.wasm", env!("CARGO_MANIFEST_DIR"));
+    std::fs::read(&path).unwrap_or_else(|err| panic!("cannot read {path}: {err}"))
+}
+
+/// The example of `examples/hello_{language}`, built by the tests of the macro.
+pub fn built_example(language: &str) -> Vec<u8> {
+    let path = format!(
+        "{}/../../examples/hello_{language}/hello.wasm",
+        env!("CARGO_MANIFEST_DIR")
+    );
+    std::fs::read(&path).unwrap_or_else(|err| {
+        panic!("cannot read {path}: {err}. Run the tests of the macro first, to build it")
+    })
+}
@@ -0,0 +1,134 @@
+mod common;
+
+use common::{built_example, example, fixture};
+use wasm_minimal_protocol_host::conformance::{Status, Suite};
+
+fn suite(name: &str) -> Suite {
+    Suite::load(format!(
+        "{}/conformance/{name}.txt",
+        env!("CARGO_MANIFEST_DIR")
+    ))
+    .unwrap()
+}
+
+#[test]
+fn fixture_passes() {
+    assert_eq!(suite("core").run(&fixture("examples")), []);
+}
+
+#[test]
+fn rust_examples() {
+    for name in ["hello_rust", "hello_rust_wasi"] {
+        let binary = example(name);
+        assert_eq!(suite("core").run(&binary), [], "{name}");
+        assert_eq!(suite("rust").run(&binary), [], "{name}");
+    }
+}
+
+#[test]
+#[ignore = "needs examples/hello_c/hello.wasm, built by the tests of the macro"]
+fn c_example() {
+    assert_eq!(suite("core").run(&built_example("c")), []);
+}
+
+#[test]
+#[ignore = "needs examples/hello_go/hello.wasm, built by the tests of the macro"]
+fn go_example() {
+    assert_eq!(suite("core").run(&built_example("go")), []);
+}
+
+#[test]
+#[ignore = "needs examples/hello_rust/hello.wasm, built by the tests of the macro"]
+fn rust_example() {
+    let binary = built_example("rust");
+    assert_eq!(suite("core").run(&binary), []);
+    assert_eq!(suite("rust").run(&binary), []);
+}
+
+#[test]
+#[ignore = "needs examples/hello_zig/hello.wasm, built by the tests of the macro"]
+fn zig_example() {
+    assert_eq!(suite("core").run(&built_example("zig")), []);
+}
+
+#[test]
+fn failures() {
+    let suite = Suite::parse(
+        "# comment\n\
+         \n\
+         hello => ok hello\n\
+         returns_err => ok\n\
+         returns_err => error\n\
+         invalid_err => error 0xfffe\n\
+         count => ok 0x00\n\
+         count => ok 0x00\n\
+         missing => ok\n\
+         returns_err => returns\n\
+         will_panic => returns\n",
+    )
+    .unwrap();
+    let failures: Vec<_> = suite
+        .run(&fixture("hello"))
+        .iter()
+        .map(ToString::to_string)
+        .collect();
+    assert_eq!(
+        failures,
+        [
+            "line 3: hello: expected \"hello\", got \"Hello from wasm!!!\"",
+            "line 4: returns_err: expected ok, got error",
+            "line 9: missing: plugin does not contain a function called missing",
+            "line 11: will_panic: expected returns, got trap",
+        ]
+    );
+}
+
+#[test]
+fn violations() {
+    // Typst accepts these calls, but not the suites.
+    let suite = Suite::parse("echo a => ok a\nno_result => ok\nresult_twice => returns\n").unwrap();
+    let failures: Vec<_> = suite
+        .run(&fixture("violations"))
+        .iter()
+        .map(ToString::to_string)
+        .collect();
+    assert_eq!(
+        failures,
+        [
+            "line 2: no_result: plugin did not respect the protocol: `no_result` returned \
+             without sending a result (host calls: none)",
+            "line 3: result_twice: plugin did not respect the protocol: `result_twice` sent \
+             a result more than once (host calls: send_result_to_host(0x0, 5), \
+             send_result_to_host(0x6, 6))",
+        ]
+    );
+}
+
+#[test]
+fn syntax() {
+    let suite = Suite::parse("f a \"b c\" 0x00 cbor:[1] => error \"=> x\"").unwrap();
+    let case = &suite.cases[0];
+    assert_eq!(case.args, [&b"a"[..], b"b c", &[0], &[0x81, 0x01]]);
+    assert_eq!(
+        (case.status, case.output.as_deref()),
+        (Status::Error, Some(&b"=> x"[..]))
+    );
+    assert_eq!(
+        Suite::parse("f =>x => trap").unwrap().cases[0].args,
+        [b"=>x"]
+    );
+
+    for (text, error) in [
+        ("f a", "line 1: missing `=>` and the expected result"),
+        (
+            "f => fine",
+            "line 1: expected ok, error, returns or trap, found \"fine\"",
+        ),
+        ("f => trap x", "line 1: `trap` has no result"),
+        ("f => returns x", "line 1: `returns` has no result"),
+        ("f => ok a b", "line 1: the result must be a single value"),
+        ("f \"a => ok", "line 1: unterminated string"),
+    ] {
+        assert_eq!(Suite::parse(text).unwrap_err(), error);
+    }
+}
@@ -0,0 +1,101 @@
+mod common;
+
+use common::fixture;
+use wasm_minimal_protocol_host::determinism::{check, generate_calls, Call, Scenario};
+use wasm_minimal_protocol_host::{Function, Limits, Plugin, PluginError};
+
+fn call(function: &str, args: &[&[u8]]) -> Call {
+    Call {
+        function: function.to_owned(),
+        args: args.iter().map(|arg| arg.to_vec()).collect(),
+    }
+}
+
+#[test]
+fn divergences() {
+    let calls = [
+        call("echo", &[b"a"]),
+        call("peek", &[]),
+        call("store", &[b"x"]),
+        call("peek", &[]),
+        call("counter", &[]),
+        call("will_panic", &[]),
+    ];
+    let report = check(&fixture("determinism"), &calls, 0, Limits::default()).unwrap();
+    assert_eq!(report.over_limits, []);
+    let divergences = report.divergences;
+    let summary: Vec<_> = divergences
+        .iter()
+        .map(|divergence| (divergence.call.function.as_str(), divergence.scenario))
+        .collect();
+    assert_eq!(
+        summary,
+        [
+            // `counter` is called once in the sequences.
+            ("counter", Scenario::SameInstance),
+            ("peek", Scenario::InOrder),
+            ("peek", Scenario::Reversed),
+            ("peek", Scenario::Shuffled),
+        ]
+    );
+    assert_eq!(
+        divergences[0].to_string(),
+        "counter(): 0x00 on a new instance, but 0x01 when called twice on the same \
+         instance (1 calls, the last one to counter)"
+    );
+    assert_eq!(
+        divergences[1].to_string(),
+        "peek(): \"\" on a new instance, but \"x\" after the previous calls (3 calls, \
+         the last one to store)"
+    );
+}
+
+#[test]
+fn pure() {
+    let binary = fixture("examples");
+    let functions = Plugin::new(&binary).unwrap().functions();
+    let calls = generate_calls(&functions, 10, 64, 0);
+    assert_eq!(calls.len(), 10 * functions.len());
+    assert!(calls.iter().all(|call| {
+        let function = functions.iter().find(|f| f.name == call.function).unwrap();
+        call.args.len() == function.arity && call.args.iter().all(|arg| arg.len() <= 64)
+    }));
+    assert_eq!(
+        check(&binary, &calls, 0, Limits::default()).unwrap(),
+        Default::default()
+    );
+    // The calls only depend on the seed.
+    assert_eq!(generate_calls(&functions, 10, 64, 0), calls);
+}
+
+#[test]
+fn limits() {
+    let limits = Limits {
+        fuel: Some(1_000_000),
+        memory: None,
+    };
+    let calls = [call("spin", &[]), call("work", &[]), call("spin", &[])];
+    let report = check(&fixture("limits"), &calls, 0, limits).unwrap();
+    // The call that never returns is reported once, and left out of the sequences.
+    assert_eq!(
+        report.over_limits,
+        [(call("spin", &[]), PluginError::OutOfFuel(1_000_000))]
+    );
+    assert_eq!(report.divergences, []);
+}
+
+#[test]
+fn display() {
+    let echo = Function {
+        name: "echo".to_owned(),
+        arity: 1,
+    };
+    assert_eq!(
+        generate_calls(&[echo], 1, 0, 0)[0].to_string(),
+        "echo(\"\")"
+    );
+    assert_eq!(
+        call("concatenate", &[b"a\"", &[0; 40]]).to_string(),
+        "concatenate(\"a\\\"\", <40 bytes>)"
+    );
+}
@@ -0,0 +1,41 @@
+;; A plugin whose functions depend on the previous calls in different ways.
+(module
+  (import "typst_env" "wasm_minimal_protocol_send_result_to_host" (func $send_result_to_host (param i32 i32)))
+  (import "typst_env" "wasm_minimal_protocol_write_args_to_buffer" (func $write_args_to_buffer (param i32)))
+  (memory (export "memory") 1)
+  (global $calls (mut i32) (i32.const 0))
+  (global $last (mut i32) (i32.const 0))
+
+  ;; Arguments are written at this offset.
+  (global $args i32 (i32.const 1024))
+  ;; Buffer kept between calls.
+  (global $saved i32 (i32.const 4096))
+
+  ;; Returns its argument.
+  (func (export "echo") (param i32) (result i32)
+    (call $write_args_to_buffer (global.get $args))
+    (call $send_result_to_host (global.get $args) (local.get 0))
+    (i32.const 0))
+
+  ;; Returns the number of previous calls, as a byte.
+  (func (export "counter") (result i32)
+    (i32.store8 (i32.const 0) (global.get $calls))
+    (global.set $calls (i32.add (global.get $calls) (i32.const 1)))
+    (call $send_result_to_host (i32.const 0) (i32.const 1))
+    (i32.const 0))
+
+  ;; Saves its argument, and returns nothing.
+  (func (export "store") (param i32) (result i32)
+    (call $write_args_to_buffer (global.get $saved))
+    (global.set $last (local.get 0))
+    (call $send_result_to_host (i32.const 0) (i32.const 0))
+    (i32.const 0))
+
+  ;; Returns the argument saved by `store`, like a read of uninitialised memory.
+  (func (export "peek") (result i32)
+    (call $send_result_to_host (global.get $saved) (global.get $last))
+    (i32.const 0))
+
+  (func (export "will_panic") (result i32)
+    (unreachable))
+)
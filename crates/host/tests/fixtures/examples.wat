@@ -0,0 +1,91 @@
+;; Behaves like the plugins in `examples/` (except for `complex_data`), to run the
+;; conformance suites without a compiler for wasm32.
+(module
+  (import "typst_env" "wasm_minimal_protocol_send_result_to_host" (func $send_result_to_host (param i32 i32)))
+  (import "typst_env" "wasm_minimal_protocol_write_args_to_buffer" (func $write_args_to_buffer (param i32)))
+  (memory (export "memory") 1)
+  (data (i32.const 0) "Hello from wasm!!!")
+  (data (i32.const 32) "This is an `Ok`")
+  (data (i32.const 48) "This is an `Err`")
+
+  ;; Arguments are written at this offset.
+  (global $args i32 (i32.const 1024))
+
+  (func (export "hello") (result i32)
+    (call $send_result_to_host (i32.const 0) (i32.const 18))
+    (i32.const 0))
+
+  (func (export "double_it") (param i32) (result i32)
+    (call $write_args_to_buffer (global.get $args))
+    (memory.copy
+      (i32.add (global.get $args) (local.get 0))
+      (global.get $args)
+      (local.get 0))
+    (call $send_result_to_host (global.get $args) (i32.mul (local.get 0) (i32.const 2)))
+    (i32.const 0))
+
+  ;; a*b
+  (func (export "concatenate") (param i32 i32) (result i32)
+    (local $b i32)
+    (local $out i32)
+    (call $write_args_to_buffer (global.get $args))
+    (local.set $b (i32.add (global.get $args) (local.get 0)))
+    (local.set $out (i32.add (local.get $b) (local.get 1)))
+    (memory.copy (local.get $out) (global.get $args) (local.get 0))
+    (i32.store8 (i32.add (local.get $out) (local.get 0)) (i32.const 42))
+    (memory.copy
+      (i32.add (local.get $out) (i32.add (local.get 0) (i32.const 1)))
+      (local.get $b)
+      (local.get 1))
+    (call $send_result_to_host
+      (local.get $out)
+      (i32.add (i32.add (local.get 0) (local.get 1)) (i32.const 1)))
+    (i32.const 0))
+
+  ;; c-a-b
+  (func (export "shuffle") (param i32 i32 i32) (result i32)
+    (local $b i32)
+    (local $c i32)
+    (local $out i32)
+    (call $write_args_to_buffer (global.get $args))
+    (local.set $b (i32.add (global.get $args) (local.get 0)))
+    (local.set $c (i32.add (local.get $b) (local.get 1)))
+    (local.set $out (i32.add (local.get $c) (local.get 2)))
+    (memory.copy (local.get $out) (local.get $c) (local.get 2))
+    (i32.store8 (i32.add (local.get $out) (local.get 2)) (i32.const 45))
+    (memory.copy
+      (i32.add (local.get $out) (i32.add (local.get 2) (i32.const 1)))
+      (global.get $args)
+      (local.get 0))
+    (i32.store8
+      (i32.add (local.get $out) (i32.add (local.get 2) (i32.add (local.get 0) (i32.const 1))))
+      (i32.const 45))
+    (memory.copy
+      (i32.add (local.get $out) (i32.add (local.get 2) (i32.add (local.get 0) (i32.const 2))))
+      (local.get $b)
+      (local.get 1))
+    (call $send_result_to_host
+      (local.get $out)
+      (i32.add (i32.add (local.get 0) (local.get 1)) (i32.add (local.get 2) (i32.const 2))))
+    (i32.const 0))
+
+  (func (export "returns_ok") (result i32)
+    (call $send_result_to_host (i32.const 32) (i32.const 15))
+    (i32.const 0))
+
+  (func (export "returns_err") (result i32)
+    (call $send_result_to_host (i32.const 48) (i32.const 16))
+    (i32.const 1))
+
+  (func (export "will_panic") (result i32)
+    (unreachable))
+
+  (func $set_to_a (param i32) (result i32)
+    (call $write_args_to_buffer (global.get $args))
+    (memory.fill (global.get $args) (i32.const 97) (local.get 0))
+    (call $send_result_to_host (global.get $args) (local.get 0))
+    (i32.const 0))
+
+  (export "set_to_a" (func $set_to_a))
+  (export "set_to_a_reuse_buffer" (func $set_to_a))
+)
@@ -0,0 +1,49 @@
+;; A plugin with bugs that the fuzzer should find.
+(module
+  (import "typst_env" "wasm_minimal_protocol_send_result_to_host" (func $send_result_to_host (param i32 i32)))
+  (import "typst_env" "wasm_minimal_protocol_write_args_to_buffer" (func $write_args_to_buffer (param i32)))
+  (memory (export "memory") 1)
+  (global $calls (mut i32) (i32.const 0))
+
+  ;; Arguments are written at this offset.
+  (global $args i32 (i32.const 1024))
+
+  ;; Panics when the argument is longer than its buffer of 100 bytes.
+  (func (export "parse") (param i32) (result i32)
+    (if (i32.gt_u (local.get 0) (i32.const 100))
+      (then unreachable))
+    (call $write_args_to_buffer (global.get $args))
+    (call $send_result_to_host (i32.const 0) (i32.const 0))
+    (i32.const 0))
+
+  ;; Returns its argument four times.
+  (func (export "expand") (param i32) (result i32)
+    (local $i i32)
+    (call $write_args_to_buffer (global.get $args))
+    (loop $copy
+      (local.set $i (i32.add (local.get $i) (i32.const 1)))
+      (memory.copy
+        (i32.add (global.get $args) (i32.mul (local.get $i) (local.get 0)))
+        (global.get $args)
+        (local.get 0))
+      (br_if $copy (i32.lt_u (local.get $i) (i32.const 3))))
+    (call $send_result_to_host (global.get $args) (i32.mul (local.get 0) (i32.const 4)))
+    (i32.const 0))
+
+  ;; Returns the number of previous calls, as a byte.
+  (func (export "counter") (result i32)
+    (i32.store8 (i32.const 0) (global.get $calls))
+    (global.set $calls (i32.add (global.get $calls) (i32.const 1)))
+    (call $send_result_to_host (i32.const 0) (i32.const 1))
+    (i32.const 0))
+
+  ;; Fails without a first argument, with an empty error message.
+  (func (export "join") (param i32 i32) (result i32)
+    (if (i32.eqz (local.get 0))
+      (then
+        (call $send_result_to_host (i32.const 0) (i32.const 0))
+        (return (i32.const 1))))
+    (call $write_args_to_buffer (global.get $args))
+    (call $send_result_to_host (global.get $args) (i32.add (local.get 0) (local.get 1)))
+    (i32.const 0))
+)
@@ -0,0 +1,35 @@
+;; A plugin that uses a lot of fuel or memory.
+(module
+  (import "typst_env" "wasm_minimal_protocol_send_result_to_host" (func $send_result_to_host (param i32 i32)))
+  (memory (export "memory") 1)
+  (table 1 2 funcref)
+
+  ;; Never returns.
+  (func (export "spin") (result i32)
+    (loop $forever
+      (br $forever))
+    (i32.const 0))
+
+  ;; Loops 1000 times, and returns nothing.
+  (func (export "work") (result i32)
+    (local $i i32)
+    (loop $count
+      (local.set $i (i32.add (local.get $i) (i32.const 1)))
+      (br_if $count (i32.lt_u (local.get $i) (i32.const 1000))))
+    (call $send_result_to_host (i32.const 0) (i32.const 0))
+    (i32.const 0))
+
+  ;; Grows the memory by 16 pages, and returns the new number of pages as a byte.
+  (func (export "grow") (result i32)
+    (drop (memory.grow (i32.const 16)))
+    (i32.store8 (i32.const 0) (memory.size))
+    (call $send_result_to_host (i32.const 0) (i32.const 1))
+    (i32.const 0))
+
+  ;; Grows the table past its maximum, and returns the result of `table.grow` as a
+  ;; byte.
+  (func (export "grow_table") (result i32)
+    (i32.store8 (i32.const 0) (table.grow (ref.null func) (i32.const 16)))
+    (call $send_result_to_host (i32.const 0) (i32.const 1))
+    (i32.const 0))
+)
@@ -0,0 +1,38 @@
+;; A plugin whose functions break the rules of the protocol that typst does not
+;; check.
+(module
+  (import "typst_env" "wasm_minimal_protocol_send_result_to_host" (func $send_result_to_host (param i32 i32)))
+  (import "typst_env" "wasm_minimal_protocol_write_args_to_buffer" (func $write_args_to_buffer (param i32)))
+  (memory (export "memory") 1)
+  (data (i32.const 0) "first second")
+
+  ;; Arguments are written at this offset.
+  (global $args i32 (i32.const 1024))
+
+  ;; Returns its argument.
+  (func (export "echo") (param i32) (result i32)
+    (call $write_args_to_buffer (global.get $args))
+    (call $send_result_to_host (global.get $args) (local.get 0))
+    (i32.const 0))
+
+  (func (export "no_result") (result i32)
+    (i32.const 0))
+
+  ;; Typst keeps the last result: `second`.
+  (func (export "result_twice") (result i32)
+    (call $send_result_to_host (i32.const 0) (i32.const 5))
+    (call $send_result_to_host (i32.const 6) (i32.const 6))
+    (i32.const 0))
+
+  ;; The second time, nothing is written.
+  (func (export "args_twice") (param i32) (result i32)
+    (call $write_args_to_buffer (global.get $args))
+    (call $write_args_to_buffer (i32.const 2048))
+    (call $send_result_to_host (i32.const 2048) (local.get 0))
+    (i32.const 0))
+
+  (func (export "args_after_result") (param i32) (result i32)
+    (call $send_result_to_host (i32.const 0) (i32.const 5))
+    (call $write_args_to_buffer (global.get $args))
+    (i32.const 0))
+)
@@ -0,0 +1,141 @@
+mod common;
+
+use common::fixture;
+use wasm_minimal_protocol_host::{
+    conformance::Suite,
+    fuzz::{check, fuzz, split_input, Options, Problem},
+    protocol::Rule,
+    Function, Limits, Plugin, PluginError,
+};
+
+fn options() -> Options {
+    Options {
+        runs: 300,
+        seed: 1,
+        max_output: 64,
+        ..Options::default()
+    }
+}
+
+#[test]
+fn finds_and_minimises() {
+    let binary = fixture("fuzz");
+    let functions = Plugin::new(&binary).unwrap().functions();
+    let mut reported = Vec::new();
+    let findings = fuzz(&binary, &functions, &options(), |finding| {
+        reported.push(finding.clone())
+    });
+    assert_eq!(findings, reported);
+
+    let summary: Vec<_> = findings
+        .iter()
+        .map(|finding| (finding.function.as_str(), finding.args.clone()))
+        .collect();
+    assert_eq!(
+        summary,
+        [
+            ("counter", vec![]),
+            ("expand", vec![vec![b'a'; 17]]),
+            // The arguments are minimised one after the other.
+            ("join", vec![vec![b'a'; 64], vec![b'a']]),
+            ("parse", vec![vec![b'a'; 101]]),
+        ]
+    );
+    assert_eq!(
+        findings[0].problem.to_string(),
+        "the same call gave 0x00, then 0x01"
+    );
+    assert_eq!(findings[1].problem, Problem::LargeOutput(68));
+    assert!(matches!(
+        findings[3].problem,
+        Problem::Crash(PluginError::Trap(_))
+    ));
+
+    // The same seed finds the same problems.
+    assert_eq!(fuzz(&binary, &functions, &options(), |_| {}), findings);
+}
+
+#[test]
+fn reproduction() {
+    let binary = fixture("fuzz");
+    let parse = Function {
+        name: "parse".to_owned(),
+        arity: 1,
+    };
+    let finding = &fuzz(&binary, &[parse], &options(), |_| {})[0];
+    let suite = finding.to_suite();
+    assert!(suite.starts_with("# Found by `wmp fuzz`: plugin panicked: "));
+    assert!(suite.ends_with(&format!("\nparse \"{}\" => returns\n", "a".repeat(101))));
+    let failures = Suite::parse(&suite).unwrap().run(&binary);
+    assert_eq!(failures[0].message, "expected returns, got trap");
+}
+
+#[test]
+fn checks() {
+    let binary = fixture("fuzz");
+    let options = options();
+    // Errors returned by the plugin are fine.
+    assert_eq!(check(&binary, "join", &[vec![], vec![1]], &options), None);
+    assert_eq!(check(&binary, "join", &[vec![1], vec![]], &options), None);
+    assert_eq!(
+        check(&binary, "missing", &[], &options),
+        Some(Problem::Crash(PluginError::NoFunction(
+            "missing".to_owned()
+        )))
+    );
+}
+
+#[test]
+fn violations() {
+    let binary = fixture("violations");
+    let functions = Plugin::new(&binary).unwrap().functions();
+    let options = Options {
+        runs: 50,
+        ..Options::default()
+    };
+    let rules: Vec<_> = fuzz(&binary, &functions, &options, |_| {})
+        .into_iter()
+        .map(|finding| match finding.problem {
+            Problem::Crash(PluginError::ProtocolViolation(violation)) => violation.rule,
+            problem => panic!("{problem}"),
+        })
+        .collect();
+    assert_eq!(
+        rules,
+        [
+            Rule::ArgsAfterResult,
+            Rule::ArgsWrittenTwice,
+            Rule::NoResult,
+            Rule::ResultSentTwice,
+        ]
+    );
+}
+
+#[test]
+fn limits() {
+    let options = Options {
+        limits: Limits {
+            fuel: Some(1_000_000),
+            memory: Some(20 * 65536),
+        },
+        ..options()
+    };
+    let binary = fixture("limits");
+    assert_eq!(
+        check(&binary, "spin", &[], &options),
+        Some(Problem::Crash(PluginError::OutOfFuel(1_000_000)))
+    );
+    assert_eq!(
+        check(&binary, "grow", &[], &options),
+        Some(Problem::Crash(PluginError::MemoryLimit(20 * 65536)))
+    );
+    assert_eq!(check(&binary, "work", &[], &options), None);
+}
+
+#[test]
+fn split() {
+    assert_eq!(split_input(b"\x02abcd", 2), [&b"ab"[..], b"cd"]);
+    assert_eq!(split_input(b"\x09ab", 3), [&b"ab"[..], b"", b""]);
+    assert_eq!(split_input(b"ab", 1), [b"ab"]);
+    assert_eq!(split_input(b"ab", 0), Vec::<Vec<u8>>::new());
+}
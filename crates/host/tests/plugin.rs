@@ -0,0 +1,235 @@
+mod common;
+
+use common::{fixture, wat};
+use wasm_minimal_protocol_host::{
+    protocol::{HostCall, Rule, Violation},
+    Function, Limits, Plugin, PluginError,
+};
+
+#[test]
+fn calls() {
+    let mut plugin = Plugin::new(&fixture("hello")).unwrap();
+    assert_eq!(plugin.call("hello", &[]).unwrap(), b"Hello from wasm!!!");
+    assert_eq!(plugin.call("double_it", &[b"abc"]).unwrap(), b"abcabc");
+    assert_eq!(
+        plugin.call("concatenate", &[b"hello", b"world"]).unwrap(),
+        b"helloworld"
+    );
+    assert_eq!(
+        plugin.call("shuffle", &[b"s1", b"s2", b"s3"]).unwrap(),
+        b"s3-s1-s2"
+    );
+    assert_eq!(plugin.call("concatenate", &[b"", b""]).unwrap(), b"");
+}
+
+#[test]
+fn state_persists() {
+    let mut plugin = Plugin::new(&fixture("hello")).unwrap();
+    assert_eq!(plugin.call("count", &[]).unwrap(), [0]);
+    assert_eq!(plugin.call("count", &[]).unwrap(), [1]);
+}
+
+#[test]
+fn functions() {
+    let plugin = Plugin::new(&fixture("hello")).unwrap();
+    let functions = plugin.functions();
+    assert!(functions.contains(&Function {
+        name: "shuffle".to_owned(),
+        arity: 3
+    }));
+    assert!(!functions
+        .iter()
+        .any(|function| function.name == "not_protocol"));
+}
+
+#[test]
+fn errors() {
+    let mut plugin = Plugin::new(&fixture("hello")).unwrap();
+    let mut error = |name: &str, args: &[&[u8]]| plugin.call(name, args).unwrap_err();
+
+    let err = error("returns_err", &[]);
+    assert_eq!(err, PluginError::Errored("This is an `Err`".to_owned()));
+    assert_eq!(err.to_string(), "plugin errored with: This is an `Err`");
+    assert_eq!(
+        error("invalid_err", &[]),
+        PluginError::InvalidErrorMessage(vec![0xff, 0xfe])
+    );
+    assert!(matches!(error("will_panic", &[]), PluginError::Trap(_)));
+    assert_eq!(
+        error("bad_code", &[]).to_string(),
+        "plugin did not respect the protocol: `bad_code` returned 2, instead of 0 or 1 \
+         (host calls: none)"
+    );
+    assert_eq!(
+        error("out_of_bounds", &[]).to_string(),
+        "plugin tried to read out of bounds: pointer 0xfffa is out of bounds for read of length 16"
+    );
+    assert_eq!(
+        error("missing", &[]).to_string(),
+        "plugin does not contain a function called missing"
+    );
+    assert_eq!(
+        error("concatenate", &[b"a"]).to_string(),
+        "plugin function takes 2 arguments, but 1 was given"
+    );
+    assert_eq!(
+        error("not_protocol", &[b"a"]).to_string(),
+        "plugin function `not_protocol` has a parameter that is not a 32-bit integer"
+    );
+
+    // Errors do not break the instance.
+    assert_eq!(plugin.call("hello", &[]).unwrap(), b"Hello from wasm!!!");
+}
+
+#[test]
+fn violations() {
+    let mut plugin = Plugin::new(&fixture("violations")).unwrap();
+    let mut rules = |name: &str, args: &[&[u8]]| {
+        let result = plugin.call(name, args);
+        let rules: Vec<_> = plugin.violations().iter().map(|v| v.rule).collect();
+        (result, rules)
+    };
+    assert_eq!(rules("echo", &[b"a"]), (Ok(b"a".to_vec()), vec![]));
+    // Like in typst, the calls succeed.
+    assert_eq!(rules("no_result", &[]), (Ok(vec![]), vec![Rule::NoResult]));
+    assert_eq!(
+        rules("result_twice", &[]),
+        (Ok(b"second".to_vec()), vec![Rule::ResultSentTwice])
+    );
+    assert_eq!(
+        rules("args_twice", &[b"abc"]),
+        (Ok(vec![0; 3]), vec![Rule::ArgsWrittenTwice])
+    );
+    assert_eq!(
+        rules("args_after_result", &[b"abc"]),
+        (Ok(b"first".to_vec()), vec![Rule::ArgsAfterResult])
+    );
+
+    plugin.call("result_twice", &[]).unwrap();
+    assert_eq!(
+        plugin.violations(),
+        [Violation {
+            function: "result_twice".to_owned(),
+            rule: Rule::ResultSentTwice,
+            calls: vec![
+                HostCall::SendResult { ptr: 0, len: 5 },
+                HostCall::SendResult { ptr: 6, len: 6 },
+            ],
+        }]
+    );
+    assert_eq!(
+        plugin.violations()[0].to_string(),
+        "`result_twice` sent a result more than once \
+         (host calls: send_result_to_host(0x0, 5), send_result_to_host(0x6, 6))"
+    );
+
+    plugin.set_strict(true);
+    let err = plugin.call("args_twice", &[b"abc"]).unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "plugin did not respect the protocol: `args_twice` asked for its arguments more \
+         than once (host calls: write_args_to_buffer(0x400), write_args_to_buffer(0x800), \
+         send_result_to_host(0x800, 3))"
+    );
+    assert_eq!(plugin.call("echo", &[b"a"]).unwrap(), b"a");
+
+    // Errors that typst reports are violations too, with the calls.
+    let mut plugin = Plugin::new(&fixture("hello")).unwrap();
+    assert!(matches!(
+        plugin.call("out_of_bounds", &[]),
+        Err(PluginError::OutOfBounds { .. })
+    ));
+    assert_eq!(
+        plugin.violations()[0].to_string(),
+        "`out_of_bounds` gave an out of bounds pointer: 0xfffa, for a read of length 16 \
+         (host calls: send_result_to_host(0xfffa, 16))"
+    );
+    assert!(plugin.call("will_panic", &[]).is_err());
+    assert_eq!(plugin.violations(), []);
+}
+
+#[test]
+fn fuel_limit() {
+    let limits = Limits {
+        fuel: Some(100_000),
+        ..Limits::default()
+    };
+    let mut plugin = Plugin::with_limits(&fixture("limits"), limits).unwrap();
+    let err = plugin.call("spin", &[]).unwrap_err();
+    assert_eq!(err, PluginError::OutOfFuel(100_000));
+    assert_eq!(
+        err.to_string(),
+        "plugin ran out of fuel (the limit is 100000)"
+    );
+    assert_eq!(plugin.last_call().fuel, 100_000);
+
+    // The fuel is given again to each call.
+    for _ in 0..3 {
+        plugin.call("work", &[]).unwrap();
+    }
+
+    let mut plugin = Plugin::new(&fixture("limits")).unwrap();
+    plugin.call("work", &[]).unwrap();
+    let used = plugin.last_call().fuel;
+    let with_fuel = |fuel| {
+        let limits = Limits {
+            fuel: Some(fuel),
+            ..Limits::default()
+        };
+        Plugin::with_limits(&fixture("limits"), limits)
+            .unwrap()
+            .call("work", &[])
+    };
+    assert_eq!(with_fuel(used), Ok(Vec::new()));
+    assert_eq!(with_fuel(used - 1), Err(PluginError::OutOfFuel(used - 1)));
+}
+
+#[test]
+fn memory_limit() {
+    let limits = |pages: usize| Limits {
+        memory: Some(pages * 65536),
+        ..Limits::default()
+    };
+    let mut plugin = Plugin::with_limits(&fixture("limits"), limits(40)).unwrap();
+    assert_eq!(plugin.call("grow", &[]).unwrap(), [17]);
+    assert_eq!(plugin.call("grow", &[]).unwrap(), [33]);
+    let err = plugin.call("grow", &[]).unwrap_err();
+    assert_eq!(err, PluginError::MemoryLimit(40 * 65536));
+    assert_eq!(
+        err.to_string(),
+        "plugin tried to use more memory than the limit of 2621440 bytes"
+    );
+
+    // Without a limit, the memory grows like in typst.
+    let mut plugin = Plugin::new(&fixture("limits")).unwrap();
+    for _ in 0..3 {
+        plugin.call("grow", &[]).unwrap();
+    }
+    assert_eq!(plugin.memory().len(), 49 * 65536);
+    // Growing past the maximum of the module fails with -1, without a trap.
+    assert_eq!(plugin.call("grow_table", &[]).unwrap(), [0xff]);
+
+    // The initial memory is over the limit.
+    assert_eq!(
+        Plugin::with_limits(&fixture("limits"), limits(0)).err(),
+        Some(PluginError::MemoryLimit(0))
+    );
+}
+
+#[test]
+fn load_errors() {
+    assert!(matches!(
+        Plugin::new(b"not wasm"),
+        Err(PluginError::Load(_))
+    ));
+    assert_eq!(
+        Plugin::new(&wat("(module)")).err(),
+        Some(PluginError::NoMemory)
+    );
+    assert!(matches!(
+        Plugin::new(&wat(
+            r#"(module (import "wasi_snapshot_preview1" "proc_exit" (func (param i32))))"#
+        )),
+        Err(PluginError::Instantiate(_))
+    ));
+}
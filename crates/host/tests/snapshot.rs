@@ -0,0 +1,129 @@
+mod common;
+
+use common::{example, fixture};
+use wasm_minimal_protocol_host::{
+    format::hex_dump,
+    snapshot::{diff, render, Format, Snapshots},
+    Plugin,
+};
+
+/// The snapshots of the results of an example.
+fn snapshots(example: &str) -> Snapshots {
+    Snapshots::new(format!(
+        "{}/tests/snapshots/{example}",
+        env!("CARGO_MANIFEST_DIR")
+    ))
+}
+
+/// Check the results that the `hello.typ` of the Rust example shows.
+fn check_rust_example(name: &str) {
+    let snapshots = snapshots(name);
+    let mut plugin = Plugin::new(&example(name)).unwrap();
+    let mut check = |name, function, args: &[&[u8]], format| {
+        snapshots.assert_call(name, &mut plugin, function, args, format)
+    };
+    check("hello", "hello", &[], Format::Utf8);
+    check("double_it", "double_it", &[b"abc"], Format::Utf8);
+    check(
+        "concatenate",
+        "concatenate",
+        &[b"hello", b"world"],
+        Format::Utf8,
+    );
+    check("shuffle", "shuffle", &[b"s1", b"s2", b"s3"], Format::Utf8);
+    check("returns_ok", "returns_ok", &[], Format::Utf8);
+    check("returns_err", "returns_err", &[], Format::Utf8);
+    check("will_panic", "will_panic", &[], Format::Utf8);
+    check("set_to_a", "set_to_a", &[b"xxxyyz"], Format::Hex);
+    check(
+        "set_to_a_reuse_buffer",
+        "set_to_a_reuse_buffer",
+        &[b"xxxyyz"],
+        Format::Hex,
+    );
+    // {"x": 1, "y": 2.0}
+    let args = [&[0xa2, 0x61, 0x78, 0x01, 0x61, 0x79, 0xf9, 0x40, 0x00][..]];
+    check("complex_data", "complex_data", &args, Format::Json);
+}
+
+#[test]
+fn rust_example() {
+    check_rust_example("hello_rust");
+}
+
+#[test]
+fn rust_wasi_example() {
+    check_rust_example("hello_rust_wasi");
+}
+
+#[test]
+fn changes() {
+    let dir = std::env::temp_dir().join(format!("wmp-snapshots-{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    let mut plugin = Plugin::new(&fixture("hello")).unwrap();
+    let result = plugin.call("count", &[]);
+    let first = render("count", &[], &result, Format::Hex);
+    assert_eq!(
+        first,
+        "function: count\nargs:\nformat: hex\nstatus: ok\n---\n\
+         00000000  00                                                |.|\n"
+    );
+
+    Snapshots::new(&dir)
+        .update(true)
+        .check("count", &first)
+        .unwrap();
+    let snapshots = Snapshots::new(&dir).update(false);
+    snapshots.check("count", &first).unwrap();
+
+    let result = plugin.call("count", &[]);
+    let second = render("count", &[], &result, Format::Hex);
+    let err = snapshots.check("count", &second).unwrap_err();
+    assert!(err.starts_with("snapshot `count` changed (- saved, + new):\n  function: count\n"));
+    assert!(err.contains(
+        "\n- 00000000  00                                                |.|\
+         \n+ 00000000  01                                                |.|\n"
+    ));
+    assert!(err.ends_with("run the tests with WMP_UPDATE_SNAPSHOTS=1 to accept it"));
+    assert!(dir.join("count.snap.new").exists());
+
+    // Accepting the new snapshot removes `.snap.new`.
+    Snapshots::new(&dir)
+        .update(true)
+        .check("count", &second)
+        .unwrap();
+    assert!(!dir.join("count.snap.new").exists());
+    snapshots.check("count", &second).unwrap();
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn results() {
+    let mut plugin = Plugin::new(&fixture("hello")).unwrap();
+    let mut body = |function, format| {
+        let result = plugin.call(function, &[]);
+        let snapshot = render(function, &[], &result, format);
+        snapshot.split_once("status: ").unwrap().1.to_owned()
+    };
+    assert_eq!(
+        body("returns_err", Format::Utf8),
+        "error\n---\nThis is an `Err`\n"
+    );
+    assert_eq!(
+        body("bad_code", Format::Utf8),
+        "failed\n---\nplugin did not respect the protocol: `bad_code` returned 2, instead of 0 \
+         or 1 (host calls: none)\n"
+    );
+    assert!(body("invalid_err", Format::Json).starts_with("error\n---\nnot valid CBOR ("));
+}
+
+#[test]
+fn dumps_and_diffs() {
+    assert_eq!(hex_dump(b""), "");
+    assert_eq!(
+        hex_dump(b"Hello from wasm!!!\n"),
+        "00000000  48 65 6c 6c 6f 20 66 72  6f 6d 20 77 61 73 6d 21  |Hello from wasm!|\n\
+         00000010  21 21 0a                                          |!!.|\n"
+    );
+    assert_eq!(diff("a\nb\nc\n", "a\nc\nd\n"), "  a\n- b\n  c\n+ d\n");
+}
@@ -3,7 +3,7 @@ use std::{
     process::Command,
 };
 
-fn wasi_stub(path: PathBuf) {
+fn wasi_stub(path: PathBuf, extra_args: &[&str]) {
     let path = path.canonicalize().unwrap();
 
     let wasi_stub = Command::new("cargo")
@@ -11,6 +11,7 @@ fn wasi_stub(path: PathBuf) {
         .arg(&path)
         .arg("-o")
         .arg(&path)
+        .args(extra_args)
         .current_dir(concat!(env!("CARGO_MANIFEST_DIR"), "/../wasi-stub"))
         .status()
         .unwrap();
@@ -71,7 +72,7 @@ fn test_c() {
     if !build_c.success() {
         panic!("Compiling with emcc failed");
     }
-    wasi_stub(dir_path.join("hello.wasm"));
+    wasi_stub(dir_path.join("hello.wasm"), &["--preset", "emscripten"]);
     typst_compile(dir_path);
 }
 
@@ -82,7 +83,7 @@ fn test_rust() {
         "/../../examples/hello_rust"
     ));
 
-    for target in ["wasm32-unknown-unknown", "wasm32-wasip1"] {
+    for target in ["wasm32-unknown-unknown", "wasm32-wasip1", "wasm32-wasip2"] {
         let build_rust = Command::new("cargo")
             .arg("build")
             .arg("--release")
@@ -102,8 +103,8 @@ fn test_rust() {
             dir_path.join("hello.wasm"),
         )
         .unwrap();
-        if target == "wasm32-wasip1" {
-            wasi_stub(dir_path.join("hello.wasm"));
+        if target != "wasm32-unknown-unknown" {
+            wasi_stub(dir_path.join("hello.wasm"), &[]);
         }
         typst_compile(dir_path);
     }
@@ -139,7 +140,7 @@ fn test_zig() {
             panic!("Compiling with zig failed");
         }
         if target == "wasm32-wasi" {
-            wasi_stub(dir_path.join("hello.wasm"));
+            wasi_stub(dir_path.join("hello.wasm"), &[]);
         }
         typst_compile(dir_path);
     }
@@ -177,6 +178,6 @@ fn test_go() {
     if !build_go_wasi.success() {
         panic!("Compiling with tinygo for wasip1 failed");
     }
-    wasi_stub(dir_path.join("hello.wasm"));
+    wasi_stub(dir_path.join("hello.wasm"), &["--preset", "tinygo"]);
     typst_compile(dir_path);
 }
@@ -0,0 +1,76 @@
+//! Support for components, like the ones produced for the `wasm32-wasip2` target.
+//!
+//! Typst can only load core modules: we keep the core module containing the code of
+//! the plugin, and stub the WASI interfaces it imports.
+
+use crate::{Error, Result};
+use wasmparser::{ExternalKind, Parser, Payload, TypeRef};
+
+/// Find the main core module of `component`.
+///
+/// Besides the main module, components contain adapters and shims: they are the
+/// modules that do not export their own memory. If several modules remain, the one
+/// with the most code is picked.
+pub(crate) fn main_module(component: &[u8]) -> Result<&[u8]> {
+    let mut best: Option<(&[u8], usize)> = None;
+    for payload in Parser::new(0).parse_all(component) {
+        let Payload::ModuleSection {
+            unchecked_range, ..
+        } = payload?
+        else {
+            continue;
+        };
+        let start = unchecked_range.start;
+        let module = component.get(unchecked_range).ok_or_else(|| Error::Parse {
+            message: "truncated component".to_owned(),
+            offset: Some(start),
+        })?;
+        let mut defines_memory = false;
+        let mut exports_memory = false;
+        let mut code_size = 0;
+        // Offsets of the errors are in the component.
+        for payload in Parser::new(start as u64).parse_all(module) {
+            match payload? {
+                Payload::MemorySection(memories) => defines_memory |= memories.count() > 0,
+                Payload::ExportSection(exports) => {
+                    for export in exports {
+                        exports_memory |= export?.kind == ExternalKind::Memory;
+                    }
+                }
+                Payload::CodeSectionStart { size, .. } => code_size = size as usize,
+                _ => {}
+            }
+        }
+        if defines_memory
+            && exports_memory
+            && best.is_none_or(|(_, best_size)| code_size > best_size)
+        {
+            best = Some((module, code_size));
+        }
+    }
+    best.map(|(module, _)| module).ok_or_else(|| {
+        Error::UnsupportedComponent(
+            "no core module exporting its memory in this component".to_owned(),
+        )
+    })
+}
+
+/// Modules of the WASI interfaces (`wasi:cli/environment@0.2.0`, ...) imported by
+/// `module`.
+pub(crate) fn wasi_interfaces(module: &[u8]) -> Result<Vec<String>> {
+    let mut interfaces = Vec::new();
+    for payload in Parser::new(0).parse_all(module) {
+        if let Payload::ImportSection(imports) = payload? {
+            for import in imports {
+                let import = import?;
+                if import.module.starts_with("wasi:")
+                    && matches!(import.ty, TypeRef::Func(_))
+                    && !interfaces.iter().any(|i| i == import.module)
+                {
+                    interfaces.push(import.module.to_owned());
+                }
+            }
+        }
+    }
+    Ok(interfaces)
+}
@@ -0,0 +1,26 @@
+//! Messages about the progress of a transformation, printed on stderr.
+
+use std::cell::RefCell;
+
+thread_local! {
+    /// The messages emitted by the current thread, when they are captured.
+    static CAPTURED: RefCell<Option<Vec<String>>> = const { RefCell::new(None) };
+}
+
+/// Run `f`, and return the messages it emitted instead of printing them.
+///
+/// Useful to process several modules in parallel, without mixing their messages.
+pub fn capture_diagnostics<T>(f: impl FnOnce() -> T) -> (T, Vec<String>) {
+    let previous = CAPTURED.replace(Some(Vec::new()));
+    let result = f();
+    let messages = CAPTURED.replace(previous).unwrap_or_default();
+    (result, messages)
+}
+
+/// Print `message`, or keep it if the messages are captured.
+pub(crate) fn emit(message: String) {
+    CAPTURED.with_borrow_mut(|captured| match captured {
+        Some(messages) => messages.push(message),
+        None => eprintln!("{message}"),
+    });
+}
@@ -0,0 +1,207 @@
+//! Comparison of two builds of a plugin, to notice new imports or lost exports
+//! before users do.
+
+use crate::{Error, Export, Import, ItemType, ModuleInfo};
+use std::{collections::BTreeMap, fmt};
+
+/// A difference between two modules.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Change {
+    AddedImport(Import),
+    RemovedImport(Import),
+    /// An import with the same module and name, but a different type.
+    ChangedImport {
+        old: Import,
+        new: ItemType,
+    },
+    AddedExport(Export),
+    RemovedExport(Export),
+    /// An export with the same name, but a different type.
+    ChangedExport {
+        old: Export,
+        new: ItemType,
+    },
+    /// The size of a section changed. A size of `None` means that there is no such
+    /// section.
+    SectionSize {
+        name: String,
+        old: Option<usize>,
+        new: Option<usize>,
+    },
+}
+
+/// Categories of [`Change`]s, to decide which ones should fail a build.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ChangeKind {
+    AddedImport,
+    RemovedImport,
+    AddedExport,
+    RemovedExport,
+    /// The type of an import or an export changed.
+    Signature,
+    SizeIncrease,
+    SizeDecrease,
+}
+
+impl ChangeKind {
+    pub const ALL: &'static [Self] = &[
+        Self::AddedImport,
+        Self::RemovedImport,
+        Self::AddedExport,
+        Self::RemovedExport,
+        Self::Signature,
+        Self::SizeIncrease,
+        Self::SizeDecrease,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::AddedImport => "added-import",
+            Self::RemovedImport => "removed-import",
+            Self::AddedExport => "added-export",
+            Self::RemovedExport => "removed-export",
+            Self::Signature => "signature",
+            Self::SizeIncrease => "size-increase",
+            Self::SizeDecrease => "size-decrease",
+        }
+    }
+}
+
+impl std::str::FromStr for ChangeKind {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::ALL
+            .iter()
+            .copied()
+            .find(|kind| kind.name() == s)
+            .ok_or_else(|| Error::message(format!("Unknown kind of change: {s}")))
+    }
+}
+
+impl Change {
+    pub fn kind(&self) -> ChangeKind {
+        match self {
+            Self::AddedImport(_) => ChangeKind::AddedImport,
+            Self::RemovedImport(_) => ChangeKind::RemovedImport,
+            Self::AddedExport(_) => ChangeKind::AddedExport,
+            Self::RemovedExport(_) => ChangeKind::RemovedExport,
+            Self::ChangedImport { .. } | Self::ChangedExport { .. } => ChangeKind::Signature,
+            Self::SectionSize { old, new, .. } if new > old => ChangeKind::SizeIncrease,
+            Self::SectionSize { .. } => ChangeKind::SizeDecrease,
+        }
+    }
+}
+
+impl fmt::Display for Change {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::AddedImport(import) => write!(
+                f,
+                "+ import {}::{}: {}",
+                import.module, import.name, import.ty
+            ),
+            Self::RemovedImport(import) => write!(
+                f,
+                "- import {}::{}: {}",
+                import.module, import.name, import.ty
+            ),
+            Self::ChangedImport { old, new } => write!(
+                f,
+                "~ import {}::{}: {} => {new}",
+                old.module, old.name, old.ty
+            ),
+            Self::AddedExport(export) => write!(f, "+ export {}: {}", export.name, export.ty),
+            Self::RemovedExport(export) => write!(f, "- export {}: {}", export.name, export.ty),
+            Self::ChangedExport { old, new } => {
+                write!(f, "~ export {}: {} => {new}", old.name, old.ty)
+            }
+            Self::SectionSize { name, old, new } => {
+                let size = |size: &Option<usize>| match size {
+                    Some(size) => format!("{size} bytes"),
+                    None => "none".to_owned(),
+                };
+                let delta = new.unwrap_or(0) as i64 - old.unwrap_or(0) as i64;
+                write!(
+                    f,
+                    "  section {name}: {} => {} ({delta:+})",
+                    size(old),
+                    size(new)
+                )
+            }
+        }
+    }
+}
+
+/// Differences between two modules: first the imports, then the exports, then the
+/// sections.
+pub fn diff(old: &ModuleInfo, new: &ModuleInfo) -> Vec<Change> {
+    let mut changes = Vec::new();
+
+    let key = |import: &Import| (import.module.clone(), import.name.clone());
+    let new_imports: BTreeMap<_, _> = new.imports.iter().map(|i| (key(i), i)).collect();
+    let old_imports: BTreeMap<_, _> = old.imports.iter().map(|i| (key(i), i)).collect();
+    for import in &old.imports {
+        match new_imports.get(&key(import)) {
+            None => changes.push(Change::RemovedImport(import.clone())),
+            Some(new) if new.ty != import.ty => changes.push(Change::ChangedImport {
+                old: import.clone(),
+                new: new.ty.clone(),
+            }),
+            Some(_) => {}
+        }
+    }
+    for import in &new.imports {
+        if !old_imports.contains_key(&key(import)) {
+            changes.push(Change::AddedImport(import.clone()));
+        }
+    }
+
+    let new_exports: BTreeMap<_, _> = new.exports.iter().map(|e| (&e.name, e)).collect();
+    let old_exports: BTreeMap<_, _> = old.exports.iter().map(|e| (&e.name, e)).collect();
+    for export in &old.exports {
+        match new_exports.get(&export.name) {
+            None => changes.push(Change::RemovedExport(export.clone())),
+            Some(new) if new.ty != export.ty => changes.push(Change::ChangedExport {
+                old: export.clone(),
+                new: new.ty.clone(),
+            }),
+            Some(_) => {}
+        }
+    }
+    for export in &new.exports {
+        if !old_exports.contains_key(&export.name) {
+            changes.push(Change::AddedExport(export.clone()));
+        }
+    }
+
+    // Custom sections can appear several times: their sizes are added up.
+    let mut sections: Vec<(&str, Option<usize>, Option<usize>)> = Vec::new();
+    for (info, is_new) in [(old, false), (new, true)] {
+        for section in &info.sections {
+            let index = match sections.iter().position(|(name, ..)| *name == section.name) {
+                Some(index) => index,
+                None => {
+                    sections.push((&section.name, None, None));
+                    sections.len() - 1
+                }
+            };
+            let size = if is_new {
+                &mut sections[index].2
+            } else {
+                &mut sections[index].1
+            };
+            *size = Some(size.unwrap_or(0) + section.size);
+        }
+    }
+    for (name, old, new) in sections {
+        if old != new {
+            changes.push(Change::SectionSize {
+                name: name.to_owned(),
+                old,
+                new,
+            });
+        }
+    }
+    changes
+}
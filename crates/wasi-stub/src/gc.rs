@@ -0,0 +1,377 @@
+//! Removal of the functions, globals and data segments that can never be used.
+//!
+//! Once WASI functions are stubbed, whole parts of a plugin (filesystem, formatting,
+//! ...) become unreachable, but are still shipped in the binary.
+
+use crate::{diagnostics, print_wat, Error, Result};
+use std::collections::HashMap;
+use wast::{
+    core::{
+        DataKind, ElemKind, ElemPayload, ExportKind, Expression, FuncKind, GlobalKind, Instruction,
+        ItemKind, ModuleField, ModuleKind, TableKind,
+    },
+    token::Index,
+    Wat,
+};
+
+/// Items of one kind (functions, globals or data segments) in a module.
+#[derive(Default)]
+struct Items<'a> {
+    /// Index of each named item.
+    ids: HashMap<&'a str, u32>,
+    reached: Vec<bool>,
+}
+
+impl<'a> Items<'a> {
+    fn push(&mut self, id: Option<wast::token::Id<'a>>) {
+        if let Some(id) = id {
+            self.ids.insert(id.name(), self.reached.len() as u32);
+        }
+        self.reached.push(false);
+    }
+
+    fn resolve(&self, index: &Index) -> Option<u32> {
+        match index {
+            Index::Num(index, _) => Some(*index),
+            Index::Id(id) => self.ids.get(id.name()).copied(),
+        }
+    }
+
+    fn is_reached(&self, index: &Index) -> bool {
+        self.resolve(index)
+            .is_some_and(|index| self.reached[index as usize])
+    }
+
+    /// New index of each item, if it is kept.
+    fn new_indices(&self) -> Vec<Option<u32>> {
+        let mut next = 0;
+        self.reached
+            .iter()
+            .map(|&reached| {
+                reached.then(|| {
+                    next += 1;
+                    next - 1
+                })
+            })
+            .collect()
+    }
+
+    fn removed(&self) -> usize {
+        self.reached.iter().filter(|reached| !**reached).count()
+    }
+}
+
+enum Item {
+    Func(u32),
+    Global(u32),
+}
+
+/// Items that are used, but not yet visited.
+#[derive(Default)]
+struct Worklist {
+    items: Vec<Item>,
+}
+
+impl Worklist {
+    fn visit(&mut self, instructions: &[Instruction], module: &mut ModuleItems) {
+        for instruction in instructions {
+            match instruction {
+                Instruction::Call(index)
+                | Instruction::ReturnCall(index)
+                | Instruction::RefFunc(index) => {
+                    if let Some(index) = module.funcs.resolve(index) {
+                        self.items.push(Item::Func(index));
+                    }
+                }
+                Instruction::GlobalGet(index) | Instruction::GlobalSet(index) => {
+                    if let Some(index) = module.globals.resolve(index) {
+                        self.items.push(Item::Global(index));
+                    }
+                }
+                Instruction::MemoryInit(wast::core::MemoryInit { data: index, .. })
+                | Instruction::DataDrop(index)
+                | Instruction::ArrayNewData(wast::core::ArrayNewData {
+                    data_idx: index, ..
+                })
+                | Instruction::ArrayInitData(wast::core::ArrayInit { segment: index, .. }) => {
+                    if let Some(index) = module.data.resolve(index) {
+                        module.data.reached[index as usize] = true;
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+}
+
+#[derive(Default)]
+struct ModuleItems<'a> {
+    funcs: Items<'a>,
+    globals: Items<'a>,
+    data: Items<'a>,
+}
+
+/// Remove the functions, globals and passive data segments that cannot be reached
+/// from the exports, the start function or the tables.
+///
+/// Active data segments are always kept, since they initialize the memory.
+pub fn garbage_collect(binary: &[u8]) -> Result<Vec<u8>> {
+    let printed = print_wat(binary)?;
+    let parse_buffer =
+        wast::parser::ParseBuffer::new(&printed.text).map_err(|err| printed.error(err))?;
+
+    let mut wat: Wat = wast::parser::parse(&parse_buffer).map_err(|err| printed.error(err))?;
+    let module = match &mut wat {
+        Wat::Module(m) => m,
+        Wat::Component(_) => {
+            return Err(Error::UnsupportedComponent(
+                "run wasi-stub on the component first".to_owned(),
+            ))
+        }
+    };
+    let ModuleKind::Text(fields) = &mut module.kind else {
+        return Err(Error::BinaryDirectives);
+    };
+
+    let mut items = ModuleItems::default();
+    let mut bodies = Vec::new();
+    let mut inits = Vec::new();
+    for field in fields.iter() {
+        match field {
+            ModuleField::Import(i) => match &i.item.kind {
+                ItemKind::Func(_) => {
+                    items.funcs.push(i.item.id);
+                    bodies.push(None);
+                }
+                ItemKind::Global(_) => {
+                    items.globals.push(i.item.id);
+                    inits.push(None);
+                }
+                _ => {}
+            },
+            ModuleField::Func(f) => {
+                items.funcs.push(f.id);
+                bodies.push(match &f.kind {
+                    FuncKind::Inline { expression, .. } => Some(expression),
+                    FuncKind::Import(_) => None,
+                });
+            }
+            ModuleField::Global(g) => {
+                items.globals.push(g.id);
+                inits.push(match &g.kind {
+                    GlobalKind::Inline(expression) => Some(expression),
+                    GlobalKind::Import(_) => None,
+                });
+            }
+            ModuleField::Data(d) => {
+                items.data.push(d.id);
+                if let DataKind::Active { .. } = d.kind {
+                    *items.data.reached.last_mut().unwrap() = true;
+                }
+            }
+            _ => {}
+        }
+    }
+
+    // Roots: everything the host or the tables can access.
+    let mut worklist = Worklist::default();
+    for field in fields.iter() {
+        match field {
+            ModuleField::Export(export) => match export.kind {
+                ExportKind::Func => worklist
+                    .items
+                    .extend(items.funcs.resolve(&export.item).map(Item::Func)),
+                ExportKind::Global => worklist
+                    .items
+                    .extend(items.globals.resolve(&export.item).map(Item::Global)),
+                _ => {}
+            },
+            ModuleField::Start(index) => worklist
+                .items
+                .extend(items.funcs.resolve(index).map(Item::Func)),
+            ModuleField::Elem(elem) => {
+                if let ElemKind::Active { offset, .. } = &elem.kind {
+                    worklist.visit(&offset.instrs, &mut items);
+                }
+                // Declared segments only allow `ref.func`: they keep nothing alive.
+                if let ElemKind::Declared = elem.kind {
+                    continue;
+                }
+                match &elem.payload {
+                    ElemPayload::Indices(indices) => worklist.items.extend(
+                        indices
+                            .iter()
+                            .filter_map(|i| items.funcs.resolve(i).map(Item::Func)),
+                    ),
+                    ElemPayload::Exprs { exprs, .. } => {
+                        for expr in exprs {
+                            worklist.visit(&expr.instrs, &mut items);
+                        }
+                    }
+                }
+            }
+            ModuleField::Data(data) => {
+                if let DataKind::Active { offset, .. } = &data.kind {
+                    worklist.visit(&offset.instrs, &mut items);
+                }
+            }
+            ModuleField::Table(table) => {
+                if let TableKind::Normal {
+                    init_expr: Some(init),
+                    ..
+                } = &table.kind
+                {
+                    worklist.visit(&init.instrs, &mut items);
+                }
+            }
+            _ => {}
+        }
+    }
+    while let Some(item) = worklist.items.pop() {
+        let (reached, expression) = match item {
+            Item::Func(index) => (
+                &mut items.funcs.reached[index as usize],
+                bodies[index as usize],
+            ),
+            Item::Global(index) => (
+                &mut items.globals.reached[index as usize],
+                inits[index as usize],
+            ),
+        };
+        if std::mem::replace(reached, true) {
+            continue;
+        }
+        if let Some(expression) = expression {
+            worklist.visit(&expression.instrs, &mut items);
+        }
+    }
+
+    let removed = (
+        items.funcs.removed(),
+        items.globals.removed(),
+        items.data.removed(),
+    );
+    let funcs = items.funcs.new_indices();
+    let globals = items.globals.new_indices();
+    let data = items.data.new_indices();
+
+    let mut counters = (0, 0, 0);
+    fields.retain_mut(|field| {
+        let (counter, new_indices) = match field {
+            ModuleField::Import(i) => match i.item.kind {
+                ItemKind::Func(_) => (&mut counters.0, &funcs),
+                ItemKind::Global(_) => (&mut counters.1, &globals),
+                _ => return true,
+            },
+            ModuleField::Func(_) => (&mut counters.0, &funcs),
+            ModuleField::Global(_) => (&mut counters.1, &globals),
+            ModuleField::Data(_) => (&mut counters.2, &data),
+            // Forget removed functions.
+            ModuleField::Elem(elem) => {
+                if let ElemKind::Declared = elem.kind {
+                    match &mut elem.payload {
+                        ElemPayload::Indices(indices) => {
+                            indices.retain(|i| items.funcs.is_reached(i))
+                        }
+                        ElemPayload::Exprs { exprs, .. } => exprs.retain(|expr| {
+                            !matches!(&*expr.instrs, [Instruction::RefFunc(i)] if !items.funcs.is_reached(i))
+                        }),
+                    }
+                }
+                return true;
+            }
+            _ => return true,
+        };
+        *counter += 1;
+        new_indices[*counter - 1].is_some()
+    });
+
+    let remap = |index: &mut Index, new_indices: &[Option<u32>]| {
+        if let Index::Num(index, _) = index {
+            *index = new_indices[*index as usize].unwrap();
+        }
+    };
+    let remap_expression = |expression: &mut Expression| {
+        for instruction in expression.instrs.iter_mut() {
+            match instruction {
+                Instruction::Call(index)
+                | Instruction::ReturnCall(index)
+                | Instruction::RefFunc(index) => remap(index, &funcs),
+                Instruction::GlobalGet(index) | Instruction::GlobalSet(index) => {
+                    remap(index, &globals)
+                }
+                Instruction::MemoryInit(wast::core::MemoryInit { data: index, .. })
+                | Instruction::DataDrop(index)
+                | Instruction::ArrayNewData(wast::core::ArrayNewData {
+                    data_idx: index, ..
+                })
+                | Instruction::ArrayInitData(wast::core::ArrayInit { segment: index, .. }) => {
+                    remap(index, &data)
+                }
+                _ => {}
+            }
+        }
+    };
+    for field in fields.iter_mut() {
+        match field {
+            ModuleField::Func(f) => {
+                if let FuncKind::Inline { expression, .. } = &mut f.kind {
+                    remap_expression(expression);
+                }
+            }
+            ModuleField::Global(g) => {
+                if let GlobalKind::Inline(expression) = &mut g.kind {
+                    remap_expression(expression);
+                }
+            }
+            ModuleField::Export(export) => match export.kind {
+                ExportKind::Func => remap(&mut export.item, &funcs),
+                ExportKind::Global => remap(&mut export.item, &globals),
+                _ => {}
+            },
+            ModuleField::Start(index) => remap(index, &funcs),
+            ModuleField::Elem(elem) => {
+                if let ElemKind::Active { offset, .. } = &mut elem.kind {
+                    remap_expression(offset);
+                }
+                match &mut elem.payload {
+                    ElemPayload::Indices(indices) => {
+                        for index in indices {
+                            remap(index, &funcs);
+                        }
+                    }
+                    ElemPayload::Exprs { exprs, .. } => {
+                        for expr in exprs {
+                            remap_expression(expr);
+                        }
+                    }
+                }
+            }
+            ModuleField::Data(data) => {
+                if let DataKind::Active { offset, .. } = &mut data.kind {
+                    remap_expression(offset);
+                }
+            }
+            ModuleField::Table(table) => {
+                if let TableKind::Normal {
+                    init_expr: Some(init),
+                    ..
+                } = &mut table.kind
+                {
+                    remap_expression(init);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    let output = module.encode().map_err(|err| printed.error(err))?;
+    diagnostics::emit(format!(
+        "Removed {} functions, {} globals and {} data segments: {} bytes saved",
+        removed.0,
+        removed.1,
+        removed.2,
+        binary.len().saturating_sub(output.len())
+    ));
+    Ok(output)
+}
@@ -0,0 +1,361 @@
+//! Summary of a module, from the point of view of the plugin protocol.
+//!
+//! This is what one usually looks for by disassembling a plugin that typst refuses
+//! to load: its exports, its imports, and what produced it.
+
+use crate::{component, Result};
+use std::fmt;
+use wasmparser::{
+    CompositeInnerType, ExternalKind, FuncType, GlobalType, KnownCustom, MemoryType, Parser,
+    Payload, TableType, TypeRef, ValType,
+};
+
+/// Type of an imported or exported item.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ItemType {
+    Func(FuncType),
+    Table(TableType),
+    Memory(MemoryType),
+    Global(GlobalType),
+    Tag(FuncType),
+}
+
+impl ItemType {
+    /// Whether this is the signature of a protocol function: only `i32` parameters
+    /// (the lengths of the arguments), and an `i32` result (`0` on success, `1` on
+    /// error).
+    pub fn is_protocol_function(&self) -> bool {
+        match self {
+            Self::Func(ty) => {
+                ty.params().iter().all(|param| *param == ValType::I32)
+                    && ty.results() == [ValType::I32]
+            }
+            _ => false,
+        }
+    }
+}
+
+impl fmt::Display for ItemType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Func(ty) => write_signature(f, "func", ty),
+            Self::Tag(ty) => write_signature(f, "tag", ty),
+            Self::Table(ty) => {
+                write!(f, "table {} ", ty.element_type)?;
+                write_limits(f, ty.initial, ty.maximum)
+            }
+            Self::Memory(ty) => {
+                write!(f, "memory ")?;
+                write_limits(f, ty.initial, ty.maximum)?;
+                write!(f, " pages")?;
+                if ty.memory64 {
+                    write!(f, ", i64")?;
+                }
+                if ty.shared {
+                    write!(f, ", shared")?;
+                }
+                Ok(())
+            }
+            Self::Global(ty) if ty.mutable => write!(f, "global mut {}", ty.content_type),
+            Self::Global(ty) => write!(f, "global {}", ty.content_type),
+        }
+    }
+}
+
+fn write_signature(f: &mut fmt::Formatter<'_>, kind: &str, ty: &FuncType) -> fmt::Result {
+    let list = |types: &[ValType]| {
+        types
+            .iter()
+            .map(ValType::to_string)
+            .collect::<Vec<_>>()
+            .join(", ")
+    };
+    write!(f, "{kind}({})", list(ty.params()))?;
+    match ty.results() {
+        [] => Ok(()),
+        [result] => write!(f, " -> {result}"),
+        results => write!(f, " -> ({})", list(results)),
+    }
+}
+
+fn write_limits(f: &mut fmt::Formatter<'_>, min: u64, max: Option<u64>) -> fmt::Result {
+    match max {
+        Some(max) => write!(f, "{min}..{max}"),
+        None => write!(f, "{min}.."),
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Import {
+    pub module: String,
+    pub name: String,
+    pub ty: ItemType,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Export {
+    pub name: String,
+    pub ty: ItemType,
+}
+
+/// A section of the module, with the size of its contents in bytes.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Section {
+    /// Name of the section, for example `code`, or `custom "name"`.
+    pub name: String,
+    pub size: usize,
+}
+
+/// Summary of a module, as returned by [`inspect`].
+#[derive(Clone, Debug, Default)]
+pub struct ModuleInfo {
+    pub exports: Vec<Export>,
+    pub imports: Vec<Import>,
+    /// Imported and defined memories.
+    pub memories: Vec<MemoryType>,
+    /// Total size of the data segments.
+    pub data_size: usize,
+    pub data_segments: usize,
+    pub sections: Vec<Section>,
+    /// Contents of the `producers` section: each field (`language`, `processed-by`,
+    /// `sdk`) has a list of names and versions.
+    pub producers: Vec<(String, Vec<(String, String)>)>,
+}
+
+impl ModuleInfo {
+    /// The custom sections, in the order of the module.
+    pub fn custom_sections(&self) -> impl Iterator<Item = &Section> {
+        self.sections
+            .iter()
+            .filter(|section| section.name.starts_with("custom "))
+    }
+}
+
+impl fmt::Display for ModuleInfo {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "exports:")?;
+        for export in &self.exports {
+            write!(f, "  {}: {}", export.name, export.ty)?;
+            if export.ty.is_protocol_function() {
+                write!(f, " (protocol function)")?;
+            }
+            writeln!(f)?;
+        }
+        writeln!(f, "imports:")?;
+        let mut imports: Vec<_> = self.imports.iter().collect();
+        imports.sort_by(|a, b| a.module.cmp(&b.module));
+        let mut module = None;
+        for import in imports {
+            if module != Some(&import.module) {
+                writeln!(f, "  {}:", import.module)?;
+                module = Some(&import.module);
+            }
+            writeln!(f, "    {}: {}", import.name, import.ty)?;
+        }
+        writeln!(f, "memories:")?;
+        for (index, memory) in self.memories.iter().enumerate() {
+            writeln!(f, "  {index}: {}", ItemType::Memory(*memory))?;
+        }
+        writeln!(
+            f,
+            "data: {} bytes in {} segments",
+            self.data_size, self.data_segments
+        )?;
+        writeln!(f, "custom sections:")?;
+        for section in self.custom_sections() {
+            writeln!(
+                f,
+                "  {}: {} bytes",
+                &section.name["custom ".len()..],
+                section.size
+            )?;
+        }
+        writeln!(f, "producers:")?;
+        for (field, values) in &self.producers {
+            let values: Vec<_> = values
+                .iter()
+                .map(|(name, version)| match version.as_str() {
+                    "" => name.clone(),
+                    version => format!("{name} {version}"),
+                })
+                .collect();
+            writeln!(f, "  {field}: {}", values.join(", "))?;
+        }
+        Ok(())
+    }
+}
+
+/// Summarize the exports, imports, memories and sections of `binary`.
+///
+/// If `binary` is a component, its main core module is inspected instead.
+pub fn inspect(binary: &[u8]) -> Result<ModuleInfo> {
+    let binary = if Parser::is_component(binary) {
+        component::main_module(binary)?
+    } else {
+        binary
+    };
+
+    let mut info = ModuleInfo::default();
+    // `None` for types that are not functions.
+    let mut types = Vec::new();
+    // Type of each function, imported or defined.
+    let mut functions = Vec::new();
+    let mut tables = Vec::new();
+    let mut globals = Vec::new();
+    let mut tags = Vec::new();
+    let func_type = |types: &[Option<FuncType>], index: u32| {
+        types
+            .get(index as usize)
+            .cloned()
+            .flatten()
+            .unwrap_or_else(|| FuncType::new([], []))
+    };
+    for payload in Parser::new(0).parse_all(binary) {
+        let payload = payload?;
+        if let Some((id, range)) = payload.as_section() {
+            let name = match &payload {
+                Payload::CustomSection(section) => format!("custom {:?}", section.name()),
+                _ => section_name(id).to_owned(),
+            };
+            info.sections.push(Section {
+                name,
+                size: range.len(),
+            });
+        }
+        match payload {
+            Payload::TypeSection(reader) => {
+                for group in reader {
+                    for ty in group?.into_types() {
+                        types.push(match ty.composite_type.inner {
+                            CompositeInnerType::Func(ty) => Some(ty),
+                            _ => None,
+                        });
+                    }
+                }
+            }
+            Payload::ImportSection(reader) => {
+                for import in reader {
+                    let import = import?;
+                    let ty = match import.ty {
+                        TypeRef::Func(index) => {
+                            let ty = func_type(&types, index);
+                            functions.push(ty.clone());
+                            ItemType::Func(ty)
+                        }
+                        TypeRef::Table(ty) => {
+                            tables.push(ty);
+                            ItemType::Table(ty)
+                        }
+                        TypeRef::Memory(ty) => {
+                            info.memories.push(ty);
+                            ItemType::Memory(ty)
+                        }
+                        TypeRef::Global(ty) => {
+                            globals.push(ty);
+                            ItemType::Global(ty)
+                        }
+                        TypeRef::Tag(ty) => {
+                            let ty = func_type(&types, ty.func_type_idx);
+                            tags.push(ty.clone());
+                            ItemType::Tag(ty)
+                        }
+                    };
+                    info.imports.push(Import {
+                        module: import.module.to_owned(),
+                        name: import.name.to_owned(),
+                        ty,
+                    });
+                }
+            }
+            Payload::FunctionSection(reader) => {
+                for index in reader {
+                    functions.push(func_type(&types, index?));
+                }
+            }
+            Payload::TableSection(reader) => {
+                for table in reader {
+                    tables.push(table?.ty);
+                }
+            }
+            Payload::MemorySection(reader) => {
+                for memory in reader {
+                    info.memories.push(memory?);
+                }
+            }
+            Payload::GlobalSection(reader) => {
+                for global in reader {
+                    globals.push(global?.ty);
+                }
+            }
+            Payload::TagSection(reader) => {
+                for tag in reader {
+                    tags.push(func_type(&types, tag?.func_type_idx));
+                }
+            }
+            Payload::ExportSection(reader) => {
+                for export in reader {
+                    let export = export?;
+                    let index = export.index as usize;
+                    let ty = match export.kind {
+                        ExternalKind::Func => functions.get(index).cloned().map(ItemType::Func),
+                        ExternalKind::Table => tables.get(index).copied().map(ItemType::Table),
+                        ExternalKind::Memory => {
+                            info.memories.get(index).copied().map(ItemType::Memory)
+                        }
+                        ExternalKind::Global => globals.get(index).copied().map(ItemType::Global),
+                        ExternalKind::Tag => tags.get(index).cloned().map(ItemType::Tag),
+                    };
+                    // Invalid indices are reported by the validation.
+                    if let Some(ty) = ty {
+                        info.exports.push(Export {
+                            name: export.name.to_owned(),
+                            ty,
+                        });
+                    }
+                }
+            }
+            Payload::DataSection(reader) => {
+                for data in reader {
+                    info.data_size += data?.data.len();
+                    info.data_segments += 1;
+                }
+            }
+            Payload::CustomSection(section) => {
+                if let KnownCustom::Producers(reader) = section.as_known() {
+                    for field in reader {
+                        let field = field?;
+                        let values = field
+                            .values
+                            .into_iter()
+                            .map(|value| {
+                                value.map(|value| (value.name.to_owned(), value.version.to_owned()))
+                            })
+                            .collect::<std::result::Result<_, _>>()?;
+                        info.producers.push((field.name.to_owned(), values));
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+    Ok(info)
+}
+
+fn section_name(id: u8) -> &'static str {
+    match id {
+        1 => "type",
+        2 => "import",
+        3 => "function",
+        4 => "table",
+        5 => "memory",
+        6 => "global",
+        7 => "export",
+        8 => "start",
+        9 => "element",
+        10 => "code",
+        11 => "data",
+        12 => "data count",
+        13 => "tag",
+        _ => "unknown",
+    }
+}
@@ -1,18 +1,44 @@
-use std::collections::{HashMap, HashSet};
+mod component;
+mod diagnostics;
+mod diff;
+mod gc;
+mod inspect;
+mod pre_init;
+mod preset;
+mod reactor;
+mod validate;
+
+use std::collections::{BTreeMap, HashMap, HashSet};
 
 use wast::{
     core::{
-        Expression, Func, FuncKind, FunctionType, HeapType, InlineExport, InnerTypeKind,
-        Instruction, ItemKind, Local, ModuleField, ModuleKind, RefType, TypeUse, ValType,
+        CallIndirect, DataKind, ElemKind, ElemPayload, ExportKind, Expression, Func, FuncKind,
+        FunctionType, Global, GlobalKind, GlobalType, HeapType, InlineExport, InnerTypeKind,
+        Instruction, ItemKind, Local, Memory, MemoryKind, MemoryType, ModuleField, ModuleKind,
+        RefType, Table, TableKind, TableType, TypeUse, V128Const, ValType,
     },
-    token::{Id, Index, NameAnnotation},
+    token::{Id, Index, NameAnnotation, F32, F64},
     Wat,
 };
 
+pub use diagnostics::capture_diagnostics;
+pub use diff::{diff, Change, ChangeKind};
+pub use gc::garbage_collect;
+pub use inspect::{inspect, Export, Import, ItemType, ModuleInfo, Section};
+pub use pre_init::{pre_initialize, pre_initialize_with_fuel, INIT_FUEL, INIT_FUNCTION};
+pub use preset::Preset;
+pub use reactor::command_to_reactor;
+pub use validate::{validate, ValidationError};
+pub use wasmparser::WasmFeatures;
+
+#[derive(Clone)]
 pub enum FunctionsToStub {
     All,
+    /// Names of the functions to stub. A name ending with `*` stands for all the
+    /// functions starting with the given prefix.
     Some(HashSet<String>),
 }
+#[derive(Clone)]
 pub struct ShouldStub {
     pub modules: HashMap<String, FunctionsToStub>,
 }
@@ -26,27 +52,194 @@ impl Default for ShouldStub {
     }
 }
 
+/// Definitions of the functions, globals, memories and tables that replace stubbed
+/// imports.
+///
+/// Items are identified by the `(module, name)` pair of their import.
+#[derive(Clone, Default)]
+pub struct StubValues {
+    /// Behaviour of stubbed functions. A name ending with `*` stands for all the
+    /// functions starting with the given prefix.
+    ///
+    /// Functions that are not listed return the default return value.
+    pub functions: HashMap<(String, String), FunctionStub>,
+    /// Initial values of stubbed globals. Globals that are not listed are initialized to 0.
+    pub globals: HashMap<(String, String), GlobalValue>,
+    /// Limits of stubbed memories. Memories that are not listed keep the limits of their import.
+    pub memories: HashMap<(String, String), Limits>,
+    /// Limits of stubbed tables. Tables that are not listed keep the limits of their import.
+    pub tables: HashMap<(String, String), Limits>,
+    /// Mutable `i64` globals added to the module, with their initial value.
+    ///
+    /// The bodies of stubbed functions can refer to them as `$name`, for example to
+    /// keep track of a clock.
+    pub state: BTreeMap<String, i64>,
+}
+
+impl StubValues {
+    fn function_stub(&self, module: &str, function: &str) -> Option<&FunctionStub> {
+        self.functions
+            .get(&(module.to_owned(), function.to_owned()))
+            .or_else(|| {
+                self.functions
+                    .iter()
+                    .filter(|((m, pattern), _)| m == module && name_matches(pattern, function))
+                    .max_by_key(|((_, pattern), _)| pattern.len())
+                    .map(|(_, stub)| stub)
+            })
+    }
+}
+
+/// Body of a stubbed function.
+#[derive(Clone, Debug)]
+pub enum FunctionStub {
+    /// Return the given value, converted to the type of each result.
+    Return(i64),
+    /// Trap as soon as the function is called.
+    Trap,
+    /// Instructions in the WebAssembly text format, for example
+    /// `local.get 0 local.get 1 i32.add`.
+    Body(String),
+    /// Call the function found at index `param0` of table 0, forwarding the other
+    /// parameters. This is how emscripten's `invoke_*` functions behave when
+    /// exceptions are disabled.
+    CallIndirect,
+}
+
+/// Initial value of a stubbed global.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum GlobalValue {
+    /// A value for globals of any number type.
+    Int(i64),
+    /// A value for `f32` and `f64` globals only.
+    Float(f64),
+}
+
+impl From<i64> for GlobalValue {
+    fn from(value: i64) -> Self {
+        Self::Int(value)
+    }
+}
+
+impl From<f64> for GlobalValue {
+    fn from(value: f64) -> Self {
+        Self::Float(value)
+    }
+}
+
+impl std::fmt::Display for GlobalValue {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Self::Int(value) => write!(f, "{value}"),
+            Self::Float(value) => write!(f, "{value:?}"),
+        }
+    }
+}
+
+/// Size limits of a memory (in pages) or of a table (in elements).
+#[derive(Clone, Copy)]
+pub struct Limits {
+    pub min: u64,
+    pub max: Option<u64>,
+}
+
 enum ImportIndex {
     ToStub(u32),
     Keep(u32),
 }
 
+/// New indices of the imported items of one kind (functions, globals, ...).
+#[derive(Default)]
+struct ImportIndices {
+    indices: Vec<ImportIndex>,
+    kept: u32,
+    stubbed: u32,
+}
+
+impl ImportIndices {
+    fn push(&mut self, stub: bool) {
+        if stub {
+            self.indices.push(ImportIndex::ToStub(self.stubbed));
+            self.stubbed += 1;
+        } else {
+            self.indices.push(ImportIndex::Keep(self.kept));
+            self.kept += 1;
+        }
+    }
+
+    /// Stubs become the first items defined by the module: they are placed right
+    /// after the imports that are kept.
+    fn remap(&self, index: &mut Index) {
+        if let Index::Num(index, _) = index {
+            if let Some(new_index) = self.indices.get(*index as usize) {
+                *index = match new_index {
+                    ImportIndex::ToStub(idx) => *idx + self.kept,
+                    ImportIndex::Keep(idx) => *idx,
+                };
+            }
+        }
+    }
+
+    fn is_identity(&self) -> bool {
+        self.indices
+            .iter()
+            .enumerate()
+            .all(|(i, new_index)| match new_index {
+                ImportIndex::ToStub(idx) => (*idx + self.kept) as usize == i,
+                ImportIndex::Keep(idx) => *idx as usize == i,
+            })
+    }
+}
+
 struct ToStub {
     fields_index: usize,
+    /// Stubbed import, as `module::name`.
+    import: String,
     span: wast::token::Span,
-    nb_results: usize,
-    ty: TypeUse<'static, FunctionType<'static>>,
     name: Option<NameAnnotation<'static>>,
     id: Option<Id<'static>>,
-    locals: Vec<Local<'static>>,
+    kind: StubKind,
+}
+
+enum StubKind {
+    Func {
+        ty: TypeUse<'static, FunctionType<'static>>,
+        params: Vec<ValType<'static>>,
+        results: Vec<ValType<'static>>,
+        locals: Vec<Local<'static>>,
+        stub: FunctionStub,
+    },
+    Global {
+        ty: GlobalType<'static>,
+        /// Instruction producing the initial value.
+        value: Instruction<'static>,
+    },
+    Memory(MemoryType),
+    Table(TableType<'static>),
 }
 
 impl ShouldStub {
+    /// Stub `function` from `module`, on top of the functions already stubbed.
+    pub fn add_function(&mut self, module: &str, function: &str) {
+        let functions = self
+            .modules
+            .entry(module.to_owned())
+            .or_insert(FunctionsToStub::Some(HashSet::new()));
+        match functions {
+            FunctionsToStub::All => {}
+            FunctionsToStub::Some(set) => {
+                set.insert(function.to_owned());
+            }
+        }
+    }
+
     fn should_stub(&self, module: &str, function: &str) -> bool {
         if let Some(functions) = self.modules.get(module) {
             match functions {
                 FunctionsToStub::All => true,
-                FunctionsToStub::Some(functions) => functions.contains(function),
+                FunctionsToStub::Some(functions) => functions
+                    .iter()
+                    .any(|pattern| name_matches(pattern, function)),
             }
         } else {
             false
@@ -54,6 +247,13 @@ impl ShouldStub {
     }
 }
 
+fn name_matches(pattern: &str, name: &str) -> bool {
+    match pattern.strip_suffix('*') {
+        Some(prefix) => name.starts_with(prefix),
+        None => pattern == name,
+    }
+}
+
 fn static_id(id: Option<Id>) -> Option<Id<'static>> {
     id.map(|id| {
         let mut name = id.name().to_owned();
@@ -69,217 +269,691 @@ fn static_name_annotation(name: Option<NameAnnotation>) -> Option<NameAnnotation
         name: String::from(name.name).leak(),
     })
 }
+// FIXME: This long match dance is _only_ to make the lifetime of types 'static. A lot of things have to go through this dance (see the `static_*` function...)
+// Instead, we should write the new item in `stub_wasi_functions`, in place, by replacing `field`. This is currently done in the for loop at the veryend of this function.
+// THEN, at the end of the loop, swap every item in it's right place. No need to do more !
+fn static_val_type(val_type: &ValType) -> ValType<'static> {
+    match val_type {
+        ValType::I32 => ValType::I32,
+        ValType::I64 => ValType::I64,
+        ValType::F32 => ValType::F32,
+        ValType::F64 => ValType::F64,
+        ValType::V128 => ValType::V128,
+        ValType::Ref(r) => ValType::Ref(static_ref_type(r)),
+    }
+}
+fn static_ref_type(ref_type: &RefType) -> RefType<'static> {
+    RefType {
+        nullable: ref_type.nullable,
+        heap: match ref_type.heap {
+            HeapType::Concrete(index) => HeapType::Concrete(match index {
+                Index::Num(n, s) => Index::Num(n, s),
+                Index::Id(id) => Index::Id(static_id(Some(id)).unwrap()),
+            }),
+            HeapType::Abstract { shared, ty } => HeapType::Abstract { shared, ty },
+        },
+    }
+}
+
+/// Instruction producing `value` as a constant of type `ty`.
+///
+/// Reference types are initialized to `null`.
+fn const_instruction(ty: &ValType<'static>, value: i64) -> Instruction<'static> {
+    match ty {
+        ValType::I32 => Instruction::I32Const(value as i32),
+        ValType::I64 => Instruction::I64Const(value),
+        ValType::F32 => Instruction::F32Const(F32 {
+            bits: (value as f32).to_bits(),
+        }),
+        ValType::F64 => Instruction::F64Const(F64 {
+            bits: (value as f64).to_bits(),
+        }),
+        ValType::V128 => Instruction::V128Const(V128Const::I64x2([value, value])),
+        ValType::Ref(r) => Instruction::RefNull(r.heap),
+    }
+}
+
+fn remap_expression(expression: &mut Expression, funcs: &ImportIndices, globals: &ImportIndices) {
+    for inst in expression.instrs.iter_mut() {
+        match inst {
+            Instruction::RefFunc(index)
+            | Instruction::ReturnCall(index)
+            | Instruction::Call(index) => funcs.remap(index),
+            Instruction::GlobalGet(index) | Instruction::GlobalSet(index) => globals.remap(index),
+            _ => {}
+        }
+    }
+}
+
+/// A module printed in the WebAssembly text format, to edit it with `wast`.
+struct Printed {
+    text: String,
+    /// Offset in `text` of each line, with the offset in the binary of what it prints.
+    lines: Vec<(usize, Option<usize>)>,
+}
 
+impl wasmprinter::Print for Printed {
+    fn write_str(&mut self, s: &str) -> std::io::Result<()> {
+        self.text.push_str(s);
+        Ok(())
+    }
+
+    fn start_line(&mut self, binary_offset: Option<usize>) {
+        self.lines.push((self.text.len(), binary_offset));
+    }
+}
+
+impl Printed {
+    /// An error in the text, at the offset in the binary of the closest line before
+    /// it that has one.
+    fn error(&self, err: wast::Error) -> Error {
+        let line = self
+            .lines
+            .partition_point(|(start, _)| *start <= err.span().offset());
+        Error::Parse {
+            message: err.message(),
+            offset: self.lines[..line]
+                .iter()
+                .rev()
+                .find_map(|(_, offset)| *offset),
+        }
+    }
+}
+
+/// Print `binary` in the WebAssembly text format, to edit it with `wast`.
+fn print_wat(binary: &[u8]) -> crate::Result<Printed> {
+    let mut printed = Printed {
+        text: String::new(),
+        lines: Vec::new(),
+    };
+    wasmprinter::Config::new()
+        .print(binary, &mut printed)
+        .map_err(
+            |err| match err.downcast_ref::<wasmparser::BinaryReaderError>() {
+                Some(err) => Error::from(err.clone()),
+                None => Error::Parse {
+                    message: err.to_string(),
+                    offset: None,
+                },
+            },
+        )?;
+    Ok(printed)
+}
+
+/// Parse instructions written in the WebAssembly text format. The offset of an error
+/// is in `text`.
+fn parse_instructions(text: String) -> crate::Result<Vec<Instruction<'static>>> {
+    let error = |err: wast::Error| Error::Parse {
+        message: err.message(),
+        offset: Some(err.span().offset()),
+    };
+    let parser = Box::leak(Box::new(
+        wast::parser::ParseBuffer::new(text.leak()).map_err(error)?,
+    ));
+    let expression: Expression<'static> = wast::parser::parse(parser).map_err(error)?;
+    Ok(expression.instrs.into_vec())
+}
+
+/// Instructions of a stubbed function with the given signature.
+fn stub_instructions(
+    import: &str,
+    stub: &FunctionStub,
+    params: &[ValType<'static>],
+    results: &[ValType<'static>],
+) -> crate::Result<Vec<Instruction<'static>>> {
+    Ok(match stub {
+        FunctionStub::Return(value) => results
+            .iter()
+            .map(|ty| const_instruction(ty, *value))
+            .collect(),
+        FunctionStub::Trap => vec![Instruction::Unreachable],
+        FunctionStub::Body(body) => parse_instructions(body.clone()).map_err(|err| match err {
+            Error::Parse { message, offset } => Error::InvalidStub {
+                import: import.to_owned(),
+                message,
+                offset,
+            },
+            err => err,
+        })?,
+        FunctionStub::CallIndirect => {
+            let Some((ValType::I32, forwarded)) = params.split_first() else {
+                return Err(Error::InvalidStub {
+                    import: import.to_owned(),
+                    message:
+                        "call_indirect stubs must take the function index as their first parameter"
+                            .to_owned(),
+                    offset: None,
+                });
+            };
+            let mut instructions: Vec<_> = (1..params.len() as u32)
+                .map(|i| Instruction::LocalGet(Index::Num(i, wast::token::Span::from_offset(0))))
+                .collect();
+            instructions.push(Instruction::LocalGet(Index::Num(
+                0,
+                wast::token::Span::from_offset(0),
+            )));
+            instructions.push(Instruction::CallIndirect(Box::new(CallIndirect {
+                table: Index::Num(0, wast::token::Span::from_offset(0)),
+                ty: TypeUse {
+                    index: None,
+                    inline: Some(FunctionType {
+                        params: forwarded.iter().map(|ty| (None, None, *ty)).collect(),
+                        results: results.into(),
+                    }),
+                },
+            })));
+            instructions
+        }
+    })
+}
+
+/// Same as [`remap_expression`], for constant expressions.
+///
+/// Before WebAssembly 2.0, constant expressions may only read imported globals: reads
+/// of stubbed globals are replaced by their value.
+fn remap_const_expression(
+    expression: &mut Expression,
+    funcs: &ImportIndices,
+    globals: &ImportIndices,
+    global_values: &[Instruction<'static>],
+) {
+    for inst in expression.instrs.iter_mut() {
+        if let Instruction::GlobalGet(Index::Num(index, _)) = inst {
+            if let Some(ImportIndex::ToStub(idx)) = globals.indices.get(*index as usize) {
+                *inst = global_values[*idx as usize].clone();
+            }
+        }
+    }
+    remap_expression(expression, funcs, globals)
+}
+
+/// Insert `new_fields` before the first field matching `same_kind`, or at the end
+/// of the module if there is no such field.
+fn insert_fields<'a>(
+    fields: &mut Vec<ModuleField<'a>>,
+    new_fields: Vec<ModuleField<'a>>,
+    same_kind: impl Fn(&ModuleField) -> bool,
+) {
+    let index = fields.iter().position(same_kind).unwrap_or(fields.len());
+    fields.splice(index..index, new_fields);
+}
+
+/// Insert `new_fields` after the last field matching `same_kind`, or at the end of
+/// the module if there is no such field: the indices of existing items do not change.
+fn insert_after_last<'a>(
+    fields: &mut Vec<ModuleField<'a>>,
+    new_fields: Vec<ModuleField<'a>>,
+    same_kind: impl Fn(&ModuleField) -> bool,
+) {
+    let index = fields
+        .iter()
+        .rposition(same_kind)
+        .map_or(fields.len(), |i| i + 1);
+    fields.splice(index..index, new_fields);
+}
+
+/// Replace the imports selected by `should_stub` with definitions inside the module.
+///
+/// Functions that have no entry in [`StubValues::functions`] return `return_value`.
+///
+/// The WASI interfaces imported by the module (`wasi:cli/environment@0.2.0`, ...) are
+/// stubbed too. If `binary` is a component, its main core module is stubbed instead:
+/// the result is then a core module.
 pub fn stub_wasi_functions(
     binary: &[u8],
-    should_stub: ShouldStub,
+    mut should_stub: ShouldStub,
+    stub_values: StubValues,
     return_value: u32,
 ) -> crate::Result<Vec<u8>> {
-    let wat = wasmprinter::print_bytes(binary).map_err(std::io::Error::other)?;
-    let parse_buffer = wast::parser::ParseBuffer::new(&wat)?;
+    let binary = if wasmparser::Parser::is_component(binary) {
+        diagnostics::emit("Extracting the main core module of the component".to_owned());
+        component::main_module(binary)?
+    } else {
+        binary
+    };
+    for interface in component::wasi_interfaces(binary)? {
+        should_stub.modules.insert(interface, FunctionsToStub::All);
+    }
+    let printed = print_wat(binary)?;
+    let parse_buffer =
+        wast::parser::ParseBuffer::new(&printed.text).map_err(|err| printed.error(err))?;
 
-    let mut wat: Wat = wast::parser::parse(&parse_buffer)?;
+    let mut wat: Wat = wast::parser::parse(&parse_buffer).map_err(|err| printed.error(err))?;
     let module = match &mut wat {
         Wat::Module(m) => m,
-        Wat::Component(_) => return Err(Error::message("components are not supported")),
-    };
-    let fields = match &mut module.kind {
-        ModuleKind::Text(f) => f,
-        ModuleKind::Binary(_) => {
-            println!("[WARNING] binary directives are not supported");
-            return Ok(binary.to_owned());
+        Wat::Component(_) => {
+            return Err(Error::UnsupportedComponent(
+                "nested components are not supported".to_owned(),
+            ))
         }
     };
+    let ModuleKind::Text(fields) = &mut module.kind else {
+        return Err(Error::BinaryDirectives);
+    };
 
     let mut types = Vec::new();
-    let mut imports = Vec::new();
     let mut to_stub = Vec::new();
-    let mut insert_stubs_index = None;
-    let mut new_import_indices = Vec::new();
+    let mut funcs = ImportIndices::default();
+    let mut globals = ImportIndices::default();
+    let mut memories = ImportIndices::default();
+    let mut tables = ImportIndices::default();
+    let mut global_values = Vec::new();
+    let mut exports_memory = false;
 
-    for (field_idx, field) in fields.iter_mut().enumerate() {
+    for (field_idx, field) in fields.iter().enumerate() {
         match field {
             ModuleField::Type(t) => types.push(t),
+            ModuleField::Export(e) => exports_memory |= e.kind == ExportKind::Memory,
+            ModuleField::Memory(m) => exports_memory |= !m.exports.names.is_empty(),
             ModuleField::Import(i) => {
-                let typ = match &i.item.kind {
-                    ItemKind::Func(typ) => typ.index.and_then(|index| match index {
-                        Index::Num(index, _) => Some(index as usize),
-                        Index::Id(_) => None,
-                    }),
-                    _ => None,
-                };
-                let new_index = match typ {
-                    Some(type_index) if should_stub.should_stub(i.module, i.field) => {
-                        println!("Stubbing function {}::{}", i.module, i.field);
-                        let typ = &types[type_index];
-                        let ty = TypeUse::new_with_index(Index::Num(type_index as u32, typ.span));
-                        let wast::core::TypeDef {
-                            kind: InnerTypeKind::Func(func_typ),
-                            ..
-                        } = &typ.def
-                        else {
-                            continue;
-                        };
-                        let id = static_id(i.item.id);
-                        let locals: Vec<Local> = func_typ
-                            .params
-                            .iter()
-                            .map(|(id, name, val_type)| Local {
-                                id: static_id(*id),
-                                name: static_name_annotation(*name),
-                                // FIXME: This long match dance is _only_ to make the lifetime of ty 'static. A lot of things have to go through this dance (see the `static_*` function...)
-                                // Instead, we should write the new function here, in place, by replacing `field`. This is currently done in the for loop at the veryend of this function.
-                                // THEN, at the end of the loop, swap every function in it's right place. No need to do more !
-                                ty: match val_type {
-                                    ValType::I32 => ValType::I32,
-                                    ValType::I64 => ValType::I64,
-                                    ValType::F32 => ValType::F32,
-                                    ValType::F64 => ValType::F64,
-                                    ValType::V128 => ValType::V128,
-                                    ValType::Ref(r) => ValType::Ref(RefType {
-                                        nullable: r.nullable,
-                                        heap: match r.heap {
-                                            HeapType::Concrete(index) => {
-                                                HeapType::Concrete(match index {
-                                                    Index::Num(n, s) => Index::Num(n, s),
-                                                    Index::Id(id) => {
-                                                        Index::Id(static_id(Some(id)).unwrap())
-                                                    }
-                                                })
-                                            }
-                                            HeapType::Abstract { shared, ty } => {
-                                                HeapType::Abstract { shared, ty }
-                                            }
-                                        },
-                                    }),
-                                },
+                let stub = should_stub.should_stub(i.module, i.field);
+                let key = || (i.module.to_owned(), i.field.to_owned());
+                let kind = match &i.item.kind {
+                    ItemKind::Func(typ) if stub => {
+                        let func_typ = typ
+                            .index
+                            .and_then(|index| match index {
+                                Index::Num(index, _) => Some(index as usize),
+                                Index::Id(_) => None,
                             })
-                            .collect();
-                        to_stub.push(ToStub {
-                            fields_index: field_idx,
-                            span: i.span,
-                            nb_results: func_typ.results.len(),
-                            ty,
-                            name: i.item.name.map(|n| NameAnnotation {
-                                name: n.name.to_owned().leak(),
-                            }),
-                            id,
-                            locals,
-                        });
-                        ImportIndex::ToStub(to_stub.len() as u32 - 1)
+                            .and_then(|type_index| {
+                                let typ = &types[type_index];
+                                match &typ.def.kind {
+                                    InnerTypeKind::Func(func_typ) => {
+                                        Some((type_index, typ.span, func_typ))
+                                    }
+                                    _ => None,
+                                }
+                            });
+                        func_typ.map(|(type_index, span, func_typ)| StubKind::Func {
+                            ty: TypeUse::new_with_index(Index::Num(type_index as u32, span)),
+                            params: func_typ
+                                .params
+                                .iter()
+                                .map(|(_, _, val_type)| static_val_type(val_type))
+                                .collect(),
+                            results: func_typ.results.iter().map(static_val_type).collect(),
+                            locals: func_typ
+                                .params
+                                .iter()
+                                .map(|(id, name, val_type)| Local {
+                                    id: static_id(*id),
+                                    name: static_name_annotation(*name),
+                                    ty: static_val_type(val_type),
+                                })
+                                .collect(),
+                            stub: stub_values
+                                .function_stub(i.module, i.field)
+                                .cloned()
+                                .unwrap_or(FunctionStub::Return(return_value as i64)),
+                        })
                     }
-                    _ => {
-                        imports.push(i);
-                        ImportIndex::Keep(imports.len() as u32 - 1)
+                    ItemKind::Global(ty) if stub => Some(StubKind::Global {
+                        ty: GlobalType {
+                            ty: static_val_type(&ty.ty),
+                            mutable: ty.mutable,
+                            shared: ty.shared,
+                        },
+                        value: match stub_values.globals.get(&key()) {
+                            None => const_instruction(&static_val_type(&ty.ty), 0),
+                            Some(GlobalValue::Int(value)) => {
+                                const_instruction(&static_val_type(&ty.ty), *value)
+                            }
+                            Some(GlobalValue::Float(value)) => match ty.ty {
+                                ValType::F32 => Instruction::F32Const(F32 {
+                                    bits: (*value as f32).to_bits(),
+                                }),
+                                ValType::F64 => Instruction::F64Const(F64 {
+                                    bits: value.to_bits(),
+                                }),
+                                _ => {
+                                    return Err(Error::message(format!(
+                                        "the global {}::{} is not a float, it cannot be set to {value:?}",
+                                        i.module, i.field
+                                    )))
+                                }
+                            },
+                        },
+                    }),
+                    ItemKind::Memory(ty) if stub => {
+                        let mut ty = *ty;
+                        if let Some(limits) = stub_values.memories.get(&key()) {
+                            ty.limits.min = limits.min;
+                            ty.limits.max = limits.max;
+                        }
+                        Some(StubKind::Memory(ty))
+                    }
+                    ItemKind::Table(ty) if stub => {
+                        let mut limits = ty.limits;
+                        if let Some(new_limits) = stub_values.tables.get(&key()) {
+                            limits.min = new_limits.min;
+                            limits.max = new_limits.max;
+                        }
+                        Some(StubKind::Table(TableType {
+                            limits,
+                            elem: static_ref_type(&ty.elem),
+                            shared: ty.shared,
+                        }))
                     }
+                    _ => None,
+                };
+                let (indices, kind_name) = match &i.item.kind {
+                    ItemKind::Func(_) => (&mut funcs, "function"),
+                    ItemKind::Global(_) => (&mut globals, "global"),
+                    ItemKind::Memory(_) => (&mut memories, "memory"),
+                    ItemKind::Table(_) => (&mut tables, "table"),
+                    ItemKind::Tag(_) => continue,
                 };
-                new_import_indices.push(new_index);
+                indices.push(kind.is_some());
+                if let Some(StubKind::Global { value, .. }) = &kind {
+                    global_values.push(value.clone());
+                }
+                if let Some(kind) = kind {
+                    diagnostics::emit(format!("Stubbing {kind_name} {}::{}", i.module, i.field));
+                    to_stub.push(ToStub {
+                        fields_index: field_idx,
+                        import: format!("{}::{}", i.module, i.field),
+                        span: i.span,
+                        name: static_name_annotation(i.item.name),
+                        id: static_id(i.item.id),
+                        kind,
+                    });
+                }
+            }
+            _ => {}
+        }
+    }
+    drop(types);
+
+    if !memories.is_identity() || !tables.is_identity() {
+        return Err(Error::UnsupportedModule(
+            "stubbing a memory or table that is imported before a kept memory or table is not supported".to_owned(),
+        ));
+    }
+
+    for field in fields.iter_mut() {
+        match field {
+            ModuleField::Func(func) => match &mut func.kind {
+                FuncKind::Import(f) => {
+                    if should_stub.should_stub(f.module, f.field) {
+                        diagnostics::emit(
+                            "[WARNING] Stubbing inline function is not yet supported".to_owned(),
+                        );
+                        diagnostics::emit(format!(
+                            "[WARNING] ignoring inline function \"{}\" \"{}\"",
+                            f.module, f.field
+                        ));
+                    }
+                }
+                FuncKind::Inline { expression, .. } => {
+                    remap_expression(expression, &funcs, &globals)
+                }
+            },
+            ModuleField::Global(global) => {
+                if let GlobalKind::Inline(expression) = &mut global.kind {
+                    remap_const_expression(expression, &funcs, &globals, &global_values)
+                }
+            }
+            ModuleField::Table(table) => {
+                if let TableKind::Normal {
+                    init_expr: Some(expression),
+                    ..
+                } = &mut table.kind
+                {
+                    remap_const_expression(expression, &funcs, &globals, &global_values)
+                }
             }
-            ModuleField::Func(func) => {
-                if insert_stubs_index.is_none() {
-                    insert_stubs_index = Some(field_idx);
+            ModuleField::Elem(elem) => {
+                if let ElemKind::Active { offset, .. } = &mut elem.kind {
+                    remap_const_expression(offset, &funcs, &globals, &global_values)
                 }
-                match &mut func.kind {
-                    FuncKind::Import(f) => {
-                        if should_stub.should_stub(f.module, f.field) {
-                            println!("[WARNING] Stubbing inline function is not yet supported");
-                            println!(
-                                "[WARNING] ignoring inline function \"{}\" \"{}\"",
-                                f.module, f.field
-                            );
+                match &mut elem.payload {
+                    ElemPayload::Indices(indices) => {
+                        for index in indices {
+                            funcs.remap(index)
                         }
                     }
-                    FuncKind::Inline { expression, .. } => {
-                        for inst in expression.instrs.as_mut().iter_mut() {
-                            match inst {
-                                Instruction::RefFunc(Index::Num(index, _))
-                                | Instruction::ReturnCall(Index::Num(index, _))
-                                | Instruction::Call(Index::Num(index, _)) => {
-                                    if let Some(new_index) = new_import_indices.get(*index as usize)
-                                    {
-                                        *index = match new_index {
-                                            ImportIndex::ToStub(idx) => *idx + imports.len() as u32,
-                                            ImportIndex::Keep(idx) => *idx,
-                                        };
-                                    }
-                                }
-                                _ => {}
-                            }
+                    ElemPayload::Exprs { exprs, .. } => {
+                        for expression in exprs {
+                            remap_const_expression(expression, &funcs, &globals, &global_values)
                         }
                     }
                 }
             }
+            ModuleField::Data(data) => {
+                if let DataKind::Active { offset, .. } = &mut data.kind {
+                    remap_const_expression(offset, &funcs, &globals, &global_values)
+                }
+            }
+            ModuleField::Export(export) => match export.kind {
+                ExportKind::Func => funcs.remap(&mut export.item),
+                ExportKind::Global => globals.remap(&mut export.item),
+                _ => {}
+            },
+            ModuleField::Start(index) => funcs.remap(index),
             _ => {}
         }
     }
-    drop(imports);
-    drop(types);
 
-    let insert_stubs_index = insert_stubs_index
-        .expect("This is weird: there are no code sections in this wasm executable !");
-
-    for (
-        already_stubbed,
-        ToStub {
-            fields_index,
-            span,
-            nb_results,
-            ty,
-            name,
-            id,
-            locals,
-        },
-    ) in to_stub.into_iter().enumerate()
+    let mut new_funcs = Vec::new();
+    let mut new_globals = Vec::new();
+    let mut new_memories = Vec::new();
+    let mut new_tables = Vec::new();
+    let mut stubbed_fields = Vec::new();
+    for ToStub {
+        fields_index,
+        import,
+        span,
+        name,
+        id,
+        kind,
+    } in to_stub
     {
-        let instructions = {
-            let mut res = Vec::with_capacity(nb_results);
-            for _ in 0..nb_results {
-                // Weird value, hopefully this makes it easier to track usage of these stubbed functions.
-                res.push(Instruction::I32Const(return_value as i32));
+        stubbed_fields.push(fields_index);
+        match kind {
+            StubKind::Func {
+                ty,
+                params,
+                results,
+                locals,
+                stub,
+            } => {
+                let instructions = stub_instructions(&import, &stub, &params, &results)?;
+                new_funcs.push(ModuleField::Func(Func {
+                    span,
+                    id,
+                    name,
+                    // no exports
+                    exports: InlineExport { names: Vec::new() },
+                    kind: FuncKind::Inline {
+                        locals: locals.into_boxed_slice(),
+                        expression: Expression {
+                            instrs: instructions.into_boxed_slice(),
+                            branch_hints: Box::new([]),
+                            instr_spans: None,
+                        },
+                    },
+                    ty,
+                }))
             }
-            res
-        };
-        let function = Func {
-            span,
-            id,
-            name,
-            // no exports
-            exports: InlineExport { names: Vec::new() },
-            kind: wast::core::FuncKind::Inline {
-                locals: locals.into_boxed_slice(),
-                expression: Expression {
-                    instrs: instructions.into_boxed_slice(),
+            StubKind::Global { ty, value } => new_globals.push(ModuleField::Global(Global {
+                span,
+                id,
+                name,
+                exports: InlineExport { names: Vec::new() },
+                kind: GlobalKind::Inline(Expression {
+                    instrs: Box::new([value]),
                     branch_hints: Box::new([]),
                     instr_spans: None,
+                }),
+                ty,
+            })),
+            StubKind::Memory(ty) => {
+                // Typst only accesses memory through the "memory" export.
+                let exports = if exports_memory {
+                    Vec::new()
+                } else {
+                    exports_memory = true;
+                    vec!["memory"]
+                };
+                new_memories.push(ModuleField::Memory(Memory {
+                    span,
+                    id,
+                    name,
+                    exports: InlineExport { names: exports },
+                    kind: MemoryKind::Normal(ty),
+                }))
+            }
+            StubKind::Table(ty) => new_tables.push(ModuleField::Table(Table {
+                span,
+                id,
+                name,
+                exports: InlineExport { names: Vec::new() },
+                kind: TableKind::Normal {
+                    ty,
+                    init_expr: None,
                 },
-            },
-            ty,
-        };
-        fields.insert(insert_stubs_index, ModuleField::Func(function));
-        fields.remove(fields_index - already_stubbed);
+            })),
+        }
+    }
+    for fields_index in stubbed_fields.into_iter().rev() {
+        fields.remove(fields_index);
+    }
+    insert_fields(fields, new_funcs, |f| matches!(f, ModuleField::Func(_)));
+    insert_fields(fields, new_globals, |f| matches!(f, ModuleField::Global(_)));
+    if funcs.stubbed > 0 && !stub_values.state.is_empty() {
+        let state = stub_values.state.into_iter().map(|(name, value)| {
+            let span = wast::token::Span::from_offset(0);
+            ModuleField::Global(Global {
+                span,
+                id: Some(Id::new(name.leak(), span)),
+                name: None,
+                exports: InlineExport { names: Vec::new() },
+                ty: GlobalType {
+                    ty: ValType::I64,
+                    mutable: true,
+                    shared: false,
+                },
+                kind: GlobalKind::Inline(Expression {
+                    instrs: Box::new([Instruction::I64Const(value)]),
+                    branch_hints: Box::new([]),
+                    instr_spans: None,
+                }),
+            })
+        });
+        insert_after_last(fields, state.collect(), |f| {
+            matches!(f, ModuleField::Global(_))
+        });
     }
+    insert_fields(fields, new_memories, |f| {
+        matches!(f, ModuleField::Memory(_))
+    });
+    insert_fields(fields, new_tables, |f| matches!(f, ModuleField::Table(_)));
 
-    Ok(module.encode()?)
+    module.encode().map_err(|err| printed.error(err))
 }
 
 // Error handling
-pub struct Error(Box<dyn std::error::Error + Send + Sync + 'static>);
+#[non_exhaustive]
+pub enum Error {
+    /// The input is not a valid WebAssembly binary, or its text format could not be
+    /// parsed back.
+    Parse {
+        message: String,
+        /// Offset of the error in the binary given to the function that failed, if
+        /// known. For a component, it is the offset in its main core module. Errors in
+        /// the text format are reported at the offset of the closest item before them.
+        offset: Option<usize>,
+    },
+    /// The component has no core module that can be stubbed.
+    UnsupportedComponent(String),
+    /// The module is valid, but uses something that cannot be edited.
+    UnsupportedModule(String),
+    /// The module is made of binary directives, that cannot be edited.
+    BinaryDirectives,
+    /// The stub of an import does not fit its type.
+    InvalidStub {
+        /// Import being stubbed, as `module::name`.
+        import: String,
+        message: String,
+        /// Offset of the error in the body of the stub, if known.
+        offset: Option<usize>,
+    },
+    /// The output is not a valid module.
+    Validation(ValidationError),
+    /// The init function of [`pre_initialize`] could not run.
+    PreInit(String),
+    /// The arguments given by the caller are invalid.
+    InvalidArgument(String),
+    Io(std::io::Error),
+}
 impl Error {
+    /// Shorthand for an [`Error::InvalidArgument`].
     pub fn message(reason: impl AsRef<str>) -> Self {
-        Self(Box::new(std::io::Error::other(reason.as_ref().to_string())))
+        Self::InvalidArgument(reason.as_ref().to_owned())
+    }
+}
+impl std::fmt::Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Self::Parse { message, offset } => {
+                write!(f, "{message}")?;
+                if let Some(offset) = offset {
+                    write!(f, " (at offset {offset})")?;
+                }
+                Ok(())
+            }
+            Self::UnsupportedComponent(reason) => write!(f, "unsupported component: {reason}"),
+            Self::UnsupportedModule(reason) => write!(f, "unsupported module: {reason}"),
+            Self::BinaryDirectives => write!(f, "binary directives are not supported"),
+            Self::InvalidStub {
+                import,
+                message,
+                offset,
+            } => {
+                write!(f, "invalid stub for {import}: {message}")?;
+                if let Some(offset) = offset {
+                    write!(f, " (at offset {offset})")?;
+                }
+                Ok(())
+            }
+            Self::Validation(err) => write!(f, "{err}"),
+            Self::PreInit(reason) => write!(f, "pre-initialization failed: {reason}"),
+            Self::InvalidArgument(reason) => write!(f, "{reason}"),
+            Self::Io(err) => write!(f, "{err}"),
+        }
     }
 }
 impl std::fmt::Debug for Error {
     fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
-        std::fmt::Display::fmt(&self.0, f)
+        std::fmt::Display::fmt(self, f)
+    }
+}
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Validation(err) => Some(err),
+            Self::Io(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+impl From<std::io::Error> for Error {
+    fn from(err: std::io::Error) -> Self {
+        Self::Io(err)
+    }
+}
+impl From<wasmparser::BinaryReaderError> for Error {
+    fn from(err: wasmparser::BinaryReaderError) -> Self {
+        Self::Parse {
+            message: err.message().to_owned(),
+            offset: Some(err.offset()),
+        }
     }
 }
-impl<E> From<E> for Error
-where
-    E: std::error::Error + Send + Sync + 'static,
-{
-    fn from(err: E) -> Self {
-        Self(Box::new(err))
+impl From<ValidationError> for Error {
+    fn from(err: ValidationError) -> Self {
+        Self::Validation(err)
     }
 }
 pub type Result<T> = std::result::Result<T, Error>;
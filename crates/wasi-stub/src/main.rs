@@ -1,39 +1,234 @@
 mod parse_args;
 
-use std::path::PathBuf;
-use wasi_stub::{stub_wasi_functions, Error, Result};
+use clap::{CommandFactory, Parser};
+use parse_args::{Cli, Command, Output, StubArgs};
+use std::{
+    io::{Read, Write},
+    path::{Path, PathBuf},
+    sync::{atomic::AtomicUsize, Mutex},
+};
+use wasi_stub::{
+    capture_diagnostics, command_to_reactor, diff, garbage_collect, inspect,
+    pre_initialize_with_fuel, stub_wasi_functions, validate, Error, Result, ShouldStub, StubValues,
+    WasmFeatures,
+};
+
+/// Path standing for stdin as an input, and for stdout as an output.
+const STDIO: &str = "-";
+
+/// Module of the functions provided by typst.
+const TYPST_ENV: &str = "typst_env";
 
 fn main() -> Result<()> {
-    let parse_args::Args {
-        binary,
-        path,
-        output_path,
-        list,
-        should_stub,
-        return_value,
-    } = parse_args::Args::new()?;
-
-    let output = stub_wasi_functions(&binary, should_stub, return_value)?;
-
-    if !list {
-        write_output(path, output_path, output)?;
+    let cli = Cli::parse();
+    let failed = match cli.command.unwrap_or(Command::Stub(cli.stub)) {
+        Command::Stub(args) => {
+            if args.output.is_some() && args.files.len() > 1 {
+                return Err(Error::message(
+                    "--output needs a single input file: use --in-place or --out-dir instead",
+                ));
+            }
+            let (should_stub, stub_values) = args.stub_config();
+            let output = args.output();
+            run_batch(&args.files, |path| {
+                stub(path, &args, &should_stub, &stub_values, &output)
+            })
+        }
+        Command::List { files, selection } => {
+            let (should_stub, stub_values) = selection.stub_config();
+            run_batch(&files, |path| {
+                stub_wasi_functions(
+                    &read_input(path)?,
+                    should_stub.clone(),
+                    stub_values.clone(),
+                    0,
+                )?;
+                Ok("ok".to_owned())
+            })
+        }
+        Command::Check { files, features } => {
+            let features = features.features();
+            run_batch(&files, |path| {
+                check(&read_input(path)?, features)?;
+                Ok("ok".to_owned())
+            })
+        }
+        Command::Inspect { file } => {
+            print!("{}", inspect(&read_input(&file)?)?);
+            false
+        }
+        Command::Diff { old, new, fail_on } => {
+            let (old, new) = (read_input(&old)?, read_input(&new)?);
+            let changes = diff(&inspect(&old)?, &inspect(&new)?);
+            for change in &changes {
+                println!("{change}");
+            }
+            eprintln!("total: {} => {} bytes", old.len(), new.len());
+            let failing: Vec<_> = changes
+                .iter()
+                .filter(|change| fail_on.contains(&change.kind()))
+                .collect();
+            if !failing.is_empty() {
+                eprintln!("error: {} changes matching --fail-on", failing.len());
+            }
+            !failing.is_empty()
+        }
+        Command::Completions { shell } => {
+            clap_complete::generate(
+                shell,
+                &mut Cli::command(),
+                "wasi-stub",
+                &mut std::io::stdout(),
+            );
+            false
+        }
+        Command::Man => {
+            clap_mangen::Man::new(Cli::command()).render(&mut std::io::stdout())?;
+            false
+        }
+    };
+    if failed {
+        std::process::exit(1);
+    }
+    Ok(())
+}
+
+/// The summary of a file, with the messages emitted while processing it.
+type FileResult = (Result<String>, Vec<String>);
+
+/// Run `process` on each file, and print a summary of each result, after the messages
+/// emitted for this file, prefixed by its path.
+///
+/// Returns `true` if any file failed.
+fn run_batch(paths: &[PathBuf], process: impl Fn(&Path) -> Result<String> + Sync) -> bool {
+    // Files are processed in parallel, but the summary keeps their order.
+    let results: Mutex<Vec<Option<FileResult>>> = Mutex::new(paths.iter().map(|_| None).collect());
+    let next = AtomicUsize::new(0);
+    let workers = std::thread::available_parallelism()
+        .map_or(1, |n| n.get())
+        .min(paths.len());
+    std::thread::scope(|scope| {
+        for _ in 0..workers {
+            scope.spawn(|| loop {
+                let index = next.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+                let Some(path) = paths.get(index) else {
+                    break;
+                };
+                let result = capture_diagnostics(|| process(path));
+                results.lock().unwrap()[index] = Some(result);
+            });
+        }
+    });
+
+    let mut failed = false;
+    for (path, result) in paths.iter().zip(results.into_inner().unwrap()) {
+        let (result, messages) = result.unwrap();
+        for message in messages {
+            eprintln!("{}: {message}", path.display());
+        }
+        match result {
+            Ok(summary) => eprintln!("{}: {summary}", path.display()),
+            Err(err) => {
+                failed = true;
+                eprintln!("{}: error: {err}", path.display());
+            }
+        }
+    }
+    failed
+}
+
+fn read_input(path: &Path) -> Result<Vec<u8>> {
+    if path == Path::new(STDIO) {
+        let mut binary = Vec::new();
+        std::io::stdin().read_to_end(&mut binary)?;
+        Ok(binary)
     } else {
-        println!("NOTE: no output produced because the '--list' option was specified")
+        Ok(std::fs::read(path)?)
     }
+}
+
+fn stub(
+    path: &Path,
+    args: &StubArgs,
+    should_stub: &ShouldStub,
+    stub_values: &StubValues,
+    output: &Output,
+) -> Result<String> {
+    let binary = read_input(path)?;
+    let mut contents = stub_wasi_functions(
+        &binary,
+        should_stub.clone(),
+        stub_values.clone(),
+        args.return_value,
+    )?;
+    if args.reactor {
+        contents = command_to_reactor(&contents)?;
+    }
+    if args.pre_init {
+        contents = pre_initialize_with_fuel(&contents, args.pre_init_fuel)?;
+    }
+    if args.gc {
+        contents = garbage_collect(&contents)?;
+    }
+    validate(&contents, args.features.features())?;
+
+    let output_path = write_output(path, output, &contents)?;
+    Ok(format!(
+        "{} -> {} bytes, written to {}",
+        binary.len(),
+        contents.len(),
+        output_path.display()
+    ))
+}
 
+/// Check that `binary` is valid, and that it only imports functions from typst.
+fn check(binary: &[u8], features: WasmFeatures) -> Result<()> {
+    validate(binary, features)?;
+    let mut imports = Vec::new();
+    for payload in wasmparser::Parser::new(0).parse_all(binary) {
+        if let wasmparser::Payload::ImportSection(reader) = payload? {
+            for import in reader {
+                let import = import?;
+                if import.module != TYPST_ENV {
+                    imports.push(format!("{}::{}", import.module, import.name));
+                }
+            }
+        }
+    }
+    if !imports.is_empty() {
+        return Err(Error::message(format!(
+            "imports not provided by typst: {}",
+            imports.join(", ")
+        )));
+    }
     Ok(())
 }
 
-fn write_output(path: PathBuf, output_path: Option<PathBuf>, output: Vec<u8>) -> Result<()> {
-    let output_path = match output_path {
-        Some(p) => p,
+fn write_output(path: &Path, output: &Output, contents: &[u8]) -> Result<PathBuf> {
+    let from_stdin = path == Path::new(STDIO);
+    let output_path = match output {
+        Output::Path(p) => p.clone(),
+        Output::Default if from_stdin => PathBuf::from(STDIO),
+        Output::InPlace | Output::Dir(_) if from_stdin => {
+            return Err(Error::message(
+                "stdin can only be written to a file given with --output, or to stdout",
+            ))
+        }
+        Output::InPlace => path.to_owned(),
+        Output::Dir(dir) => {
+            std::fs::create_dir_all(dir)?;
+            let file_name = path
+                .file_name()
+                .ok_or_else(|| Error::message(format!("Not a file: {}", path.display())))?;
+            dir.join(file_name)
+        }
         // Try to find an unused output path
-        None => {
+        Output::Default => {
             let mut i = 0;
             let mut file_name = path.file_stem().unwrap().to_owned();
             file_name.push(" - stubbed.wasm");
             loop {
-                let mut new_path = path.clone();
+                let mut new_path = path.to_owned();
                 if i > 0 {
                     let mut file_name = path.file_stem().unwrap().to_owned();
                     file_name.push(format!(" - stubbed ({i}).wasm"));
@@ -48,8 +243,16 @@ fn write_output(path: PathBuf, output_path: Option<PathBuf>, output: Vec<u8>) ->
             }
         }
     };
-    std::fs::write(&output_path, output)?;
-    let permissions = std::fs::File::open(path)?.metadata()?.permissions();
-    std::fs::File::open(output_path)?.set_permissions(permissions)?;
-    Ok(())
+    if output_path == Path::new(STDIO) {
+        let mut stdout = std::io::stdout().lock();
+        stdout.write_all(contents)?;
+        stdout.flush()?;
+        return Ok(output_path);
+    }
+    std::fs::write(&output_path, contents)?;
+    if !from_stdin {
+        let permissions = std::fs::metadata(path)?.permissions();
+        std::fs::File::open(&output_path)?.set_permissions(permissions)?;
+    }
+    Ok(output_path)
 }
@@ -1,328 +1,271 @@
-use crate::Error;
-use std::{
-    collections::{HashMap, HashSet},
-    ffi::OsString,
-    path::PathBuf,
+use clap::{builder::PossibleValuesParser, builder::TypedValueParser, Args, Parser, Subcommand};
+use std::path::PathBuf;
+use wasi_stub::{
+    ChangeKind, FunctionsToStub, GlobalValue, Limits, Preset, ShouldStub, StubValues, WasmFeatures,
+    INIT_FUEL,
 };
-use wasi_stub::{FunctionsToStub, ShouldStub};
 
-pub(crate) struct Args {
-    pub binary: Vec<u8>,
-    pub path: PathBuf,
-    pub output_path: Option<PathBuf>,
-    pub list: bool,
-    pub should_stub: ShouldStub,
-    pub return_value: u32,
+/// A command to replace wasi functions with stubs. The stubbed function can still be
+/// called, but they won't have any side-effect, and will simply return dummy values.
+///
+/// Without a subcommand, the arguments are those of `wasi-stub stub`.
+#[derive(Parser)]
+#[command(name = "wasi-stub", version, args_conflicts_with_subcommands = true)]
+pub(crate) struct Cli {
+    #[command(subcommand)]
+    pub command: Option<Command>,
+    #[command(flatten)]
+    pub stub: StubArgs,
 }
 
-enum Arg {
-    Plain {
-        name: &'static str,
-        required: bool,
-        help: &'static str,
+#[derive(Subcommand)]
+pub(crate) enum Command {
+    /// Stub the imports of wasm files (this is the default command).
+    Stub(StubArgs),
+    /// List the functions to stub, but don't write anything.
+    List {
+        /// Input wasm files. Use - to read from stdin.
+        #[arg(required = true)]
+        files: Vec<PathBuf>,
+        #[command(flatten)]
+        selection: Selection,
+    },
+    /// Check that wasm files are valid, and only import functions of the plugin
+    /// protocol.
+    Check {
+        /// Input wasm files. Use - to read from stdin.
+        #[arg(required = true)]
+        files: Vec<PathBuf>,
+        #[command(flatten)]
+        features: Features,
     },
-    LongFlag {
-        name: &'static str,
-        help: &'static str,
+    /// Print a summary of a module: its exports (and which ones look like protocol
+    /// functions), its imports, memories, custom sections and producers.
+    Inspect {
+        /// Input wasm file. Use - to read from stdin.
+        file: PathBuf,
     },
-    #[allow(dead_code)]
-    ShortFlag { flag: char, help: &'static str },
-    KeyValue {
-        keys: &'static [&'static str],
-        value_type: &'static str,
-        help: &'static str,
+    /// Compare the imports, exports and section sizes of two builds of a plugin.
+    Diff {
+        /// The previous build. Use - to read from stdin.
+        old: PathBuf,
+        /// The new build. Use - to read from stdin.
+        new: PathBuf,
+        /// Exit with an error if there are changes of these kinds.
+        #[arg(long, value_name = "KIND", value_delimiter = ',', value_parser = named_parser(ChangeKind::ALL, ChangeKind::name))]
+        fail_on: Vec<ChangeKind>,
     },
+    /// Print a completion script for the given shell.
+    Completions { shell: clap_complete::Shell },
+    /// Print the man page, in the roff format.
+    Man,
 }
 
-struct TestArgParser {
-    command_name: &'static str,
-    help: &'static str,
-    args: Vec<Arg>,
-    plain_args: HashMap<String, OsString>,
-    long_flags: HashSet<String>,
-    short_flags: HashSet<char>,
-    key_values: HashMap<String, OsString>,
-    requested_help: bool,
+#[derive(Args)]
+pub(crate) struct StubArgs {
+    /// Input wasm files. Use - to read from stdin.
+    #[arg(required = true)]
+    pub files: Vec<PathBuf>,
+    /// Specify the output path. Only allowed with a single input file. Use - to write
+    /// to stdout (the default when reading from stdin).
+    #[arg(short, long, value_name = "PATH", conflicts_with_all = ["in_place", "out_dir"])]
+    pub output: Option<PathBuf>,
+    /// Overwrite each input file with its output.
+    #[arg(long, conflicts_with = "out_dir")]
+    pub in_place: bool,
+    /// Write the output of each input file in this directory, with the same file name.
+    #[arg(long, value_name = "PATH")]
+    pub out_dir: Option<PathBuf>,
+    #[command(flatten)]
+    pub selection: Selection,
+    /// Initial value of a stubbed global. It must have the format 'module:global=value',
+    /// where the value is an integer, or a float for f32 and f64 globals.
+    ///
+    /// Example: wasi-stub input.wasm --stub-module env --global-value env:__stack_pointer=65536
+    ///
+    /// By default, stubbed globals are initialized to 0.
+    #[arg(long, value_name = "MODULE:GLOBAL=VALUE", value_delimiter = ',', value_parser = parse_item_value)]
+    pub global_value: Vec<((String, String), GlobalValue)>,
+    /// Limits (in pages) of a stubbed memory. It must have the format 'module:memory=min'
+    /// or 'module:memory=min:max'.
+    ///
+    /// By default, stubbed memories keep the limits of their import.
+    #[arg(long, value_name = "MODULE:MEMORY=MIN[:MAX]", value_delimiter = ',', value_parser = parse_item_limits)]
+    pub memory_limits: Vec<((String, String), Limits)>,
+    /// Limits (in elements) of a stubbed table. It must have the format 'module:table=min'
+    /// or 'module:table=min:max'.
+    ///
+    /// By default, stubbed tables keep the limits of their import.
+    #[arg(long, value_name = "MODULE:TABLE=MIN[:MAX]", value_delimiter = ',', value_parser = parse_item_limits)]
+    pub table_limits: Vec<((String, String), Limits)>,
+    /// Make all stubbed function that return values return this number.
+    // Weird value, hopefully this makes it easier to track usage of these stubbed functions.
+    #[arg(short, long, value_name = "INTEGER", default_value_t = 76)]
+    pub return_value: u32,
+    /// Turn a WASI command into a reactor: the static constructors (`_initialize` or
+    /// `__wasm_call_ctors`) run before the first call to a protocol function, and
+    /// `_start` is no longer exported.
+    #[arg(long)]
+    pub reactor: bool,
+    /// Run the exported `wasm_minimal_protocol_init` function in an interpreter, and
+    /// store the resulting memory and globals in the output: the plugin starts already
+    /// initialized.
+    #[arg(long)]
+    pub pre_init: bool,
+    /// Fuel available to `wasm_minimal_protocol_init` with --pre-init: roughly, the
+    /// number of instructions it can execute before it is considered to loop forever.
+    #[arg(long, value_name = "FUEL", default_value_t = INIT_FUEL)]
+    pub pre_init_fuel: u64,
+    /// Remove the functions, globals and data segments that cannot be reached from the
+    /// exports, the start function or the tables.
+    #[arg(long)]
+    pub gc: bool,
+    #[command(flatten)]
+    pub features: Features,
 }
-impl TestArgParser {
-    fn new(command_name: &'static str, help: &'static str, args: Vec<Arg>) -> Self {
-        Self {
-            command_name,
-            help,
-            args,
-            plain_args: Default::default(),
-            long_flags: Default::default(),
-            short_flags: Default::default(),
-            key_values: Default::default(),
-            requested_help: false,
-        }
-    }
 
-    fn parse(&mut self) -> Result<(), String> {
-        let mut expect_long_flags = HashSet::new();
-        let mut expect_short_flags = HashSet::new();
-        let mut expect_keys = HashSet::new();
-        let mut remaining_plain_args: Vec<_> = self
-            .args
-            .iter()
-            .filter_map(|a| {
-                match a {
-                    Arg::Plain { name, required, .. } => return Some((*name, *required)),
-                    Arg::LongFlag { name, .. } => {
-                        expect_long_flags.insert(*name);
-                    }
-                    Arg::ShortFlag { flag, .. } => {
-                        expect_short_flags.insert(*flag);
-                    }
-                    Arg::KeyValue { keys, .. } => {
-                        for key in *keys {
-                            expect_keys.insert(*key);
-                        }
-                    }
-                }
-                None
-            })
-            .collect();
+/// Which imports to stub.
+#[derive(Args)]
+pub(crate) struct Selection {
+    /// Stub the given module.
+    #[arg(long, value_name = "MODULE", value_delimiter = ',')]
+    pub stub_module: Vec<String>,
+    /// Stub the given function. It must have the format 'module:function'.
+    ///
+    /// Example: wasi-stub input.wasm --stub-function horrible_module:terrible_function
+    ///
+    /// A function name ending with '*' stubs all functions starting with this prefix.
+    #[arg(long, value_name = "MODULE:FUNCTION", value_delimiter = ',', value_parser = parse_function)]
+    pub stub_function: Vec<(String, String)>,
+    /// Stub the imports generated by a toolchain, with appropriate behaviours.
+    #[arg(long, value_delimiter = ',', value_parser = named_parser(Preset::ALL, Preset::name))]
+    pub preset: Vec<Preset>,
+}
 
-        let mut current_key = None;
-        for arg in std::env::args_os().skip(1) {
-            if let Some(key) = current_key.take() {
-                self.key_values.insert(key, arg);
-                continue;
-            }
-            let arg = match arg.to_str() {
-                Some(a) => a,
-                None => {
-                    if let Some(plain) = remaining_plain_args.pop() {
-                        self.plain_args.insert(plain.0.to_owned(), arg);
-                        continue;
-                    } else {
-                        return Err(format!("Unexpected argument: '{arg:?}'"));
-                    }
-                }
-            };
-            if arg == "--help" || arg == "-h" {
-                self.requested_help = true;
-                continue;
-            }
-            if expect_long_flags.contains(arg) {
-                self.long_flags.insert(arg.to_owned());
-            } else if expect_keys.contains(arg) {
-                current_key = Some(arg.to_owned());
-            } else if arg.starts_with('-') {
-                for c in arg.chars().skip(1) {
-                    if expect_short_flags.contains(&c) {
-                        self.short_flags.insert(c);
-                    } else {
-                        return Err(format!("Unknown short option: {c}"));
-                    }
-                }
-            } else if let Some(plain) = remaining_plain_args.pop() {
-                self.plain_args.insert(plain.0.to_owned(), arg.into());
-            } else {
-                return Err(format!("Unexpected argument: '{arg}'"));
-            }
-        }
-        if !self.requested_help {
-            for (plain_arg, required) in remaining_plain_args {
-                if required {
-                    return Err(format!("Missing argument {plain_arg}"));
-                }
-            }
+/// WebAssembly features used to validate modules.
+#[derive(Args)]
+pub(crate) struct Features {
+    /// WebAssembly features allowed in the output, in addition to the default ones (for
+    /// example 'memory64'). The output is validated before being written.
+    #[arg(long, value_name = "FEATURE", value_delimiter = ',', value_parser = parse_feature)]
+    pub enable_features: Vec<WasmFeatures>,
+    /// WebAssembly features forbidden in the output (for example 'simd,threads').
+    #[arg(long, value_name = "FEATURE", value_delimiter = ',', value_parser = parse_feature)]
+    pub disable_features: Vec<WasmFeatures>,
+}
+
+/// Where to write the output of each input file.
+pub(crate) enum Output {
+    /// Next to the input, with an unused name.
+    Default,
+    Path(PathBuf),
+    InPlace,
+    Dir(PathBuf),
+}
+
+impl StubArgs {
+    pub fn output(&self) -> Output {
+        if let Some(path) = &self.output {
+            Output::Path(path.clone())
+        } else if self.in_place {
+            Output::InPlace
+        } else if let Some(dir) = &self.out_dir {
+            Output::Dir(dir.clone())
+        } else {
+            Output::Default
         }
-        Ok(())
     }
 
-    fn print_help_message(&self) {
-        println!("{} {}", self.command_name, env!("CARGO_PKG_VERSION"));
-        println!();
-        println!("{}", self.help);
-        println!();
-        println!("USAGE:");
-        print!("    {} [OPTIONS]", self.command_name);
-        for (name, required) in self.args.iter().filter_map(|a| match a {
-            Arg::Plain { name, required, .. } => Some((*name, *required)),
-            _ => None,
-        }) {
-            if required {
-                print!(" <{}>", name)
-            } else {
-                print!(" [{}]", name)
-            }
+    pub fn stub_config(&self) -> (ShouldStub, StubValues) {
+        let (should_stub, mut stub_values) = self.selection.stub_config();
+        stub_values
+            .globals
+            .extend(self.global_value.iter().cloned());
+        stub_values
+            .memories
+            .extend(self.memory_limits.iter().cloned());
+        stub_values.tables.extend(self.table_limits.iter().cloned());
+        (should_stub, stub_values)
+    }
+}
+
+impl Selection {
+    pub fn stub_config(&self) -> (ShouldStub, StubValues) {
+        let mut should_stub = ShouldStub::default();
+        let mut stub_values = StubValues::default();
+        for (module, function) in &self.stub_function {
+            should_stub.add_function(module, function);
         }
-        println!();
-        println!();
-        for arg in &self.args {
-            match arg {
-                Arg::Plain {
-                    name,
-                    required,
-                    help,
-                } => {
-                    if *required {
-                        println!("    <{name}>");
-                    } else {
-                        println!("    [{name}]");
-                    }
-                    Self::print_help(help);
-                }
-                _ => continue,
-            }
+        for module in &self.stub_module {
+            should_stub
+                .modules
+                .insert(module.to_owned(), FunctionsToStub::All);
         }
-        println!();
-        println!("OPTIONS:");
-        for arg in &self.args {
-            match arg {
-                Arg::Plain { .. } => continue,
-                Arg::LongFlag { name, help } => {
-                    println!("    {name}");
-                    Self::print_help(help);
-                }
-                Arg::ShortFlag { flag, help } => {
-                    println!("    -{flag}");
-                    Self::print_help(help);
-                }
-                Arg::KeyValue {
-                    keys,
-                    value_type,
-                    help,
-                } => {
-                    print!("    ");
-                    for (i, key) in keys.iter().enumerate() {
-                        if i != 0 {
-                            print!(", ");
-                        }
-                        print!("{key}")
-                    }
-                    println!(" <{value_type}>");
-                    Self::print_help(help);
-                }
-            }
+        for preset in &self.preset {
+            preset.apply(&mut should_stub, &mut stub_values);
         }
+        (should_stub, stub_values)
     }
+}
 
-    fn print_help(help: &str) {
-        for line in help.lines() {
-            println!("        {line}");
+impl Features {
+    pub fn features(&self) -> WasmFeatures {
+        let mut features = WasmFeatures::default();
+        for feature in &self.enable_features {
+            features |= *feature;
         }
-        if !help.contains('\n') {
-            println!();
+        for feature in &self.disable_features {
+            features -= *feature;
         }
+        features
     }
 }
 
-impl Args {
-    pub fn new() -> Result<Self, Error> {
-        let mut arg_parser = TestArgParser::new(
-            env!("CARGO_PKG_NAME"),
-            "A command to replace wasi functions with stubs. The stubbed function can still be called, but they won't have any side-effect, and will simply return dummy values.",
-            vec![
-                Arg::Plain {
-                    name: "file",
-                    required: true,
-                    help: "Input wasm file.",
-                },
-                Arg::KeyValue {
-                    keys: &["-o", "--output"],
-                    value_type: "PATH",
-                    help: "Specify the output path.",
-                },
-                Arg::KeyValue {
-                    keys: &["--stub-module"],
-                    value_type: "STRING",
-                    help: "Stub the given module.
-You can also give a list of comma-separated modules.",
-                },
-                Arg::KeyValue {
-                    keys: &["--stub-function"],
-                    value_type: "STRING:STRING",
-                    help: "Stub the given function. It must have the format 'module:function'.
-Example:
-wasi-stub input.wasm --stub-function horrible_module:terrible_function
-
-Multiple functions can be given: simply separate them with commas (without whitespace).",
-                },
-                Arg::KeyValue {
-                    keys: &["-r", "--return-value"],
-                    value_type: "INTEGER",
-                    help: "Make all stubbed function that return values return this number. By default, functions return 76."
-                },
-                Arg::LongFlag {
-                    name: "--list",
-                    help: "List the functions to stub, but don't write anything.",
-                },
-            ],
-        );
+/// Parse one of `values`, by name.
+fn named_parser<T: Copy + Send + Sync + 'static>(
+    values: &'static [T],
+    name: fn(T) -> &'static str,
+) -> impl TypedValueParser<Value = T> {
+    PossibleValuesParser::new(values.iter().map(|value| name(*value)))
+        .map(move |s| *values.iter().find(|value| name(**value) == s).unwrap())
+}
 
-        arg_parser.parse().map_err(Error::message)?;
+fn parse_number<T: std::str::FromStr>(arg: &str) -> Result<T, String> {
+    arg.parse().map_err(|_| format!("Invalid number: {arg}"))
+}
 
-        if arg_parser.requested_help {
-            arg_parser.print_help_message();
-            std::process::exit(0);
-        }
+/// Parse an argument of the form `module:function`.
+fn parse_function(arg: &str) -> Result<(String, String), String> {
+    let (module, function) = arg
+        .split_once(':')
+        .ok_or_else(|| format!("Malformed argument: {arg}"))?;
+    Ok((module.to_owned(), function.to_owned()))
+}
 
-        let path = PathBuf::from(&arg_parser.plain_args["file"]);
-        let list = arg_parser.long_flags.contains("--list");
-        let mut output_path = None;
-        let mut should_stub = ShouldStub::default();
-        let mut return_value: u32 = 76;
+/// Parse an argument of the form `module:item=value`.
+fn parse_item_value(arg: &str) -> Result<((String, String), GlobalValue), String> {
+    let (item, value) = arg
+        .split_once('=')
+        .ok_or_else(|| format!("Malformed argument: {arg}"))?;
+    let value = match value.parse() {
+        Ok(value) => GlobalValue::Int(value),
+        Err(_) => GlobalValue::Float(parse_number(value)?),
+    };
+    Ok((parse_function(item)?, value))
+}
 
-        if let Some(path) = arg_parser
-            .key_values
-            .get("--output")
-            .or(arg_parser.key_values.get("-o"))
-        {
-            output_path = Some(PathBuf::from(path));
-        }
-        if let Some(stub_functions) = arg_parser.key_values.get("--stub-function") {
-            if let Some(stub_functions) = stub_functions.to_str() {
-                for function in stub_functions.split(',') {
-                    let (module, function) = match function.split_once(':') {
-                        Some((m, f)) => (m, f),
-                        None => {
-                            return Err(Error::message(format!("Malformed argument: {function}")))
-                        }
-                    };
-                    let functions = should_stub
-                        .modules
-                        .entry(module.to_owned())
-                        .or_insert(FunctionsToStub::Some(HashSet::new()));
-                    match functions {
-                        FunctionsToStub::All => {}
-                        FunctionsToStub::Some(set) => {
-                            set.insert(function.to_owned());
-                        }
-                    }
-                }
-            }
-        }
-        if let Some(stub_modules) = arg_parser.key_values.get("--stub-module") {
-            if let Some(stub_modules) = stub_modules.to_str() {
-                for module in stub_modules.split(',') {
-                    should_stub
-                        .modules
-                        .insert(module.to_owned(), FunctionsToStub::All);
-                }
-            }
-        }
-        if let Some(value) = arg_parser
-            .key_values
-            .get("--return-value")
-            .or(arg_parser.key_values.get("-r"))
-        {
-            match value.to_str() {
-                Some(v) => return_value = v.parse()?,
-                None => return Err(Error::message(format!("Invalid number: {value:?}"))),
-            }
-        }
+/// Parse an argument of the form `module:item=min` or `module:item=min:max`.
+fn parse_item_limits(arg: &str) -> Result<((String, String), Limits), String> {
+    let (item, value) = arg
+        .split_once('=')
+        .ok_or_else(|| format!("Malformed argument: {arg}"))?;
+    let (min, max) = match value.split_once(':') {
+        Some((min, max)) => (parse_number(min)?, Some(parse_number(max)?)),
+        None => (parse_number(value)?, None),
+    };
+    Ok((parse_function(item)?, Limits { min, max }))
+}
 
-        Ok(Self {
-            binary: std::fs::read(&path)?,
-            path,
-            output_path,
-            list,
-            should_stub,
-            return_value,
-        })
-    }
+fn parse_feature(name: &str) -> Result<WasmFeatures, String> {
+    WasmFeatures::from_name(&name.to_uppercase()).ok_or_else(|| format!("Unknown feature: {name}"))
 }
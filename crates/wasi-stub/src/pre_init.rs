@@ -0,0 +1,355 @@
+//! Pre-initialisation of modules, in the style of [wizer](https://github.com/bytecodealliance/wizer).
+//!
+//! The module is instantiated in an interpreter, and its initialisation function is
+//! called. The resulting memories and globals then become the initial state of the
+//! module, so this work is not done again each time typst loads the plugin.
+
+use crate::{insert_after_last, print_wat, Error, Printed, Result};
+use wast::{
+    core::{
+        Data, DataKind, DataVal, Export, ExportKind, Expression, GlobalKind, Instruction, ItemKind,
+        MemoryKind, ModuleField, ModuleKind, V128Const, ValType,
+    },
+    token::{Index, Span, F32, F64},
+    Wat,
+};
+
+/// Function called to initialise the module.
+pub const INIT_FUNCTION: &str = "wasm_minimal_protocol_init";
+
+/// Default fuel of [`pre_initialize_with_fuel`].
+pub const INIT_FUEL: u64 = 1_000_000_000;
+
+/// Prefix of the exports added to read the state of the instance.
+const SNAPSHOT_EXPORT: &str = "__wasi_stub_snapshot";
+
+/// Runs of zeroes shorter than this do not split data segments, as each segment has
+/// a few bytes of overhead.
+const MIN_ZEROES_GAP: usize = 16;
+
+struct Snapshot {
+    /// Value of each defined global.
+    globals: Vec<wasmi::Val>,
+    /// Size in pages, and contents of each defined memory.
+    memories: Vec<(u64, Vec<u8>)>,
+}
+
+#[derive(Default)]
+struct HostState {
+    result: Vec<u8>,
+}
+
+/// Run the exported `wasm_minimal_protocol_init` function at build time, and store
+/// the resulting state of the memories and globals in the module.
+///
+/// The init function, like protocol functions, returns `0` on success, or `1` with
+/// an error message sent to the host: any other code is an error too. It has
+/// [`INIT_FUEL`] to run. It is no longer exported in the output, and
+/// the start function is removed, since it has already run.
+///
+/// Tables are not part of the snapshot: the init function should not modify them.
+pub fn pre_initialize(binary: &[u8]) -> Result<Vec<u8>> {
+    pre_initialize_with_fuel(binary, INIT_FUEL)
+}
+
+/// Like [`pre_initialize`], with `fuel` available to the start function and the init
+/// function together: roughly, the number of instructions they can execute. Going
+/// over it is an error, as the init function may loop forever.
+pub fn pre_initialize_with_fuel(binary: &[u8], fuel: u64) -> Result<Vec<u8>> {
+    let printed = print_wat(binary)?;
+    let snapshot = run_init(&printed, fuel)?;
+
+    let parse_buffer =
+        wast::parser::ParseBuffer::new(&printed.text).map_err(|err| printed.error(err))?;
+    let mut wat: Wat = wast::parser::parse(&parse_buffer).map_err(|err| printed.error(err))?;
+    let module = match &mut wat {
+        Wat::Module(m) => m,
+        Wat::Component(_) => {
+            return Err(Error::UnsupportedComponent(
+                "run wasi-stub on the component first".to_owned(),
+            ))
+        }
+    };
+    let ModuleKind::Text(fields) = &mut module.kind else {
+        return Err(Error::BinaryDirectives);
+    };
+
+    let mut globals = snapshot.globals.into_iter();
+    // Index type (`i32` or `i64`) of each memory.
+    let mut memories_is64 = Vec::new();
+    for field in fields.iter_mut() {
+        match field {
+            ModuleField::Global(global) => {
+                let value = globals.next().unwrap();
+                let GlobalKind::Inline(expression) = &mut global.kind else {
+                    continue;
+                };
+                let instruction = match value {
+                    wasmi::Val::I32(value) => Instruction::I32Const(value),
+                    wasmi::Val::I64(value) => Instruction::I64Const(value),
+                    wasmi::Val::F32(value) => Instruction::F32Const(F32 {
+                        bits: value.to_bits(),
+                    }),
+                    wasmi::Val::F64(value) => Instruction::F64Const(F64 {
+                        bits: value.to_bits(),
+                    }),
+                    wasmi::Val::V128(value) => {
+                        let value = value.as_u128();
+                        Instruction::V128Const(V128Const::I64x2([
+                            value as i64,
+                            (value >> 64) as i64,
+                        ]))
+                    }
+                    wasmi::Val::FuncRef(_) | wasmi::Val::ExternRef(_) => {
+                        let is_null = match &value {
+                            wasmi::Val::FuncRef(r) => r.is_null(),
+                            wasmi::Val::ExternRef(r) => r.is_null(),
+                            _ => unreachable!(),
+                        };
+                        match global.ty.ty {
+                            ValType::Ref(ty) if is_null => Instruction::RefNull(ty.heap),
+                            // The initializer of an immutable global gives the same value.
+                            _ if !global.ty.mutable => continue,
+                            _ => {
+                                return Err(Error::PreInit(
+                                    "references in mutable globals cannot be pre-initialized"
+                                        .to_owned(),
+                                ))
+                            }
+                        }
+                    }
+                };
+                *expression = Expression {
+                    instrs: Box::new([instruction]),
+                    branch_hints: Box::new([]),
+                    instr_spans: None,
+                };
+            }
+            ModuleField::Memory(memory) => {
+                let MemoryKind::Normal(ty) = &mut memory.kind else {
+                    return Err(Error::PreInit(
+                        "inline memories are not supported".to_owned(),
+                    ));
+                };
+                ty.limits.min = snapshot.memories[memories_is64.len()].0;
+                memories_is64.push(ty.limits.is64);
+            }
+            // The snapshot already contains the data of active segments. They are
+            // emptied rather than removed, so that data indices do not change.
+            ModuleField::Data(data) => {
+                if let DataKind::Active { .. } = data.kind {
+                    data.kind = DataKind::Passive;
+                    data.data = Vec::new();
+                }
+            }
+            _ => {}
+        }
+    }
+    fields.retain(|field| match field {
+        ModuleField::Start(_) => false,
+        ModuleField::Export(export) => export.name != INIT_FUNCTION,
+        _ => true,
+    });
+
+    let span = Span::from_offset(0);
+    let mut segments = Vec::new();
+    for (memory, (_, contents)) in snapshot.memories.iter().enumerate() {
+        for (offset, bytes) in non_zero_ranges(contents) {
+            let offset = if memories_is64[memory] {
+                Instruction::I64Const(offset as i64)
+            } else {
+                Instruction::I32Const(offset as i32)
+            };
+            segments.push(ModuleField::Data(Data {
+                span,
+                id: None,
+                name: None,
+                kind: DataKind::Active {
+                    memory: Index::Num(memory as u32, span),
+                    offset: Expression {
+                        instrs: Box::new([offset]),
+                        branch_hints: Box::new([]),
+                        instr_spans: None,
+                    },
+                },
+                data: vec![DataVal::Integral(bytes.to_vec())],
+            }));
+        }
+    }
+    insert_after_last(fields, segments, |f| matches!(f, ModuleField::Data(_)));
+
+    module.encode().map_err(|err| printed.error(err))
+}
+
+/// Instantiate the module, call its init function, and read the resulting state.
+fn run_init(printed: &Printed, fuel: u64) -> Result<Snapshot> {
+    let parse_buffer =
+        wast::parser::ParseBuffer::new(&printed.text).map_err(|err| printed.error(err))?;
+    let mut wat: Wat = wast::parser::parse(&parse_buffer).map_err(|err| printed.error(err))?;
+    let module = match &mut wat {
+        Wat::Module(m) => m,
+        Wat::Component(_) => {
+            return Err(Error::UnsupportedComponent(
+                "run wasi-stub on the component first".to_owned(),
+            ))
+        }
+    };
+    let ModuleKind::Text(fields) = &mut module.kind else {
+        return Err(Error::BinaryDirectives);
+    };
+
+    let mut globals = (0, 0);
+    let mut memories = (0, 0);
+    for field in fields.iter() {
+        match field {
+            ModuleField::Import(i) => match i.item.kind {
+                ItemKind::Global(_) => globals.0 += 1,
+                ItemKind::Memory(_) => {
+                    return Err(Error::PreInit(
+                        "modules importing memories cannot be pre-initialized".to_owned(),
+                    ))
+                }
+                _ => {}
+            },
+            ModuleField::Global(_) => globals.1 += 1,
+            ModuleField::Memory(_) => memories.1 += 1,
+            _ => {}
+        }
+    }
+    // Export every defined global and memory, to read them after the init function.
+    let global_names: Vec<_> = (0..globals.1)
+        .map(|i| format!("{SNAPSHOT_EXPORT}_global_{i}"))
+        .collect();
+    let memory_names: Vec<_> = (0..memories.1)
+        .map(|i| format!("{SNAPSHOT_EXPORT}_memory_{i}"))
+        .collect();
+    let span = Span::from_offset(0);
+    for (i, name) in global_names.iter().enumerate() {
+        fields.push(ModuleField::Export(Export {
+            span,
+            name,
+            kind: ExportKind::Global,
+            item: Index::Num(globals.0 + i as u32, span),
+        }));
+    }
+    for (i, name) in memory_names.iter().enumerate() {
+        fields.push(ModuleField::Export(Export {
+            span,
+            name,
+            kind: ExportKind::Memory,
+            item: Index::Num(memories.0 + i as u32, span),
+        }));
+    }
+    let binary = module.encode().map_err(|err| printed.error(err))?;
+
+    let mut config = wasmi::Config::default();
+    config.consume_fuel(true);
+    let engine = wasmi::Engine::new(&config);
+    let module =
+        wasmi::Module::new(&engine, &binary).map_err(|err| Error::PreInit(err.to_string()))?;
+    let mut store = wasmi::Store::new(&engine, HostState::default());
+    store
+        .set_fuel(fuel)
+        .map_err(|err| Error::PreInit(err.to_string()))?;
+    let mut linker = wasmi::Linker::<HostState>::new(&engine);
+    // There are no arguments to write, and the result is only read on errors.
+    linker
+        .func_wrap(
+            "typst_env",
+            "wasm_minimal_protocol_send_result_to_host",
+            |mut caller: wasmi::Caller<'_, HostState>, ptr: u32, len: u32| {
+                let Some(memory) = caller
+                    .get_export("memory")
+                    .and_then(wasmi::Extern::into_memory)
+                else {
+                    return;
+                };
+                let mut result = vec![0; len as usize];
+                if memory.read(&caller, ptr as usize, &mut result).is_ok() {
+                    caller.data_mut().result = result;
+                }
+            },
+        )
+        .map_err(|err| Error::PreInit(err.to_string()))?;
+    linker
+        .func_wrap(
+            "typst_env",
+            "wasm_minimal_protocol_write_args_to_buffer",
+            |_: u32| {},
+        )
+        .map_err(|err| Error::PreInit(err.to_string()))?;
+    let instance = linker
+        .instantiate_and_start(&mut store, &module)
+        .map_err(|err| {
+            Error::PreInit(format!(
+                "cannot instantiate the module: {}",
+                describe(err, fuel)
+            ))
+        })?;
+
+    let init = instance
+        .get_func(&store, INIT_FUNCTION)
+        .ok_or_else(|| Error::PreInit(format!("the module does not export {INIT_FUNCTION}")))?;
+    let mut results = vec![wasmi::Val::I32(0); init.ty(&store).results().len()];
+    init.call(&mut store, &[], &mut results).map_err(|err| {
+        Error::PreInit(format!("{INIT_FUNCTION} failed: {}", describe(err, fuel)))
+    })?;
+    match results[..] {
+        [wasmi::Val::I32(code)] if code != 0 => {
+            let message = String::from_utf8_lossy(&store.data().result);
+            return Err(Error::PreInit(format!(
+                "{INIT_FUNCTION} returned {code}: {message}"
+            )));
+        }
+        _ => {}
+    }
+
+    let globals = global_names
+        .iter()
+        .map(|name| instance.get_global(&store, name).unwrap().get(&store))
+        .collect();
+    let memories = memory_names
+        .iter()
+        .map(|name| {
+            let memory = instance.get_memory(&store, name).unwrap();
+            (memory.size(&store), memory.data(&store).to_vec())
+        })
+        .collect();
+    Ok(Snapshot { globals, memories })
+}
+
+/// The reason of a failed call, with the fuel limit if it was reached.
+fn describe(err: wasmi::Error, fuel: u64) -> String {
+    match err.as_trap_code() {
+        Some(wasmi::TrapCode::OutOfFuel) => {
+            format!("it ran out of fuel (the limit is {fuel}), it may loop forever")
+        }
+        _ => err.to_string(),
+    }
+}
+
+/// Offsets and contents of the parts of `memory` that are not zeroes.
+fn non_zero_ranges(memory: &[u8]) -> Vec<(usize, &[u8])> {
+    let mut ranges = Vec::new();
+    let mut offset = 0;
+    while let Some(start) = memory[offset..].iter().position(|&b| b != 0) {
+        let start = offset + start;
+        let mut end = start;
+        loop {
+            match memory[end..].iter().position(|&b| b == 0) {
+                None => {
+                    end = memory.len();
+                    break;
+                }
+                Some(zero) => end += zero,
+            }
+            match memory[end..].iter().position(|&b| b != 0) {
+                Some(gap) if gap < MIN_ZEROES_GAP => end += gap,
+                _ => break,
+            }
+        }
+        ranges.push((start, &memory[start..end]));
+        offset = end;
+    }
+    ranges
+}
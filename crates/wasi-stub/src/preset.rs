@@ -0,0 +1,206 @@
+//! Stubs tailored to the imports generated by a given toolchain.
+
+use crate::{Error, FunctionStub, ShouldStub, StubValues};
+
+/// Value of `ENOSYS` in emscripten's `errno.h`.
+const EMSCRIPTEN_ENOSYS: i64 = 52;
+
+/// Global holding the time, in nanoseconds, returned by the stubbed clocks.
+const CLOCK: &str = "__wasi_stub_clock";
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Preset {
+    /// Functions of the `env` module imported by code compiled with `emcc`.
+    Emscripten,
+    /// WASI and `gojs` functions imported by the TinyGo runtime.
+    ///
+    /// The stubbed clock advances on each read and on each sleep, so that the
+    /// scheduler does not wait forever for a timer.
+    TinyGo,
+}
+
+impl Preset {
+    pub const ALL: &'static [Self] = &[Self::Emscripten, Self::TinyGo];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Emscripten => "emscripten",
+            Self::TinyGo => "tinygo",
+        }
+    }
+
+    /// Stub the imports known by this preset, with the behaviour they need.
+    pub fn apply(self, should_stub: &mut ShouldStub, stub_values: &mut StubValues) {
+        for (module, function, stub) in self.functions() {
+            should_stub.add_function(module, function);
+            stub_values
+                .functions
+                .insert((module.to_owned(), function.to_owned()), stub);
+        }
+        if self == Self::TinyGo {
+            stub_values.state.insert(CLOCK.to_owned(), 0);
+        }
+    }
+
+    fn functions(self) -> Vec<(&'static str, &'static str, FunctionStub)> {
+        match self {
+            Self::Emscripten => {
+                let memcpy = "local.get 0 local.get 1 local.get 2 memory.copy";
+                [
+                    ("emscripten_notify_memory_growth", FunctionStub::Return(0)),
+                    // (requested_size) -> success
+                    //
+                    // Grow the memory to the requested size in bytes, like typst lets
+                    // `memory.grow` do.
+                    (
+                        "emscripten_resize_heap",
+                        FunctionStub::Body(
+                            "local.get 0 i64.extend_i32_u i64.const 65535 i64.add i64.const 16 i64.shr_u
+                            i32.wrap_i64 memory.size i32.sub local.set 0
+                            local.get 0 i32.const 0 i32.le_s
+                            if (result i32)
+                              i32.const 1
+                            else
+                              local.get 0 memory.grow i32.const -1 i32.ne
+                            end"
+                                .to_owned(),
+                        ),
+                    ),
+                    (
+                        "emscripten_memcpy_js",
+                        FunctionStub::Body(memcpy.to_owned()),
+                    ),
+                    (
+                        "_emscripten_memcpy_js",
+                        FunctionStub::Body(memcpy.to_owned()),
+                    ),
+                    // Older versions of emscripten return the destination.
+                    (
+                        "emscripten_memcpy_big",
+                        FunctionStub::Body(format!("{memcpy} local.get 0")),
+                    ),
+                    ("emscripten_date_now", FunctionStub::Return(0)),
+                    ("emscripten_get_now", FunctionStub::Return(0)),
+                    ("_tzset_js", FunctionStub::Return(0)),
+                    ("_emscripten_throw_longjmp", FunctionStub::Trap),
+                    ("emscripten_longjmp", FunctionStub::Trap),
+                    ("abort", FunctionStub::Trap),
+                    ("_abort_js", FunctionStub::Trap),
+                    ("__assert_fail", FunctionStub::Trap),
+                    ("_mmap_js", FunctionStub::Return(-EMSCRIPTEN_ENOSYS)),
+                    ("_munmap_js", FunctionStub::Return(-EMSCRIPTEN_ENOSYS)),
+                    ("__syscall_*", FunctionStub::Return(-EMSCRIPTEN_ENOSYS)),
+                    ("invoke_*", FunctionStub::CallIndirect),
+                ]
+                .into_iter()
+                .map(|(function, stub)| ("env", function, stub))
+                .collect()
+            }
+            Self::TinyGo => {
+                let wasi = "wasi_snapshot_preview1";
+                let body =
+                    |body: &str| FunctionStub::Body(body.replace("$clock", &format!("${CLOCK}")));
+                vec![
+                    // (clock_id, precision, time_ptr) -> errno
+                    (
+                        wasi,
+                        "clock_time_get",
+                        body(
+                            "global.get $clock i64.const 1000 i64.add global.set $clock
+                            local.get 2 global.get $clock i64.store
+                            i32.const 0",
+                        ),
+                    ),
+                    // (subscriptions, events, nsubscriptions, nevents_ptr) -> errno
+                    //
+                    // The runtime only subscribes to one clock event at a time: the
+                    // clock jumps to the end of its timeout, and the event is reported.
+                    (
+                        wasi,
+                        "poll_oneoff",
+                        body(
+                            "global.get $clock local.get 0 i64.load offset=24 i64.add global.set $clock
+                            local.get 1 local.get 0 i64.load i64.store
+                            local.get 1 i32.const 0 i32.store offset=8
+                            local.get 3 i32.const 1 i32.store
+                            i32.const 0",
+                        ),
+                    ),
+                    // (fd, iovs, iovs_len, nwritten_ptr) -> errno
+                    //
+                    // Pretend everything was written, otherwise `os.File.Write` fails.
+                    (
+                        wasi,
+                        "fd_write",
+                        body(
+                            "local.get 3 i32.const 0 i32.store
+                            block
+                              loop
+                                local.get 2 i32.eqz br_if 1
+                                local.get 3 local.get 3 i32.load local.get 1 i32.load offset=4 i32.add i32.store
+                                local.get 1 i32.const 8 i32.add local.set 1
+                                local.get 2 i32.const 1 i32.sub local.set 2
+                                br 0
+                              end
+                            end
+                            i32.const 0",
+                        ),
+                    ),
+                    // (buf, len) -> errno
+                    //
+                    // Zeroes keep plugins deterministic, as typst requires.
+                    (
+                        wasi,
+                        "random_get",
+                        body("local.get 0 i32.const 0 local.get 1 memory.fill i32.const 0"),
+                    ),
+                    // (count_ptr, buf_size_ptr) -> errno
+                    (
+                        wasi,
+                        "args_sizes_get",
+                        body("local.get 0 i32.const 0 i32.store local.get 1 i32.const 0 i32.store i32.const 0"),
+                    ),
+                    (
+                        wasi,
+                        "environ_sizes_get",
+                        body("local.get 0 i32.const 0 i32.store local.get 1 i32.const 0 i32.store i32.const 0"),
+                    ),
+                    (wasi, "args_get", FunctionStub::Return(0)),
+                    (wasi, "environ_get", FunctionStub::Return(0)),
+                    (wasi, "proc_exit", FunctionStub::Trap),
+                    // `-target=wasm` measures time in milliseconds, as a `f64`.
+                    (
+                        "gojs",
+                        "runtime.ticks",
+                        body(
+                            "global.get $clock i64.const 1000 i64.add global.set $clock
+                            global.get $clock f64.convert_i64_u f64.const 1000000 f64.div",
+                        ),
+                    ),
+                    (
+                        "gojs",
+                        "runtime.sleepTicks",
+                        body(
+                            "global.get $clock
+                            local.get 0 f64.const 1000000 f64.mul i64.trunc_sat_f64_u
+                            i64.add global.set $clock",
+                        ),
+                    ),
+                    ("gojs", "*", FunctionStub::Return(0)),
+                ]
+            }
+        }
+    }
+}
+
+impl std::str::FromStr for Preset {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::ALL
+            .iter()
+            .copied()
+            .find(|preset| preset.name() == s)
+            .ok_or_else(|| Error::message(format!("Unknown preset: {s}")))
+    }
+}
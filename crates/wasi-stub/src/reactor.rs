@@ -0,0 +1,225 @@
+//! Conversion of WASI commands into reactors.
+//!
+//! Commands export `_start`, which runs the static constructors before `main`. Typst
+//! never calls it, so the constructors would never run: instead, each protocol
+//! function runs them on its first call.
+
+use crate::{diagnostics, insert_after_last, parse_instructions, print_wat, Error, Result};
+use wast::{
+    core::{
+        ExportKind, Expression, Func, FuncKind, Global, GlobalKind, GlobalType, InlineExport,
+        InnerTypeKind, Instruction, ItemKind, ModuleField, ModuleKind, TypeUse, ValType,
+    },
+    token::{Id, Index, Span},
+    Wat,
+};
+
+/// Global set once the constructors have run.
+const INITIALIZED: &str = "__wasi_stub_initialized";
+
+/// Make every protocol function run the constructors of the module first, and remove
+/// the `_start` export.
+///
+/// The constructors are found in the `_initialize` export, or else in the
+/// `__wasm_call_ctors` function. Without names (in stripped builds), they are the
+/// first function called by `_start` that takes and returns nothing, as in the
+/// `_start` of wasi-libc.
+pub fn command_to_reactor(binary: &[u8]) -> Result<Vec<u8>> {
+    let printed = print_wat(binary)?;
+    let parse_buffer =
+        wast::parser::ParseBuffer::new(&printed.text).map_err(|err| printed.error(err))?;
+
+    let mut wat: Wat = wast::parser::parse(&parse_buffer).map_err(|err| printed.error(err))?;
+    let module = match &mut wat {
+        Wat::Module(m) => m,
+        Wat::Component(_) => {
+            return Err(Error::UnsupportedComponent(
+                "run wasi-stub on the component first".to_owned(),
+            ))
+        }
+    };
+    let ModuleKind::Text(fields) = &mut module.kind else {
+        return Err(Error::BinaryDirectives);
+    };
+
+    // Parameters of each type, if it is a protocol function type: protocol functions
+    // only take and return integers.
+    let mut protocol_types = Vec::new();
+    // Whether each type is the type of constructors, which take and return nothing.
+    let mut constructor_types = Vec::new();
+    // Identifier, type index and field (if it is not imported) of each function.
+    let mut functions = Vec::new();
+    for (field_index, field) in fields.iter().enumerate() {
+        match field {
+            ModuleField::Type(t) => {
+                let ty = match &t.def.kind {
+                    InnerTypeKind::Func(ty) => Some(ty),
+                    _ => None,
+                };
+                protocol_types.push(ty.and_then(|ty| {
+                    let protocol = ty.params.iter().all(|(_, _, ty)| *ty == ValType::I32)
+                        && *ty.results == [ValType::I32];
+                    protocol.then_some(ty.params.len())
+                }));
+                constructor_types
+                    .push(ty.is_some_and(|ty| ty.params.is_empty() && ty.results.is_empty()));
+            }
+            ModuleField::Import(i) => {
+                if let ItemKind::Func(ty) = &i.item.kind {
+                    functions.push((i.item.id, ty.index, None));
+                }
+            }
+            ModuleField::Func(f) => functions.push((f.id, f.ty.index, Some(field_index))),
+            _ => {}
+        }
+    }
+    let position = |function: &Index| match function {
+        Index::Num(index, _) => Some(*index as usize).filter(|i| *i < functions.len()),
+        Index::Id(id) => functions
+            .iter()
+            .position(|(i, _, _)| i.as_ref() == Some(id)),
+    };
+    let type_index = |function: &Index| match functions[position(function)?].1 {
+        Some(Index::Num(ty, _)) => Some(ty),
+        _ => None,
+    };
+
+    let mut constructors = None;
+    let mut start = None;
+    // Field, type index and number of parameters of each protocol function.
+    let mut exports = Vec::new();
+    for (field_index, field) in fields.iter().enumerate() {
+        let ModuleField::Export(export) = field else {
+            continue;
+        };
+        if export.kind != ExportKind::Func {
+            continue;
+        }
+        match export.name {
+            "_initialize" => constructors = Some(export.item),
+            "_start" => start = Some((field_index, export.item)),
+            _ => {
+                if let Some(ty) = type_index(&export.item) {
+                    if let Some(Some(nb_params)) = protocol_types.get(ty as usize) {
+                        exports.push((field_index, ty, *nb_params));
+                    }
+                }
+            }
+        }
+    }
+    let constructors = constructors
+        .or_else(|| {
+            let index = functions
+                .iter()
+                .position(|(id, _, _)| id.is_some_and(|id| id.name() == "__wasm_call_ctors"))?;
+            Some(Index::Num(index as u32, Span::from_offset(0)))
+        })
+        .or_else(|| {
+            let (_, start) = start?;
+            let ModuleField::Func(Func {
+                kind: FuncKind::Inline { expression, .. },
+                ..
+            }) = &fields[functions[position(&start)?].2?]
+            else {
+                return None;
+            };
+            expression
+                .instrs
+                .iter()
+                .find_map(|instruction| match instruction {
+                    Instruction::Call(function) => {
+                        let (_, _, field) = functions[position(function)?];
+                        let is_constructor =
+                            *constructor_types.get(type_index(function)? as usize)?;
+                        (field.is_some() && is_constructor).then_some(*function)
+                    }
+                    _ => None,
+                })
+        });
+    // Wrappers are the last functions: no other function index changes.
+    let first_wrapper = functions.len() as u32;
+
+    let Some(constructors) = constructors else {
+        if start.is_some() {
+            return Err(Error::UnsupportedModule(
+                "`_start` does not call constructors that can be found".to_owned(),
+            ));
+        }
+        diagnostics::emit(
+            "[WARNING] no constructors found: protocol functions are left as is".to_owned(),
+        );
+        return module.encode().map_err(|err| printed.error(err));
+    };
+
+    let mut wrappers = Vec::new();
+    for (i, (field_index, ty, nb_params)) in exports.into_iter().enumerate() {
+        let ModuleField::Export(export) = &mut fields[field_index] else {
+            unreachable!()
+        };
+        diagnostics::emit(format!("Running constructors before {}", export.name));
+        let mut body = format!(
+            "global.get ${INITIALIZED} i32.eqz
+            if
+              i32.const 1 global.set ${INITIALIZED}
+              call {}
+            end",
+            index_to_wat(&constructors)
+        );
+        for param in 0..nb_params {
+            body.push_str(&format!(" local.get {param}"));
+        }
+        body.push_str(&format!(" call {}", index_to_wat(&export.item)));
+        export.item = Index::Num(first_wrapper + i as u32, Span::from_offset(0));
+
+        let span = Span::from_offset(0);
+        wrappers.push(ModuleField::Func(Func {
+            span,
+            id: None,
+            name: None,
+            exports: InlineExport { names: Vec::new() },
+            kind: FuncKind::Inline {
+                locals: Box::new([]),
+                expression: Expression {
+                    instrs: parse_instructions(body)?.into_boxed_slice(),
+                    branch_hints: Box::new([]),
+                    instr_spans: None,
+                },
+            },
+            ty: TypeUse::new_with_index(Index::Num(ty, span)),
+        }));
+    }
+    if let Some((start, _)) = start {
+        fields.remove(start);
+    }
+
+    let span = Span::from_offset(0);
+    let initialized = ModuleField::Global(Global {
+        span,
+        id: Some(Id::new(INITIALIZED, span)),
+        name: None,
+        exports: InlineExport { names: Vec::new() },
+        ty: GlobalType {
+            ty: ValType::I32,
+            mutable: true,
+            shared: false,
+        },
+        kind: GlobalKind::Inline(Expression {
+            instrs: Box::new([Instruction::I32Const(0)]),
+            branch_hints: Box::new([]),
+            instr_spans: None,
+        }),
+    });
+    insert_after_last(fields, vec![initialized], |f| {
+        matches!(f, ModuleField::Global(_))
+    });
+    insert_after_last(fields, wrappers, |f| matches!(f, ModuleField::Func(_)));
+
+    module.encode().map_err(|err| printed.error(err))
+}
+
+fn index_to_wat(index: &Index) -> String {
+    match index {
+        Index::Num(index, _) => index.to_string(),
+        Index::Id(id) => format!("${}", id.name()),
+    }
+}
@@ -0,0 +1,88 @@
+//! Validation of the output, so that invalid modules are reported here rather than
+//! when typst loads them.
+
+use crate::Result;
+use wasmparser::{Parser, Payload, TypeRef, Validator, WasmFeatures};
+
+/// An invalid module was produced.
+#[derive(Debug)]
+pub struct ValidationError {
+    pub message: String,
+    /// Offset of the error in the binary.
+    pub offset: usize,
+    /// Index of the function containing the error, if any.
+    pub function: Option<u32>,
+    /// Disassembly of the offending instruction.
+    pub instruction: Option<String>,
+}
+
+impl std::fmt::Display for ValidationError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "invalid module: {} (at offset {:#x})",
+            self.message, self.offset
+        )?;
+        if let Some(function) = self.function {
+            write!(f, "\n  in function {function}")?;
+        }
+        if let Some(instruction) = &self.instruction {
+            write!(f, "\n  at `{instruction}`")?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for ValidationError {}
+
+/// Check that `binary` is a valid module, using the given WebAssembly features.
+pub fn validate(binary: &[u8], features: WasmFeatures) -> Result<()> {
+    let Err(err) = Validator::new_with_features(features).validate_all(binary) else {
+        return Ok(());
+    };
+    let offset = err.offset();
+    let mut function = None;
+    let mut imported_functions = 0;
+    let mut defined_functions = 0;
+    for payload in Parser::new(0).parse_all(binary) {
+        match payload {
+            Ok(Payload::ImportSection(imports)) => {
+                for import in imports.into_iter().flatten() {
+                    if let TypeRef::Func(_) = import.ty {
+                        imported_functions += 1;
+                    }
+                }
+            }
+            Ok(Payload::CodeSectionEntry(body)) => {
+                if body.range().contains(&offset) {
+                    function = Some((imported_functions + defined_functions, body.range()));
+                    break;
+                }
+                defined_functions += 1;
+            }
+            Ok(_) => {}
+            Err(_) => break,
+        }
+    }
+
+    // The last instruction starting before the error.
+    let instruction = function.as_ref().and_then(|(_, range)| {
+        let mut storage = String::new();
+        let lines = wasmprinter::Config::new()
+            .offsets_and_lines(binary, &mut storage)
+            .ok()?;
+        lines
+            .filter_map(|(line_offset, line)| Some((line_offset?, line)))
+            .filter(|(line_offset, _)| range.contains(line_offset) && *line_offset <= offset)
+            .last()
+            .map(|(_, line)| line.trim().to_owned())
+    });
+
+    Err(ValidationError {
+        message: err.message().to_owned(),
+        offset,
+        function: function.map(|(index, _)| index),
+        instruction,
+    }
+    .into())
+}
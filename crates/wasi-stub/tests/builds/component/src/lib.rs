@@ -0,0 +1,9 @@
+//! A component built for `wasm32-wasip2`: it has no `typst_env` imports, which
+//! `wasm-component-ld` cannot link.
+
+#[no_mangle]
+pub extern "C" fn hello() -> i32 {
+    // Goes through the `wasi:io/streams` interface.
+    println!("hello");
+    0
+}
@@ -0,0 +1,245 @@
+mod common;
+
+use common::{call, fixture};
+use std::{
+    io::Write,
+    path::PathBuf,
+    process::{Command, Stdio},
+};
+
+/// A new empty directory, with the given fixtures written as `{name}.wasm`.
+fn temp_dir(test: &str, fixtures: &[&str]) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("wasi-stub-{test}-{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    for name in fixtures {
+        std::fs::write(dir.join(format!("{name}.wasm")), fixture(name)).unwrap();
+    }
+    dir
+}
+
+fn wasi_stub(args: &[&str], dir: &PathBuf) -> std::process::Output {
+    Command::new(env!("CARGO_BIN_EXE_wasi-stub"))
+        .args(args)
+        .current_dir(dir)
+        .output()
+        .unwrap()
+}
+
+#[test]
+fn out_dir() {
+    let dir = temp_dir("out-dir", &["command", "gc"]);
+    let output = wasi_stub(&["--out-dir", "dist", "command.wasm", "gc.wasm"], &dir);
+    assert!(output.status.success());
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    // Each message says which file it is about.
+    assert!(stderr.contains("command.wasm: Stubbing function "));
+    assert!(stderr
+        .lines()
+        .all(|line| line.starts_with("command.wasm: ") || line.starts_with("gc.wasm: ")));
+    assert!(dir.join("dist/command.wasm").exists());
+    assert!(dir.join("dist/gc.wasm").exists());
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn in_place() {
+    let dir = temp_dir("in-place", &["command", "gc"]);
+    let before = std::fs::read(dir.join("gc.wasm")).unwrap();
+    let output = wasi_stub(&["--in-place", "command.wasm", "gc.wasm"], &dir);
+    assert!(output.status.success());
+    assert_ne!(std::fs::read(dir.join("gc.wasm")).unwrap(), before);
+    // No other file is created.
+    assert_eq!(std::fs::read_dir(&dir).unwrap().count(), 2);
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn failure() {
+    let dir = temp_dir("failure", &["command"]);
+    std::fs::write(dir.join("invalid.wasm"), b"not wasm").unwrap();
+    let output = wasi_stub(&["--out-dir", "dist", "invalid.wasm", "command.wasm"], &dir);
+    assert!(!output.status.success());
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.contains("invalid.wasm: error: "));
+    // Other files are still processed.
+    assert!(dir.join("dist/command.wasm").exists());
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn output_needs_single_file() {
+    let dir = temp_dir("output", &["command", "gc"]);
+    let output = wasi_stub(&["-o", "out.wasm", "command.wasm", "gc.wasm"], &dir);
+    assert!(!output.status.success());
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn stdio() {
+    for args in [&["-"][..], &["-", "-o", "-"]] {
+        let mut child = Command::new(env!("CARGO_BIN_EXE_wasi-stub"))
+            .args(args)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .unwrap();
+        child
+            .stdin
+            .take()
+            .unwrap()
+            .write_all(&fixture("command"))
+            .unwrap();
+        let output = child.wait_with_output().unwrap();
+        assert!(output.status.success());
+        // Messages do not end up in the binary.
+        assert!(String::from_utf8(output.stderr)
+            .unwrap()
+            .contains("Stubbing function wasi_snapshot_preview1::proc_exit"));
+        assert_eq!(
+            call(&output.stdout, "constructed").unwrap(),
+            (0, b"0".to_vec())
+        );
+    }
+}
+
+#[test]
+fn stub_subcommand() {
+    let dir = temp_dir("stub-subcommand", &["command"]);
+    let output = wasi_stub(
+        &[
+            "stub",
+            "command.wasm",
+            "--stub-module=env",
+            "--stub-module",
+            "other",
+            "-o",
+            "out.wasm",
+        ],
+        &dir,
+    );
+    assert!(output.status.success());
+    assert!(dir.join("out.wasm").exists());
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn list() {
+    let dir = temp_dir("list", &["command"]);
+    let output = wasi_stub(&["list", "command.wasm"], &dir);
+    assert!(output.status.success());
+    assert!(String::from_utf8(output.stderr)
+        .unwrap()
+        .contains("Stubbing function wasi_snapshot_preview1::proc_exit"));
+    // Nothing is written.
+    assert_eq!(std::fs::read_dir(&dir).unwrap().count(), 1);
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn check() {
+    let dir = temp_dir("check", &["command"]);
+    let output = wasi_stub(&["check", "command.wasm"], &dir);
+    assert!(!output.status.success());
+    assert!(String::from_utf8(output.stderr)
+        .unwrap()
+        .contains("imports not provided by typst: wasi_snapshot_preview1::proc_exit"));
+
+    assert!(wasi_stub(&["command.wasm", "-o", "stubbed.wasm"], &dir)
+        .status
+        .success());
+    let output = wasi_stub(&["check", "stubbed.wasm"], &dir);
+    assert!(output.status.success());
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn completions_and_man() {
+    let dir = temp_dir("completions", &[]);
+    let output = wasi_stub(&["completions", "bash"], &dir);
+    assert!(output.status.success());
+    assert!(String::from_utf8(output.stdout)
+        .unwrap()
+        .contains("--stub-module"));
+    let output = wasi_stub(&["man"], &dir);
+    assert!(output.status.success());
+    assert!(String::from_utf8(output.stdout)
+        .unwrap()
+        .contains(".TH wasi-stub"));
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn inspect() {
+    let dir = temp_dir("inspect", &["inspect"]);
+    let output = wasi_stub(&["inspect", "inspect.wasm"], &dir);
+    assert!(output.status.success());
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(stdout.contains("  concat: func(i32, i32) -> i32 (protocol function)\n"));
+    assert!(stdout.contains("  processed-by: rustc 1.80.0"));
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn diff_fail_on() {
+    let dir = temp_dir("diff", &["inspect"]);
+    assert!(wasi_stub(&["inspect.wasm", "-o", "stubbed.wasm"], &dir)
+        .status
+        .success());
+    let output = wasi_stub(&["diff", "inspect.wasm", "stubbed.wasm"], &dir);
+    assert!(output.status.success());
+    assert!(String::from_utf8(output.stdout)
+        .unwrap()
+        .contains("- import wasi_snapshot_preview1::fd_write: "));
+
+    let output = wasi_stub(
+        &[
+            "diff",
+            "inspect.wasm",
+            "stubbed.wasm",
+            "--fail-on",
+            "added-import,removed-export",
+        ],
+        &dir,
+    );
+    assert!(output.status.success());
+    let output = wasi_stub(
+        &[
+            "diff",
+            "inspect.wasm",
+            "stubbed.wasm",
+            "--fail-on",
+            "removed-import",
+        ],
+        &dir,
+    );
+    assert!(!output.status.success());
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn stub_values() {
+    let dir = temp_dir("stub-values", &["imports"]);
+    let output = wasi_stub(
+        &[
+            "imports.wasm",
+            "-o",
+            "out.wasm",
+            "--stub-module",
+            "env",
+            "--global-value",
+            "env:offset=7,env:ratio=3.5,env:scale=0.25",
+            "--memory-limits",
+            "env:memory=2:2",
+            "--table-limits",
+            "env:table=3",
+        ],
+        &dir,
+    );
+    assert!(output.status.success());
+    let binary = std::fs::read(dir.join("out.wasm")).unwrap();
+    assert_eq!(call(&binary, "globals").unwrap(), (0, vec![7, 3, 2]));
+    assert_eq!(call(&binary, "sizes").unwrap(), (0, vec![2, 3, 0, 1]));
+    std::fs::remove_dir_all(dir).unwrap();
+}
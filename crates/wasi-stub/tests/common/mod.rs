@@ -0,0 +1,128 @@
+//! Helpers shared by the integration tests.
+
+// Each test binary only uses some of the helpers.
+#![allow(dead_code)]
+
+use wasmi::{Caller, Config, Engine, Extern, Linker, Module, Store, Val};
+
+/// Enough fuel for every fixture function, as long as it does not loop forever.
+const FUEL: u64 = 1_000_000;
+
+pub fn fixture(name: &str) -> Vec<u8> {
+    let path = format!("{}/tests/fixtures/{name}.wat", env!("CARGO_MANIFEST_DIR"));
+    wat(&std::fs::read_to_string(path).unwrap())
+}
+
+/// A module produced by a real toolchain, from `tests/builds`.
+pub fn build(name: &str) -> Vec<u8> {
+    let path = format!("{}/tests/builds/{name}.wasm", env!("CARGO_MANIFEST_DIR"));
+    std::fs::read(path).unwrap()
+}
+
+pub fn wat(source: &str) -> Vec<u8> {
+    let buffer = wast::parser::ParseBuffer::new(source).unwrap();
+    let mut wat: wast::Wat = wast::parser::parse(&buffer).unwrap();
+    wat.encode().unwrap()
+}
+
+#[derive(Default)]
+struct HostState {
+    args: Vec<u8>,
+    result: Vec<u8>,
+}
+
+/// Instance of a plugin, with only the protocol imports available, like typst does.
+pub struct Plugin {
+    store: Store<HostState>,
+    instance: wasmi::Instance,
+}
+
+impl Plugin {
+    pub fn new(binary: &[u8]) -> Result<Self, wasmi::Error> {
+        let mut config = Config::default();
+        config.consume_fuel(true);
+        let engine = Engine::new(&config);
+        let module = Module::new(&engine, binary)?;
+        let mut store = Store::new(&engine, HostState::default());
+        let mut linker = Linker::<HostState>::new(&engine);
+        linker.func_wrap(
+            "typst_env",
+            "wasm_minimal_protocol_send_result_to_host",
+            |mut caller: Caller<'_, HostState>, ptr: u32, len: u32| {
+                let memory = caller
+                    .get_export("memory")
+                    .and_then(Extern::into_memory)
+                    .unwrap();
+                let mut result = vec![0; len as usize];
+                memory.read(&caller, ptr as usize, &mut result).unwrap();
+                caller.data_mut().result = result;
+            },
+        )?;
+        linker.func_wrap(
+            "typst_env",
+            "wasm_minimal_protocol_write_args_to_buffer",
+            |mut caller: Caller<'_, HostState>, ptr: u32| {
+                let memory = caller
+                    .get_export("memory")
+                    .and_then(Extern::into_memory)
+                    .unwrap();
+                let args = std::mem::take(&mut caller.data_mut().args);
+                memory.write(&mut caller, ptr as usize, &args).unwrap();
+            },
+        )?;
+        store.set_fuel(FUEL)?;
+        let instance = linker.instantiate_and_start(&mut store, &module)?;
+        Ok(Self { store, instance })
+    }
+
+    pub fn call(&mut self, function: &str, args: &[&[u8]]) -> Result<(i32, Vec<u8>), wasmi::Error> {
+        self.store.set_fuel(FUEL)?;
+        self.store.data_mut().args = args.concat();
+        let func = self
+            .instance
+            .get_func(&self.store, function)
+            .ok_or_else(|| wasmi::Error::new(format!("no function {function}")))?;
+        let params: Vec<_> = args.iter().map(|a| Val::I32(a.len() as i32)).collect();
+        let mut results = [Val::I32(0)];
+        func.call(&mut self.store, &params, &mut results)?;
+        let code = results[0].i32().unwrap();
+        Ok((code, std::mem::take(&mut self.store.data_mut().result)))
+    }
+
+    pub fn exports(&self, function: &str) -> bool {
+        self.instance.get_func(&self.store, function).is_some()
+    }
+}
+
+/// Call `function` without arguments, on a new instance.
+pub fn call(binary: &[u8], function: &str) -> Result<(i32, Vec<u8>), wasmi::Error> {
+    Plugin::new(binary)?.call(function, &[])
+}
+
+/// Remove the `name` custom section of `binary`, like `strip` or `wasm-opt` do.
+pub fn strip_names(binary: &[u8]) -> Vec<u8> {
+    let mut stripped = binary[..8].to_vec();
+    let mut offset = 8;
+    while offset < binary.len() {
+        let start = offset;
+        let id = binary[offset];
+        offset += 1;
+        let (mut size, mut shift) = (0, 0);
+        loop {
+            let byte = binary[offset];
+            offset += 1;
+            size |= ((byte & 0x7f) as usize) << shift;
+            shift += 7;
+            if byte & 0x80 == 0 {
+                break;
+            }
+        }
+        let contents = &binary[offset..offset + size];
+        offset += size;
+        // A custom section starts with its name, prefixed by its length.
+        if id != 0 || !contents.starts_with(b"\x04name") {
+            stripped.extend_from_slice(&binary[start..offset]);
+        }
+    }
+    stripped
+}
@@ -0,0 +1,43 @@
+mod common;
+
+use common::{build, call, Plugin};
+use wasi_stub::{inspect, stub_wasi_functions, ShouldStub, StubValues};
+
+/// Stub `binary` with the default options, and check that only the protocol
+/// functions remain imported.
+fn stub(binary: &[u8]) -> Vec<u8> {
+    let module =
+        stub_wasi_functions(binary, ShouldStub::default(), StubValues::default(), 76).unwrap();
+    assert!(wasmparser::Parser::is_core_wasm(&module));
+    let info = inspect(&module).unwrap();
+    assert!(info
+        .imports
+        .iter()
+        .all(|import| import.module == "typst_env"));
+    module
+}
+
+#[test]
+fn wasip2_module() {
+    let module = stub(&build("hello_rust_wasip2"));
+
+    let mut plugin = Plugin::new(&module).unwrap();
+    assert_eq!(
+        plugin.call("hello", &[]).unwrap(),
+        (0, b"Hello from wasm!!!".to_vec())
+    );
+    assert_eq!(
+        plugin.call("concatenate", &[b"hello", b"world"]).unwrap(),
+        (0, b"hello*world".to_vec())
+    );
+}
+
+#[test]
+fn wasip2_component() {
+    let component = build("component");
+    assert!(wasmparser::Parser::is_component(&component));
+    let module = stub(&component);
+
+    // The message is written to the stubbed `wasi:io/streams` interface.
+    assert_eq!(call(&module, "hello").unwrap(), (0, Vec::new()));
+}
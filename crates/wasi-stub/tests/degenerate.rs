@@ -0,0 +1,81 @@
+//! Modules with few or no items, built from every combination of a small set of
+//! fields: every pass must handle them without panicking.
+
+use wasi_stub::{
+    command_to_reactor, garbage_collect, pre_initialize, stub_wasi_functions, validate,
+    FunctionsToStub, ShouldStub, StubValues, WasmFeatures,
+};
+
+const IMPORT_FUNC: usize = 1 << 0;
+const IMPORT_GLOBAL: usize = 1 << 1;
+const IMPORT_MEMORY: usize = 1 << 2;
+const IMPORT_TABLE: usize = 1 << 3;
+const GLOBAL: usize = 1 << 4;
+const TABLE: usize = 1 << 5;
+const MEMORY: usize = 1 << 6;
+const DATA: usize = 1 << 7;
+const EXPORT: usize = 1 << 8;
+
+/// Fields added by each flag, in the order they must appear in the module.
+const FIELDS: &[(usize, &str)] = &[
+    (
+        IMPORT_FUNC,
+        r#"(import "wasi_snapshot_preview1" "proc_exit" (func (param i32)))"#,
+    ),
+    (
+        IMPORT_GLOBAL,
+        r#"(import "env" "__memory_base" (global i32))"#,
+    ),
+    (IMPORT_MEMORY, r#"(import "env" "memory" (memory 1))"#),
+    (IMPORT_TABLE, r#"(import "env" "table" (table 1 funcref))"#),
+    (GLOBAL, "(global (mut i32) (i32.const 0))"),
+    (TABLE, "(table 2 funcref)"),
+    (MEMORY, "(memory 1)"),
+    (DATA, r#"(data (i32.const 0) "data")"#),
+    (EXPORT, r#"(export "exit" (func 0))"#),
+];
+
+fn module(flags: usize) -> Option<Vec<u8>> {
+    if flags & DATA != 0 && flags & (IMPORT_MEMORY | MEMORY) == 0 {
+        return None;
+    }
+    if flags & EXPORT != 0 && flags & IMPORT_FUNC == 0 {
+        return None;
+    }
+    let mut source = String::from("(module\n");
+    for (flag, field) in FIELDS {
+        if flags & flag != 0 {
+            source.push_str(field);
+            source.push('\n');
+        }
+    }
+    source.push(')');
+    let buffer = wast::parser::ParseBuffer::new(&source).unwrap();
+    let mut wat: wast::Wat = wast::parser::parse(&buffer).unwrap();
+    Some(wat.encode().unwrap())
+}
+
+#[test]
+fn degenerate_modules() {
+    for flags in 0..1 << FIELDS.len() {
+        let Some(binary) = module(flags) else {
+            continue;
+        };
+        let mut should_stub = ShouldStub::default();
+        should_stub
+            .modules
+            .insert("env".to_owned(), FunctionsToStub::All);
+        let stubbed = stub_wasi_functions(&binary, should_stub, StubValues::default(), 76)
+            .unwrap_or_else(|err| panic!("module {flags:#b}: {err}"));
+        validate(&stubbed, WasmFeatures::default())
+            .unwrap_or_else(|err| panic!("module {flags:#b}: {err}"));
+
+        for output in [command_to_reactor(&stubbed), garbage_collect(&stubbed)] {
+            let output = output.unwrap_or_else(|err| panic!("module {flags:#b}: {err}"));
+            validate(&output, WasmFeatures::default())
+                .unwrap_or_else(|err| panic!("module {flags:#b}: {err}"));
+        }
+        // There is no init function: this must fail, but not panic.
+        assert!(pre_initialize(&stubbed).is_err());
+    }
+}
@@ -0,0 +1,60 @@
+mod common;
+
+use common::{fixture, wat};
+use wasi_stub::{diff, inspect, stub_wasi_functions, Change, ChangeKind, ShouldStub, StubValues};
+
+#[test]
+fn imports_and_exports() {
+    let old = wat(r#"(module
+        (import "env" "a" (func (param i32)))
+        (import "env" "b" (func))
+        (func (export "f") (result i32) (i32.const 0))
+        (func (export "g") (result i32) (i32.const 0)))"#);
+    let new = wat(r#"(module
+        (import "env" "a" (func (param i64)))
+        (import "env" "c" (func))
+        (func (export "f") (param i32) (result i32) (i32.const 0))
+        (func (export "h") (result i32) (i32.const 0)))"#);
+    let changes = diff(&inspect(&old).unwrap(), &inspect(&new).unwrap());
+    let kinds: Vec<_> = changes
+        .iter()
+        .filter(|change| !matches!(change, Change::SectionSize { .. }))
+        .map(|change| change.kind())
+        .collect();
+    assert_eq!(
+        kinds,
+        [
+            ChangeKind::Signature,
+            ChangeKind::RemovedImport,
+            ChangeKind::AddedImport,
+            ChangeKind::Signature,
+            ChangeKind::RemovedExport,
+            ChangeKind::AddedExport,
+        ]
+    );
+    assert_eq!(
+        changes[0].to_string(),
+        "~ import env::a: func(i32) => func(i64)"
+    );
+    assert_eq!(changes[4].to_string(), "- export g: func() -> i32");
+}
+
+#[test]
+fn stubbed() {
+    let old = fixture("inspect");
+    let new = stub_wasi_functions(&old, ShouldStub::default(), StubValues::default(), 76).unwrap();
+    let changes = diff(&inspect(&old).unwrap(), &inspect(&new).unwrap());
+    assert!(changes.iter().any(|change| matches!(
+        change,
+        Change::RemovedImport(import) if import.name == "fd_write"
+    )));
+    assert!(changes
+        .iter()
+        .any(|change| matches!(change, Change::SectionSize { name, .. } if name == "code")));
+    // Unchanged items are not reported.
+    assert!(!changes
+        .iter()
+        .any(|change| matches!(change, Change::AddedExport(_) | Change::RemovedExport(_))));
+
+    assert!(diff(&inspect(&new).unwrap(), &inspect(&new).unwrap()).is_empty());
+}
@@ -0,0 +1,57 @@
+mod common;
+
+use common::{fixture, wat};
+use wasi_stub::{stub_wasi_functions, Error, FunctionStub, ShouldStub, StubValues};
+
+fn stub(binary: &[u8], stub_values: StubValues) -> Result<Vec<u8>, Error> {
+    stub_wasi_functions(binary, ShouldStub::default(), stub_values, 76)
+}
+
+#[test]
+fn parse_error() {
+    let Err(Error::Parse { offset, .. }) = stub(b"\0asm garbage", StubValues::default()) else {
+        panic!("the input should not parse");
+    };
+    assert_eq!(offset, Some(4));
+
+    // The last section is cut: the error is at its start, in the input.
+    let mut binary = fixture("command");
+    let last_section = wasmparser::Parser::new(0)
+        .parse_all(&binary)
+        .filter_map(|payload| Some(payload.ok()?.as_section()?.1.start))
+        .last()
+        .unwrap();
+    binary.truncate(binary.len() - 3);
+    let Err(Error::Parse { offset, message }) = stub(&binary, StubValues::default()) else {
+        panic!("the input should not parse");
+    };
+    assert_eq!(message, "unexpected end-of-file");
+    assert_eq!(offset, Some(last_section));
+}
+
+#[test]
+fn invalid_stub_body() {
+    let mut stub_values = StubValues::default();
+    stub_values.functions.insert(
+        ("wasi_snapshot_preview1".to_owned(), "proc_exit".to_owned()),
+        FunctionStub::Body("i32.const 0 not_an_instruction".to_owned()),
+    );
+    let Err(Error::InvalidStub { import, offset, .. }) = stub(&fixture("command"), stub_values)
+    else {
+        panic!("the stub should be invalid");
+    };
+    assert_eq!(import, "wasi_snapshot_preview1::proc_exit");
+    assert_eq!(offset, Some(12));
+}
+
+#[test]
+fn unsupported_module() {
+    let binary = wat(r#"(module
+        (import "env" "stubbed" (memory 1))
+        (import "typst_env" "kept" (memory 1))
+    )"#);
+    let mut should_stub = ShouldStub::default();
+    should_stub.add_function("env", "stubbed");
+    let err = stub_wasi_functions(&binary, should_stub, StubValues::default(), 76).unwrap_err();
+    assert!(matches!(err, Error::UnsupportedModule(_)), "{err}");
+}
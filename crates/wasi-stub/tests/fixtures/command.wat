@@ -0,0 +1,27 @@
+;; A WASI command, as produced by `clang` without `-mexec-model=reactor`: `_start`
+;; runs the constructors, then `main`.
+(module
+  (import "wasi_snapshot_preview1" "proc_exit" (func $proc_exit (param i32)))
+  (import "typst_env" "wasm_minimal_protocol_send_result_to_host" (func $send_result_to_host (param i32 i32)))
+  (import "typst_env" "wasm_minimal_protocol_write_args_to_buffer" (func $write_args_to_buffer (param i32)))
+  (memory (export "memory") 1)
+  (global $constructed (mut i32) (i32.const 0))
+
+  (func $__wasm_call_ctors
+    (global.set $constructed (i32.add (global.get $constructed) (i32.const 1))))
+  (func $main (result i32)
+    (i32.const 0))
+  (func $_start (export "_start")
+    (call $__wasm_call_ctors)
+    (call $proc_exit (call $main)))
+
+  ;; Returns the number of times the constructors ran.
+  (func (export "constructed") (result i32)
+    (i32.store8 (i32.const 0) (i32.add (global.get $constructed) (i32.const 48)))
+    (call $send_result_to_host (i32.const 0) (i32.const 1))
+    (i32.const 0))
+  (func (export "echo") (param i32) (result i32)
+    (call $write_args_to_buffer (i32.const 0))
+    (call $send_result_to_host (i32.const 0) (local.get 0))
+    (i32.const 0))
+)
@@ -0,0 +1,47 @@
+;; The imports of `env` that emscripten generates for exceptions and system calls.
+(module
+  (import "env" "invoke_iii" (func $invoke_iii (param i32 i32 i32) (result i32)))
+  (import "env" "invoke_vii" (func $invoke_vii (param i32 i32 i32)))
+  (import "env" "__syscall_openat" (func $openat (param i32 i32 i32 i32) (result i32)))
+  (import "env" "__syscall_getcwd" (func $getcwd (param i32 i32) (result i32)))
+  (import "env" "emscripten_resize_heap" (func $resize_heap (param i32) (result i32)))
+  (import "typst_env" "wasm_minimal_protocol_send_result_to_host" (func $send_result_to_host (param i32 i32)))
+  (memory (export "memory") 1 4)
+  (table 2 funcref)
+  (elem (i32.const 0) $sub $store)
+
+  (func $sub (param i32 i32) (result i32)
+    (i32.sub (local.get 0) (local.get 1)))
+
+  (func $store (param i32 i32)
+    (i32.store8 (local.get 0) (local.get 1)))
+
+  ;; Calls the functions of the table through `invoke_*`: returns `10 - 3`, then the
+  ;; byte stored by `$store`.
+  (func (export "invoke") (result i32)
+    (i32.store8 (i32.const 0) (call $invoke_iii (i32.const 0) (i32.const 10) (i32.const 3)))
+    (call $invoke_vii (i32.const 1) (i32.const 1) (i32.const 42))
+    (call $send_result_to_host (i32.const 0) (i32.const 2))
+    (i32.const 0))
+
+  ;; Returns the results of the system calls, as little-endian i32.
+  (func (export "syscalls") (result i32)
+    (i32.store (i32.const 0) (call $openat (i32.const 0) (i32.const 0) (i32.const 0) (i32.const 0)))
+    (i32.store (i32.const 4) (call $getcwd (i32.const 0) (i32.const 0)))
+    (call $send_result_to_host (i32.const 0) (i32.const 8))
+    (i32.const 0))
+
+  ;; Resizes the heap to 1, 2 and 5 pages (more than the maximum), then 3 pages
+  ;; plus one byte: returns the result and the size in pages after each call.
+  (func (export "resize_heap") (result i32)
+    (i32.store8 (i32.const 0) (call $resize_heap (i32.const 65536)))
+    (i32.store8 (i32.const 1) (memory.size))
+    (i32.store8 (i32.const 2) (call $resize_heap (i32.const 131072)))
+    (i32.store8 (i32.const 3) (memory.size))
+    (i32.store8 (i32.const 4) (call $resize_heap (i32.const 327680)))
+    (i32.store8 (i32.const 5) (memory.size))
+    (i32.store8 (i32.const 6) (call $resize_heap (i32.const 196609)))
+    (i32.store8 (i32.const 7) (memory.size))
+    (call $send_result_to_host (i32.const 0) (i32.const 8))
+    (i32.const 0))
+)
@@ -0,0 +1,33 @@
+;; After stubbing, the filesystem code behind `path_open` can no longer do anything,
+;; but nothing in the binary says so: only reachability can tell it is never used.
+(module
+  (import "wasi_snapshot_preview1" "fd_write" (func $fd_write (param i32 i32 i32 i32) (result i32)))
+  (import "wasi_snapshot_preview1" "path_open" (func $path_open (param i32 i32 i32 i32 i32 i64 i64 i32 i32) (result i32)))
+  (import "typst_env" "wasm_minimal_protocol_send_result_to_host" (func $send_result_to_host (param i32 i32)))
+  (import "typst_env" "wasm_minimal_protocol_write_args_to_buffer" (func $write_args_to_buffer (param i32)))
+  (memory (export "memory") 1)
+  (table 1 funcref)
+  (global $open_files (mut i32) (i32.const 0))
+  (global $greeting i32 (i32.const 16))
+  (data (i32.const 16) "hello")
+  (data $path "/etc/fonts")
+  (elem (i32.const 0) $greet)
+  (elem declare func $open)
+
+  (func $greet (result i32)
+    (call $send_result_to_host (global.get $greeting) (i32.const 5))
+    (i32.const 0))
+  (func $print (param i32 i32)
+    (drop (call $fd_write (i32.const 1) (local.get 0) (local.get 1) (i32.const 0))))
+  (func $open (result i32)
+    (memory.init $path (i32.const 0) (i32.const 0) (i32.const 10))
+    (global.set $open_files (i32.add (global.get $open_files) (i32.const 1)))
+    (call $path_open (i32.const 3) (i32.const 0) (i32.const 0) (i32.const 10) (i32.const 0) (i64.const 0) (i64.const 0) (i32.const 0) (i32.const 0)))
+  (func $load_fonts (result i32)
+    (drop (ref.func $open))
+    (call $open))
+
+  (func (export "hello") (result i32)
+    (call $print (i32.const 16) (i32.const 5))
+    (call_indirect (result i32) (i32.const 0)))
+)
@@ -0,0 +1,28 @@
+;; A plugin importing a memory, globals and a table, to be stubbed.
+(module
+  (import "env" "memory" (memory 1))
+  (import "env" "table" (table 1 funcref))
+  (import "env" "offset" (global $offset i32))
+  (import "env" "ratio" (global $ratio f32))
+  (import "env" "scale" (global $scale f64))
+  (import "typst_env" "wasm_minimal_protocol_send_result_to_host" (func $send_result_to_host (param i32 i32)))
+  (export "memory" (memory 0))
+
+  ;; Returns the values of the globals, each one as a byte.
+  (func (export "globals") (result i32)
+    (i32.store8 (i32.const 0) (global.get $offset))
+    (i32.store8 (i32.const 1) (i32.trunc_f32_s (global.get $ratio)))
+    (i32.store8 (i32.const 2) (i32.trunc_f64_s (f64.mul (global.get $scale) (f64.const 10))))
+    (call $send_result_to_host (i32.const 0) (i32.const 3))
+    (i32.const 0))
+
+  ;; Returns the sizes of the memory and of the table, then whether they can grow by
+  ;; one, each one as a byte.
+  (func (export "sizes") (result i32)
+    (i32.store8 (i32.const 0) (memory.size))
+    (i32.store8 (i32.const 1) (table.size))
+    (i32.store8 (i32.const 2) (i32.ne (memory.grow (i32.const 1)) (i32.const -1)))
+    (i32.store8 (i32.const 3) (i32.ne (table.grow (ref.null func) (i32.const 1)) (i32.const -1)))
+    (call $send_result_to_host (i32.const 0) (i32.const 4))
+    (i32.const 0))
+)
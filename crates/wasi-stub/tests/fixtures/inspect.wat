@@ -0,0 +1,24 @@
+;; A plugin with a bit of everything that `inspect` reports.
+(module
+  (@producers
+    (language "Rust" "")
+    (processed-by "rustc" "1.80.0 (051478957 2024-07-21)"))
+  (@custom "target_features" "+mutable-globals")
+  (import "typst_env" "wasm_minimal_protocol_send_result_to_host" (func $send_result_to_host (param i32 i32)))
+  (import "typst_env" "wasm_minimal_protocol_write_args_to_buffer" (func $write_args_to_buffer (param i32)))
+  (import "wasi_snapshot_preview1" "fd_write" (func (param i32 i32 i32 i32) (result i32)))
+  (import "env" "__stack_pointer" (global (mut i32)))
+  (memory (export "memory") 2 16)
+  (data (i32.const 0) "hello")
+  (data (i32.const 16) "world!")
+
+  (func (export "hello") (result i32)
+    (call $send_result_to_host (i32.const 0) (i32.const 5))
+    (i32.const 0))
+  (func (export "concat") (param i32 i32) (result i32)
+    (call $write_args_to_buffer (i32.const 0))
+    (call $send_result_to_host (i32.const 0) (i32.add (local.get 0) (local.get 1)))
+    (i32.const 0))
+  (func (export "helper") (param i64) (result i64)
+    (local.get 0))
+)
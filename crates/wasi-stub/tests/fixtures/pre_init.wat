@@ -0,0 +1,42 @@
+;; A plugin that builds a lookup table in `wasm_minimal_protocol_init`, instead of
+;; on each call.
+(module
+  (import "typst_env" "wasm_minimal_protocol_send_result_to_host" (func $send_result_to_host (param i32 i32)))
+  (import "typst_env" "wasm_minimal_protocol_write_args_to_buffer" (func $write_args_to_buffer (param i32)))
+  (memory (export "memory") 1)
+  (global $started (mut i32) (i32.const 0))
+  (global $initialized (mut i32) (i32.const 0))
+  (global $ratio (mut f64) (f64.const 0))
+  (data (i32.const 0) "squares:")
+
+  (func $start
+    (global.set $started (i32.add (global.get $started) (i32.const 1))))
+  (start $start)
+
+  ;; Store the squares of 1..=16 after the "squares:" prefix, in a new page.
+  (func (export "wasm_minimal_protocol_init") (result i32)
+    (local $i i32)
+    (drop (memory.grow (i32.const 1)))
+    (loop $fill
+      (local.set $i (i32.add (local.get $i) (i32.const 1)))
+      (i32.store8
+        (i32.add (i32.const 65535) (local.get $i))
+        (i32.mul (local.get $i) (local.get $i)))
+      (br_if $fill (i32.lt_u (local.get $i) (i32.const 16))))
+    (memory.copy (i32.const 65528) (i32.const 0) (i32.const 8))
+    (global.set $initialized (i32.const 1))
+    (global.set $ratio (f64.const 0.5))
+    (i32.const 0))
+
+  (func (export "squares") (result i32)
+    (call $send_result_to_host (i32.const 65528) (i32.const 24))
+    (i32.const 0))
+  ;; Sends the number of times the start function ran, and whether the module is
+  ;; initialized.
+  (func (export "state") (result i32)
+    (i32.store8 (i32.const 8) (i32.add (global.get $started) (i32.const 48)))
+    (i32.store8 (i32.const 9) (i32.add (global.get $initialized) (i32.const 48)))
+    (i32.store8 (i32.const 10) (i32.add (i32.trunc_f64_u (f64.mul (global.get $ratio) (f64.const 2))) (i32.const 48)))
+    (call $send_result_to_host (i32.const 8) (i32.const 3))
+    (i32.const 0))
+)
@@ -0,0 +1,10 @@
+;; A plugin whose initialisation fails.
+(module
+  (import "typst_env" "wasm_minimal_protocol_send_result_to_host" (func $send_result_to_host (param i32 i32)))
+  (memory (export "memory") 1)
+  (data (i32.const 0) "no fonts found")
+
+  (func (export "wasm_minimal_protocol_init") (result i32)
+    (call $send_result_to_host (i32.const 0) (i32.const 14))
+    (i32.const 1))
+)
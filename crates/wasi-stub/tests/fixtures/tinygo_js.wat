@@ -0,0 +1,25 @@
+;; Reproduces how the runtime of TinyGo, built with `-target=wasm`, measures time:
+;; `runtime.ticks` and `runtime.sleepTicks` come from `wasm_exec.js`, in milliseconds.
+(module
+  (import "gojs" "runtime.ticks" (func $ticks (result f64)))
+  (import "gojs" "runtime.sleepTicks" (func $sleep_ticks (param f64)))
+  (import "gojs" "syscall/js.finalizeRef" (func $finalize_ref (param i32)))
+  (import "typst_env" "wasm_minimal_protocol_send_result_to_host" (func $send_result_to_host (param i32 i32)))
+
+  (memory (export "memory") 1)
+  (data (i32.const 0) "slept")
+
+  (func (export "sleep") (result i32)
+    (local $deadline f64)
+    (local $now f64)
+    (local.set $deadline (f64.add (call $ticks) (f64.const 10)))
+    (block $expired
+      (loop $wait
+        (local.set $now (call $ticks))
+        (br_if $expired (f64.ge (local.get $now) (local.get $deadline)))
+        (call $sleep_ticks (f64.sub (local.get $deadline) (local.get $now)))
+        (br $wait)))
+    (call $finalize_ref (i32.const 0))
+    (call $send_result_to_host (i32.const 0) (i32.const 5))
+    (i32.const 0))
+)
@@ -0,0 +1,76 @@
+;; Reproduces how the runtime of TinyGo, built with `GOOS=wasip1 GOARCH=wasm`, uses
+;; its WASI imports: `ticks` and `sleepTicks` are the ones of `runtime_wasip1.go`,
+;; and `print` retries partial writes like `os.File.Write`.
+(module
+  (import "wasi_snapshot_preview1" "clock_time_get" (func $clock_time_get (param i32 i64 i32) (result i32)))
+  (import "wasi_snapshot_preview1" "poll_oneoff" (func $poll_oneoff (param i32 i32 i32 i32) (result i32)))
+  (import "wasi_snapshot_preview1" "fd_write" (func $fd_write (param i32 i32 i32 i32) (result i32)))
+  (import "wasi_snapshot_preview1" "random_get" (func $random_get (param i32 i32) (result i32)))
+  (import "wasi_snapshot_preview1" "args_sizes_get" (func $args_sizes_get (param i32 i32) (result i32)))
+  (import "wasi_snapshot_preview1" "proc_exit" (func $proc_exit (param i32)))
+  (import "typst_env" "wasm_minimal_protocol_send_result_to_host" (func $send_result_to_host (param i32 i32)))
+  (import "typst_env" "wasm_minimal_protocol_write_args_to_buffer" (func $write_args_to_buffer (param i32)))
+
+  ;; 0: time, 16: subscription, 64: event, 96: nevents, 100: nwritten,
+  ;; 104: argc, 108: argv_buf_size, 128: iovec, 256: message, 512: random bytes
+  (memory (export "memory") 1)
+  (data (i32.const 256) "hello\n")
+
+  (func $ticks (result i64)
+    (drop (call $clock_time_get (i32.const 1) (i64.const 1) (i32.const 0)))
+    (i64.load (i32.const 0)))
+
+  (func $sleep_ticks (param $duration i64)
+    (i32.store8 offset=8 (i32.const 16) (i32.const 0))
+    (i64.store offset=24 (i32.const 16) (local.get $duration))
+    (drop (call $poll_oneoff (i32.const 16) (i32.const 64) (i32.const 1) (i32.const 96))))
+
+  ;; Like `time.Sleep(10 * time.Millisecond)`: the scheduler sleeps until the
+  ;; timer expires.
+  (func (export "sleep") (result i32)
+    (local $deadline i64)
+    (local $now i64)
+    (local.set $deadline (i64.add (call $ticks) (i64.const 10000000)))
+    (block $expired
+      (loop $wait
+        (local.set $now (call $ticks))
+        (br_if $expired (i64.ge_u (local.get $now) (local.get $deadline)))
+        (call $sleep_ticks (i64.sub (local.get $deadline) (local.get $now)))
+        (br $wait)))
+    (call $send_result_to_host (i32.const 256) (i32.const 5))
+    (i32.const 0))
+
+  (func (export "print") (result i32)
+    (i32.store (i32.const 128) (i32.const 256))
+    (i32.store (i32.const 132) (i32.const 6))
+    (block $written
+      (loop $write
+        (if (call $fd_write (i32.const 1) (i32.const 128) (i32.const 1) (i32.const 100))
+          (then
+            (call $send_result_to_host (i32.const 256) (i32.const 0))
+            (return (i32.const 1))))
+        (i32.store (i32.const 128) (i32.add (i32.load (i32.const 128)) (i32.load (i32.const 100))))
+        (i32.store (i32.const 132) (i32.sub (i32.load (i32.const 132)) (i32.load (i32.const 100))))
+        (br_if $write (i32.load (i32.const 132)))))
+    (call $send_result_to_host (i32.const 256) (i32.const 6))
+    (i32.const 0))
+
+  (func (export "random") (result i32)
+    (i64.store (i32.const 512) (i64.const -1))
+    (if (call $random_get (i32.const 512) (i32.const 8))
+      (then (unreachable)))
+    (call $send_result_to_host (i32.const 512) (i32.const 8))
+    (i32.const 0))
+
+  (func (export "args") (result i32)
+    (i32.store (i32.const 104) (i32.const -1))
+    (i32.store (i32.const 108) (i32.const -1))
+    (if (call $args_sizes_get (i32.const 104) (i32.const 108))
+      (then (unreachable)))
+    (call $send_result_to_host (i32.const 104) (i32.const 8))
+    (i32.const 0))
+
+  (func (export "exit") (result i32)
+    (call $proc_exit (i32.const 2))
+    (i32.const 0))
+)
@@ -0,0 +1,52 @@
+mod common;
+
+use common::{call, fixture};
+use wasi_stub::{garbage_collect, stub_wasi_functions, ShouldStub, StubValues};
+
+/// Number of functions, globals and data segments in `binary`.
+fn count_items(binary: &[u8]) -> (u32, u32, u32) {
+    let mut counts = (0, 0, 0);
+    for payload in wasmparser::Parser::new(0).parse_all(binary) {
+        match payload.unwrap() {
+            wasmparser::Payload::ImportSection(imports) => {
+                for import in imports {
+                    match import.unwrap().ty {
+                        wasmparser::TypeRef::Func(_) => counts.0 += 1,
+                        wasmparser::TypeRef::Global(_) => counts.1 += 1,
+                        _ => {}
+                    }
+                }
+            }
+            wasmparser::Payload::FunctionSection(functions) => counts.0 += functions.count(),
+            wasmparser::Payload::GlobalSection(globals) => counts.1 += globals.count(),
+            wasmparser::Payload::DataSection(data) => counts.2 += data.count(),
+            _ => {}
+        }
+    }
+    counts
+}
+
+fn stub(binary: &[u8]) -> Vec<u8> {
+    stub_wasi_functions(binary, ShouldStub::default(), StubValues::default(), 76).unwrap()
+}
+
+#[test]
+fn gc() {
+    let binary = stub(&fixture("gc"));
+    assert_eq!(count_items(&binary), (9, 2, 2));
+
+    let collected = garbage_collect(&binary).unwrap();
+    // `path_open`, `write_args_to_buffer`, `$open`, `$load_fonts`, `$open_files` and
+    // `$path` are removed.
+    assert_eq!(count_items(&collected), (5, 1, 1));
+    assert!(collected.len() < binary.len());
+    assert_eq!(call(&collected, "hello").unwrap(), (0, b"hello".to_vec()));
+}
+
+#[test]
+fn gc_nothing_to_remove() {
+    let binary = stub(&fixture("command"));
+    let collected = garbage_collect(&binary).unwrap();
+    assert_eq!(count_items(&collected), count_items(&binary));
+    assert_eq!(call(&collected, "constructed").unwrap(), (0, b"0".to_vec()));
+}
@@ -0,0 +1,79 @@
+mod common;
+
+use common::{call, fixture};
+use wasi_stub::{
+    stub_wasi_functions, Error, FunctionsToStub, GlobalValue, Limits, ShouldStub, StubValues,
+};
+
+fn stub(stub_values: StubValues) -> Result<Vec<u8>, Error> {
+    let mut should_stub = ShouldStub::default();
+    should_stub
+        .modules
+        .insert("env".to_owned(), FunctionsToStub::All);
+    stub_wasi_functions(&fixture("imports"), should_stub, stub_values, 76)
+}
+
+fn key(name: &str) -> (String, String) {
+    ("env".to_owned(), name.to_owned())
+}
+
+#[test]
+fn defaults() {
+    let binary = stub(StubValues::default()).unwrap();
+    assert_eq!(call(&binary, "globals").unwrap(), (0, vec![0, 0, 0]));
+    // The memory and the table keep the limits of their import.
+    assert_eq!(call(&binary, "sizes").unwrap(), (0, vec![1, 1, 1, 1]));
+}
+
+#[test]
+fn globals() {
+    let mut stub_values = StubValues::default();
+    stub_values
+        .globals
+        .insert(key("offset"), GlobalValue::Int(7));
+    stub_values
+        .globals
+        .insert(key("ratio"), GlobalValue::Float(3.5));
+    stub_values
+        .globals
+        .insert(key("scale"), GlobalValue::Float(0.25));
+    let binary = stub(stub_values).unwrap();
+    assert_eq!(call(&binary, "globals").unwrap(), (0, vec![7, 3, 2]));
+
+    // Integers are converted for float globals.
+    let mut stub_values = StubValues::default();
+    stub_values
+        .globals
+        .insert(key("ratio"), GlobalValue::Int(4));
+    let binary = stub(stub_values).unwrap();
+    assert_eq!(call(&binary, "globals").unwrap(), (0, vec![0, 4, 0]));
+
+    let mut stub_values = StubValues::default();
+    stub_values
+        .globals
+        .insert(key("offset"), GlobalValue::Float(1.5));
+    let Err(Error::InvalidArgument(message)) = stub(stub_values) else {
+        panic!("an i32 global cannot be a float");
+    };
+    assert_eq!(
+        message,
+        "the global env::offset is not a float, it cannot be set to 1.5"
+    );
+}
+
+#[test]
+fn limits() {
+    let mut stub_values = StubValues::default();
+    stub_values.memories.insert(
+        key("memory"),
+        Limits {
+            min: 2,
+            max: Some(2),
+        },
+    );
+    stub_values
+        .tables
+        .insert(key("table"), Limits { min: 3, max: None });
+    let binary = stub(stub_values).unwrap();
+    assert_eq!(call(&binary, "sizes").unwrap(), (0, vec![2, 3, 0, 1]));
+}
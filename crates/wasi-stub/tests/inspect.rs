@@ -0,0 +1,65 @@
+mod common;
+
+use common::{build, fixture};
+use wasi_stub::{inspect, ItemType};
+
+#[test]
+fn summary() {
+    let info = inspect(&fixture("inspect")).unwrap();
+
+    let protocol: Vec<_> = info
+        .exports
+        .iter()
+        .filter(|export| export.ty.is_protocol_function())
+        .map(|export| export.name.as_str())
+        .collect();
+    assert_eq!(protocol, ["hello", "concat"]);
+    assert_eq!(info.exports[3].ty.to_string(), "func(i64) -> i64");
+
+    assert_eq!(info.imports.len(), 4);
+    assert_eq!(info.imports[3].ty.to_string(), "global mut i32");
+
+    assert_eq!(
+        ItemType::Memory(info.memories[0]).to_string(),
+        "memory 2..16 pages"
+    );
+    assert_eq!((info.data_size, info.data_segments), (11, 2));
+
+    let custom: Vec<_> = info
+        .custom_sections()
+        .map(|section| section.name.as_str())
+        .collect();
+    // The name section comes from the `$ids` of the fixture.
+    assert_eq!(
+        custom,
+        [
+            "custom \"name\"",
+            "custom \"producers\"",
+            "custom \"target_features\""
+        ]
+    );
+    assert_eq!(
+        info.producers[0],
+        (
+            "language".to_owned(),
+            vec![("Rust".to_owned(), String::new())]
+        )
+    );
+    assert_eq!(info.producers[1].1[0].0, "rustc");
+
+    let summary = info.to_string();
+    assert!(summary.contains("  hello: func() -> i32 (protocol function)\n"));
+    assert!(summary
+        .contains("  typst_env:\n    wasm_minimal_protocol_send_result_to_host: func(i32, i32)\n"));
+}
+
+#[test]
+fn component() {
+    // The main module of the component is inspected.
+    let info = inspect(&build("component")).unwrap();
+    assert!(info
+        .imports
+        .iter()
+        .any(|import| import.module.starts_with("wasi:")));
+    assert!(info.exports.iter().any(|export| export.name == "hello"));
+}
@@ -0,0 +1,66 @@
+mod common;
+
+use common::{fixture, wat, Plugin};
+use wasi_stub::{pre_initialize, pre_initialize_with_fuel, Error};
+
+#[test]
+fn pre_init() {
+    let binary = pre_initialize(&fixture("pre_init")).unwrap();
+
+    let mut plugin = Plugin::new(&binary).unwrap();
+    assert!(!plugin.exports("wasm_minimal_protocol_init"));
+    let squares: Vec<u8> = (1..=16u8).map(|i| i.wrapping_mul(i)).collect();
+    assert_eq!(
+        plugin.call("squares", &[]).unwrap(),
+        (0, [b"squares:".as_slice(), &squares].concat())
+    );
+    // The start function ran at build time only.
+    assert_eq!(plugin.call("state", &[]).unwrap(), (0, b"111".to_vec()));
+}
+
+#[test]
+fn pre_init_error() {
+    let Err(Error::PreInit(message)) = pre_initialize(&fixture("pre_init_error")) else {
+        panic!("the init function should fail");
+    };
+    assert!(message.contains("no fonts found"));
+}
+
+#[test]
+fn pre_init_failures() {
+    let init = |body: &str| {
+        wat(&format!(
+            r#"(module
+                (memory (export "memory") 1)
+                (func (export "wasm_minimal_protocol_init") (result i32) {body}))"#
+        ))
+    };
+    // Any code other than 0 is a failure.
+    let Err(Error::PreInit(message)) = pre_initialize(&init("(i32.const -1)")) else {
+        panic!("the init function should fail");
+    };
+    assert_eq!(message, "wasm_minimal_protocol_init returned -1: ");
+
+    let looping = init("(loop $forever (br $forever)) (i32.const 0)");
+    let Err(Error::PreInit(message)) = pre_initialize_with_fuel(&looping, 100_000) else {
+        panic!("the init function should run out of fuel");
+    };
+    assert_eq!(
+        message,
+        "wasm_minimal_protocol_init failed: it ran out of fuel (the limit is 100000), it may \
+         loop forever"
+    );
+}
+
+#[test]
+fn pre_init_missing() {
+    // The module instantiates, but has no init function.
+    let binary = pre_initialize(&fixture("pre_init")).unwrap();
+    let Err(Error::PreInit(message)) = pre_initialize(&binary) else {
+        panic!("there is no init function to run");
+    };
+    assert_eq!(
+        message,
+        "the module does not export wasm_minimal_protocol_init"
+    );
+}
@@ -0,0 +1,89 @@
+mod common;
+
+use common::{call, fixture, Plugin};
+use wasi_stub::{stub_wasi_functions, Preset, ShouldStub, StubValues};
+
+fn stub(binary: &[u8], preset: Option<Preset>) -> Vec<u8> {
+    let mut should_stub = ShouldStub::default();
+    let mut stub_values = StubValues::default();
+    if let Some(preset) = preset {
+        preset.apply(&mut should_stub, &mut stub_values);
+    }
+    stub_wasi_functions(binary, should_stub, stub_values, 76).unwrap()
+}
+
+#[test]
+fn tinygo_wasip1() {
+    let binary = stub(&fixture("tinygo_wasip1"), Some(Preset::TinyGo));
+
+    assert_eq!(call(&binary, "sleep").unwrap(), (0, b"hello".to_vec()));
+    assert_eq!(call(&binary, "print").unwrap(), (0, b"hello\n".to_vec()));
+    assert_eq!(call(&binary, "random").unwrap(), (0, vec![0; 8]));
+    assert_eq!(call(&binary, "args").unwrap(), (0, vec![0; 8]));
+    assert!(call(&binary, "exit").is_err());
+}
+
+#[test]
+fn tinygo_wasip1_without_preset() {
+    let binary = stub(&fixture("tinygo_wasip1"), None);
+
+    // The clock never advances: the scheduler waits forever.
+    assert!(call(&binary, "sleep").is_err());
+    assert_eq!(call(&binary, "print").unwrap(), (1, Vec::new()));
+}
+
+/// A build of `tests/tinygo/main.go`.
+fn tinygo_build(target: &str) -> Vec<u8> {
+    let path = format!("{}/tests/tinygo/{target}.wasm", env!("CARGO_MANIFEST_DIR"));
+    std::fs::read(&path).unwrap_or_else(|err| {
+        panic!("cannot read {path}: {err}, see tests/tinygo/README.md to build it")
+    })
+}
+
+#[test]
+#[ignore = "needs tests/tinygo/wasip1.wasm, built with tinygo"]
+fn tinygo_wasip1_build() {
+    let binary = stub(&tinygo_build("wasip1"), Some(Preset::TinyGo));
+
+    let mut plugin = Plugin::new(&binary).unwrap();
+    assert_eq!(plugin.call("sleep", &[]).unwrap(), (0, b"slept".to_vec()));
+    assert_eq!(plugin.call("print", &[]).unwrap(), (0, b"printed".to_vec()));
+    assert_eq!(plugin.call("random", &[]).unwrap(), (0, vec![0; 8]));
+    assert_eq!(plugin.call("args", &[]).unwrap(), (0, b"0".to_vec()));
+}
+
+#[test]
+#[ignore = "needs tests/tinygo/js.wasm, built with tinygo"]
+fn tinygo_js_build() {
+    let binary = stub(&tinygo_build("js"), Some(Preset::TinyGo));
+
+    let mut plugin = Plugin::new(&binary).unwrap();
+    assert_eq!(plugin.call("sleep", &[]).unwrap(), (0, b"slept".to_vec()));
+    assert_eq!(plugin.call("print", &[]).unwrap(), (0, b"printed".to_vec()));
+}
+
+#[test]
+fn tinygo_js() {
+    let binary = stub(&fixture("tinygo_js"), Some(Preset::TinyGo));
+
+    assert_eq!(call(&binary, "sleep").unwrap(), (0, b"slept".to_vec()));
+}
+
+#[test]
+fn emscripten() {
+    let binary = stub(&fixture("emscripten"), Some(Preset::Emscripten));
+
+    // The arguments are forwarded in order to the function of the table.
+    assert_eq!(call(&binary, "invoke").unwrap(), (0, vec![7, 42]));
+    // System calls fail with `-ENOSYS`.
+    let enosys = (-52i32).to_le_bytes();
+    assert_eq!(
+        call(&binary, "syscalls").unwrap(),
+        (0, [enosys, enosys].concat())
+    );
+    // The heap grows up to the maximum of the memory.
+    assert_eq!(
+        call(&binary, "resize_heap").unwrap(),
+        (0, vec![1, 1, 1, 2, 0, 2, 1, 4])
+    );
+}
@@ -0,0 +1,73 @@
+mod common;
+
+use common::{fixture, strip_names, wat, Plugin};
+use wasi_stub::{command_to_reactor, inspect, stub_wasi_functions, Error, ShouldStub, StubValues};
+
+#[test]
+fn command() {
+    let binary = stub_wasi_functions(
+        &fixture("command"),
+        ShouldStub::default(),
+        StubValues::default(),
+        76,
+    )
+    .unwrap();
+    let binary = command_to_reactor(&binary).unwrap();
+
+    let mut plugin = Plugin::new(&binary).unwrap();
+    assert!(!plugin.exports("_start"));
+    assert_eq!(plugin.call("constructed", &[]).unwrap(), (0, b"1".to_vec()));
+    assert_eq!(
+        plugin.call("echo", &[b"hello"]).unwrap(),
+        (0, b"hello".to_vec())
+    );
+    // The constructors only run once.
+    assert_eq!(plugin.call("constructed", &[]).unwrap(), (0, b"1".to_vec()));
+}
+
+#[test]
+fn stripped_command() {
+    let binary = stub_wasi_functions(
+        &strip_names(&fixture("command")),
+        ShouldStub::default(),
+        StubValues::default(),
+        76,
+    )
+    .unwrap();
+    let info = inspect(&binary).unwrap();
+    assert_eq!(info.custom_sections().count(), 0);
+    let binary = command_to_reactor(&binary).unwrap();
+
+    // The constructors are found in the body of `_start`.
+    let mut plugin = Plugin::new(&binary).unwrap();
+    assert!(!plugin.exports("_start"));
+    assert_eq!(plugin.call("constructed", &[]).unwrap(), (0, b"1".to_vec()));
+    assert_eq!(plugin.call("constructed", &[]).unwrap(), (0, b"1".to_vec()));
+
+    // `main` is not a constructor.
+    let binary = wat(r#"(module
+        (memory (export "memory") 1)
+        (func (result i32) (i32.const 0))
+        (func (export "_start") (drop (call 0))))"#);
+    let Err(Error::UnsupportedModule(message)) = command_to_reactor(&binary) else {
+        panic!("there are no constructors");
+    };
+    assert_eq!(
+        message,
+        "`_start` does not call constructors that can be found"
+    );
+}
+
+#[test]
+fn command_without_pass() {
+    let binary = stub_wasi_functions(
+        &fixture("command"),
+        ShouldStub::default(),
+        StubValues::default(),
+        76,
+    )
+    .unwrap();
+
+    let mut plugin = Plugin::new(&binary).unwrap();
+    assert_eq!(plugin.call("constructed", &[]).unwrap(), (0, b"0".to_vec()));
+}
@@ -0,0 +1,45 @@
+// A plugin using the parts of the TinyGo runtime that need the preset: the scheduler
+// and its clock, the standard output, the random source and the command line.
+package main
+
+import (
+	"crypto/rand"
+	"fmt"
+	"os"
+	"time"
+	"unsafe"
+)
+
+//go:wasmimport typst_env wasm_minimal_protocol_send_result_to_host
+func sendResultToHost(ptr, size int32)
+
+func send(result []byte) int32 {
+	sendResultToHost(int32(uintptr(unsafe.Pointer(unsafe.SliceData(result)))), int32(len(result)))
+	return 0
+}
+
+func main() {}
+
+//go:export sleep
+func sleep() int32 {
+	time.Sleep(10 * time.Millisecond)
+	return send([]byte("slept"))
+}
+
+//go:export print
+func print() int32 {
+	fmt.Println("hello")
+	return send([]byte("printed"))
+}
+
+//go:export random
+func random() int32 {
+	buf := make([]byte, 8)
+	rand.Read(buf)
+	return send(buf)
+}
+
+//go:export args
+func args() int32 {
+	return send([]byte(fmt.Sprint(len(os.Args))))
+}
@@ -0,0 +1,50 @@
+mod common;
+
+use common::fixture;
+use wasi_stub::{
+    stub_wasi_functions, validate, Error, FunctionStub, ShouldStub, StubValues, WasmFeatures,
+};
+
+#[test]
+fn valid_output() {
+    let binary = stub_wasi_functions(
+        &fixture("command"),
+        ShouldStub::default(),
+        StubValues::default(),
+        76,
+    )
+    .unwrap();
+    validate(&binary, WasmFeatures::default()).unwrap();
+}
+
+#[test]
+fn invalid_stub() {
+    let mut stub_values = StubValues::default();
+    // `proc_exit` returns nothing.
+    stub_values.functions.insert(
+        ("wasi_snapshot_preview1".to_owned(), "proc_exit".to_owned()),
+        FunctionStub::Body("i64.const 0".to_owned()),
+    );
+    let binary =
+        stub_wasi_functions(&fixture("command"), ShouldStub::default(), stub_values, 76).unwrap();
+
+    let error = format!(
+        "{:?}",
+        validate(&binary, WasmFeatures::default()).unwrap_err()
+    );
+    assert!(error.contains("in function 2"));
+    assert!(error.contains("i64.const 0"));
+}
+
+#[test]
+fn disabled_feature() {
+    let binary = fixture("pre_init");
+    validate(&binary, WasmFeatures::default()).unwrap();
+    // `memory.copy` needs bulk memory operations.
+    let Err(Error::Validation(error)) =
+        validate(&binary, WasmFeatures::default() - WasmFeatures::BULK_MEMORY)
+    else {
+        panic!("the output should be invalid");
+    };
+    assert!(error.instruction.unwrap().contains("memory.copy"));
+}